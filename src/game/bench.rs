@@ -0,0 +1,85 @@
+use super::{magic_table::SliderBackend, movgen::ATTK_TBL, perft::POSITIONS, search::*, types::Square, uci::START_FEN};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_DEPTH: u8 = 8;
+
+/// The perft positions followed by a spread of middlegames and endgames.
+pub const BENCH_POSITIONS: [&str; 20] = [
+    POSITIONS[0], POSITIONS[1], POSITIONS[2], POSITIONS[3], POSITIONS[4], POSITIONS[5],
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "rnbqkb1r/pp3ppp/4pn2/2pp4/2PP4/2N2N2/PP2PPPP/R1BQKB1R w KQkq - 0 5",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 1 8",
+    "r2q1rk1/1b2bppp/p2ppn2/1p6/3BP3/1BN2Q2/PPP2PPP/2KR3R w - - 2 13",
+    "2rr2k1/1p2qppp/p1n1pn2/8/2PP4/P1Q1PN2/5PPP/2R2RK1 b - - 0 19",
+    "r1b2rk1/2q1bppp/p2p1n2/np2p3/3PP3/5N1P/PPB2PP1/RNBQR1K1 w - - 1 13",
+    "6k1/5ppp/p7/1p1r4/3R4/1P3P2/P5PP/6K1 w - - 0 30",
+    "8/5pk1/6p1/7p/2R4P/r5P1/5PK1/8 b - - 3 41",
+    "8/8/3k4/3p4/3P4/3K4/8/8 w - - 0 1",
+    "8/3k4/8/8/8/8/3K1P2/8 w - - 0 1",
+    "4k3/8/8/8/8/8/2B5/1N2K3 w - - 0 1",
+    "6k1/6p1/7p/8/8/8/1Q3PPP/6K1 w - - 0 1",
+    "r5k1/5ppp/8/8/8/8/5PPP/1R4K1 w - - 0 1",
+    "1r4k1/p4ppp/2Q5/8/8/8/PPP2qPP/4R2K w - - 0 1",
+];
+
+/// Searches every bench position to `depth` from a clean state and returns the total
+/// node count and time. The node count is a fingerprint of the search.
+pub fn bench(s: &mut Searcher, depth: u8) -> (u64, Duration) {
+    let (mut nodes, start) = (0, Instant::now());
+    for (i, fen) in BENCH_POSITIONS.iter().enumerate() {
+        s.pos.load_fen(fen);
+        s.new_game();
+        let mut info = SearchInfo::new(depth, None).quiet();
+        s.search(&mut info);
+        println!("position {:2} nodes {:10} bestmove {}", i + 1, info.nodes(), info.best_move);
+        nodes += info.nodes();
+    }
+    s.pos.load_fen(START_FEN);
+    (nodes, start.elapsed())
+}
+
+pub fn run(s: &mut Searcher, depth: u8) {
+    let (nodes, time) = bench(s, depth);
+    let nps = nodes as u128 * 1000 / time.as_millis().max(1);
+    println!("===========================");
+    println!("Total time (ms) : {}", time.as_millis());
+    println!("Nodes searched  : {}", nodes);
+    println!("Nodes/second    : {}", nps);
+}
+
+/// Times `lookups` random rook and bishop lookups with every available slider backend.
+pub fn sliders(lookups: usize) {
+    let mut rng = SmallRng::seed_from_u64(1);
+    let queries: Vec<(Square, u64)> = (0..4096)
+        .map(|_| (Square::from_index_unchecked(rng.gen_range(0..64)), rng.gen::<u64>() & rng.gen::<u64>()))
+        .collect();
+    let default = ATTK_TBL.backend();
+    for backend in SliderBackend::ALL.iter().copied() {
+        if !ATTK_TBL.set_backend(backend) {
+            println!("{:6}: not supported on this CPU", backend);
+            continue
+        }
+        let (mut acc, start) = (0u64, Instant::now());
+        for (sq, occ) in queries.iter().cycle().take(lookups) {
+            acc ^= ATTK_TBL.rook_attacks(*sq, *occ) ^ ATTK_TBL.bishop_attacks(*sq, *occ);
+        }
+        let ns = start.elapsed().as_nanos() as f64 / lookups.max(1) as f64;
+        println!("{:6}: {:.2} ns per rook+bishop lookup (checksum {:016x})", backend, ns, acc);
+    }
+    ATTK_TBL.set_backend(default);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::pos::Position;
+
+    #[test]
+    fn bench_is_deterministic() {
+        let mut s = Searcher::with_hash_mb(Position::new(), 4);
+        let (first, _) = bench(&mut s, 4);
+        let (second, _) = bench(&mut s, 4);
+        assert_eq!(first, second);
+    }
+}
@@ -1,29 +1,33 @@
-use super::defs::*;
+use super::types::{File, Rank, Square};
 
 pub type BitBoard = u64;
 
 pub trait BitHelpers {
-    fn set(&mut self, sq: u8);
-    fn clear(&mut self, sq: u8);
-    fn chk(&self, sq: u8) -> bool;
+    fn set(&mut self, sq: Square);
+    fn clear(&mut self, sq: Square);
+    fn chk(&self, sq: Square) -> bool;
     fn bits(&self) -> BitRunner;
 
+    #[allow(dead_code)] //for debugging
     fn print(&self);
 }
 
 impl BitHelpers for BitBoard {
-    fn set(&mut self, sq: u8) { *self |= 1 << sq; }
-    fn clear(&mut self, sq: u8) { *self &= !(1 << sq); }
+    #[inline(always)]
+    fn set(&mut self, sq: Square) { *self |= sq.bb(); }
+    #[inline(always)]
+    fn clear(&mut self, sq: Square) { *self &= !sq.bb(); }
 
+    #[inline(always)]
     fn bits(&self) -> BitRunner { BitRunner(*self) }
-    fn chk(&self, sq: u8) -> bool { *self & (1 << sq) != 0 }
+    #[inline(always)]
+    fn chk(&self, sq: Square) -> bool { *self & sq.bb() != 0 }
 
     fn print(&self) {
-       for rank in (0..8).rev() {
-            print!("{}  ", RANKC[rank as usize]);
-            for file in 0..8 {
-                let sq = file + rank * 8;
-                print!("{} ", match self.chk(sq) {
+       for rank in Rank::ALL.iter().rev() {
+            print!("{}  ", rank);
+            for file in File::ALL {
+                print!("{} ", match self.chk(Square::new(file, *rank)) {
                     true => '1',
                     false => '.',
                 });
@@ -31,8 +35,8 @@ impl BitHelpers for BitBoard {
             println!();
         }
         print!("   ");
-        for fc in FILEC.iter() {
-            print!("{} ", fc);
+        for file in File::ALL {
+            print!("{} ", file);
         } 
         println!()
     }
@@ -41,13 +45,14 @@ impl BitHelpers for BitBoard {
 pub struct BitRunner(u64);
 
 impl Iterator for BitRunner {
-    type Item = u8;
+    type Item = Square;
 
+    #[inline(always)]
     fn next(&mut self) -> Option<Self::Item> {
         if self.0 != 0 {
             let n = self.0.trailing_zeros() as u8;
             self.0 &= self.0 - 1;
-            Some(n)
+            Some(Square::from_index_unchecked(n))
         } else {
             None
         }
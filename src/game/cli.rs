@@ -0,0 +1,101 @@
+use super::{pos::*, movgen::*, search::*};
+ 
+/// Runs the subcommand in `args`, or the interactive console if there is none.
+pub fn run(args: &[String]) {
+    if let Some("gen-data") = args.first().map(|s| s.as_str()) {
+        match super::datagen::Config::from_args(&args[1..]) {
+            Ok(cfg) => if let Err(e) = super::datagen::run(&cfg) {
+                eprintln!("gen-data failed: {}", e);
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+        return
+    }
+    if let Some("perft") = args.first().map(|s| s.as_str()) {
+        perft_command(&args[1..]);
+        return
+    }
+    if let (Some("bench"), Some("sliders")) = (args.first().map(|s| s.as_str()), args.get(1).map(|s| s.as_str())) {
+        super::bench::sliders(args.get(2).and_then(|n| n.parse().ok()).unwrap_or(100_000_000));
+        return
+    }
+    if let Some("bench") = args.first().map(|s| s.as_str()) {
+        let depth = args.get(1).and_then(|d| d.parse().ok()).unwrap_or(super::bench::DEFAULT_DEPTH);
+        super::bench::run(&mut Searcher::with_hash_mb(Position::new(), 16), depth);
+        return
+    }
+    if let Some("match") = args.first().map(|s| s.as_str()) {
+        match super::matchup::Config::from_args(&args[1..]) {
+            Ok(cfg) => if let Err(e) = super::matchup::run(&cfg) {
+                eprintln!("match failed: {}", e);
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+        return
+    }
+    if let Some("wdl-fit") = args.first().map(|s| s.as_str()) {
+        if let Err(e) = super::wdl::run(&args[1..]) {
+            eprintln!("wdl-fit failed: {}", e);
+        }
+        return
+    }
+    if let Some("tune") = args.first().map(|s| s.as_str()) {
+        match super::tune::Config::from_args(&args[1..]) {
+            Ok(cfg) => if let Err(e) = super::tune::run(&cfg) {
+                eprintln!("tune failed: {}", e);
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+        return
+    }
+
+    super::console::run();
+}
+
+/// `perft <depth> [fen] [threads N] [hash MB] [variant V]` or
+/// `perft suite <file> [depth N] [threads N] [hash MB] [variant V]`.
+fn perft_command(args: &[String]) {
+    use super::{perft, variant::Variant};
+    let mut threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let (mut hash_mb, mut max_depth) = (64, 6);
+    let mut variant = Variant::Standard;
+    let mut rest = Vec::new();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let next = it.as_slice().first();
+        let val = next.and_then(|v| v.parse::<usize>().ok());
+        match (arg.as_str(), val) {
+            ("threads", Some(v)) => threads = v.max(1),
+            ("hash", Some(v)) => hash_mb = v,
+            ("depth", Some(v)) => max_depth = v as u8,
+            ("variant", _) => match next.map(|v| v.parse::<Variant>()) {
+                Some(Ok(v)) => variant = v,
+                Some(Err(e)) => return eprintln!("{}", e),
+                None => return eprintln!("missing value for variant"),
+            },
+            ("sliders", _) => match next.map(|v| v.parse::<super::magic_table::SliderBackend>()) {
+                Some(Ok(b)) => if !ATTK_TBL.set_backend(b) { return eprintln!("{} not supported on this CPU", b) },
+                Some(Err(e)) => return eprintln!("{}", e),
+                None => return eprintln!("missing value for sliders"),
+            },
+            _ => { rest.push(arg.as_str()); continue }
+        }
+        it.next();
+    }
+    match rest.as_slice() {
+        ["suite", path] => if let Err(e) = perft::run_suite(path, variant, max_depth, threads, hash_mb) {
+            eprintln!("perft suite failed: {}", e);
+        },
+        [depth, fen @ ..] => match depth.parse::<u8>() {
+            Ok(depth) => {
+                let fen = if fen.is_empty() { variant.start_fen().to_owned() } else { fen.join(" ") };
+                let mut p = Position::new();
+                p.variant = variant;
+                p.load_fen(&fen);
+                perft::run(&mut p, depth, threads, hash_mb);
+            }
+            Err(_) => eprintln!("bad perft depth {}", depth),
+        },
+        [] => eprintln!("usage: perft <depth> [fen] | perft suite <file>"),
+    }
+}
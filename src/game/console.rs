@@ -0,0 +1,299 @@
+use super::{movgen::Move, pgn, pos::Position, rules::GameStatus, search::*, skill::{self, Skill}, uci};
+use super::types::{Color, File, Piece, Rank, Square};
+use super::variant::Variant;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+const HELP: &str = "\
+Moves are given in SAN (Nf3, O-O, exd8=Q) or UCI notation (g1f3).
+  new [variant]        start a new game
+  fen [FEN]            show the FEN or set up a position
+  moves                list the legal moves
+  undo, redo           take back or replay a move, the engine's reply included
+  go                   let the engine move now
+  play SIDE            sides the engine plays: white, black, both or none
+  depth N, time SECS, nodes N
+                       limit the engine's search, 0 removes the limit
+  skill N, elo N       weaken the engine to a level from 0 to 19 or a rating,
+                       skill 20 and elo 2800 play at full strength
+  hint                 suggest a move
+  flip                 turn the board around
+  unicode              switch between letters and unicode pieces
+  save FILE, load FILE write or read the game as PGN
+  eval                 show the static evaluation
+  uci                  switch to the UCI protocol
+  quit";
+
+fn glyph(p: Piece, unicode: bool) -> char {
+    if !unicode { return p.char() }
+    const GLYPHS: [char; 12] = ['♙', '♟', '♘', '♞', '♗', '♝', '♖', '♜', '♕', '♛', '♔', '♚'];
+    GLYPHS[p.index()]
+}
+
+struct Console {
+    s: Searcher,
+    /// Where the game began, for PGN output.
+    start: Position,
+    redo: Vec<Move>,
+    engine: [bool; 2],
+    limits: SearchLimits,
+    flipped: bool,
+    unicode: bool,
+}
+
+impl Console {
+    fn new() -> Self {
+        let mut s = Searcher::new(Position::new());
+        s.pos.load_fen(s.pos.variant.start_fen());
+        Self {
+            start: s.pos.clone(),
+            s,
+            redo: Vec::new(),
+            engine: [false, true],
+            limits: SearchLimits { move_time: Some(Duration::from_secs(1)), ..SearchLimits::default() },
+            flipped: false,
+            unicode: false,
+        }
+    }
+
+    fn board(&self) -> String {
+        let pos = &self.s.pos;
+        let mut ranks = Rank::ALL;
+        let mut files = File::ALL;
+        if self.flipped { files.reverse() } else { ranks.reverse() }
+        let mut out = String::new();
+        for &r in ranks.iter() {
+            out.push_str(&format!(" {} ", r));
+            for &f in files.iter() {
+                out.push(' ');
+                out.push(pos.board[Square::new(f, r)].map_or('.', |p| glyph(p, self.unicode)));
+            }
+            out.push('\n');
+        }
+        out.push_str("   ");
+        for f in files.iter() { out.push_str(&format!(" {}", f)); }
+        out.push_str(&format!("\n\n{} to move, {}\n", pos.turn, pos.fen()));
+        out
+    }
+
+    fn status(&mut self) -> GameStatus { self.s.pos.status() }
+
+    fn announce(&mut self) {
+        let status = self.status();
+        if status.is_over() { println!("game over: {} ({})", status, status.result()); }
+    }
+
+    /// Starts a game from `fen`, or from the variant's start position.
+    fn new_game(&mut self, variant: Variant, fen: Option<&str>) -> Result<(), String> {
+        let old = self.s.pos.variant;
+        self.s.pos.variant = variant;
+        if let Err(e) = self.s.pos.try_load_fen(fen.unwrap_or_else(|| variant.start_fen())) {
+            self.s.pos.variant = old;
+            return Err(e)
+        }
+        self.start = self.s.pos.clone();
+        self.redo.clear();
+        self.s.new_game();
+        Ok(())
+    }
+
+    fn parse_move(&mut self, s: &str) -> Result<Move, String> {
+        self.s.pos.parse_san(s).or_else(|e| Move::from_uci(&self.s.pos, s).map_err(|_| e))
+    }
+
+    fn play(&mut self, m: Move) {
+        self.s.pos.make_move(m);
+        self.redo.clear();
+    }
+
+    /// Searches within the limits; hints leave out the skill setting.
+    fn think(&mut self, weakened: bool) -> SearchResult {
+        let mut limits = self.limits.clone();
+        if !weakened { limits.skill = None; }
+        self.s.go(&limits, |_| ())
+    }
+
+    fn describe(&mut self, res: &SearchResult) -> String {
+        let m = res.best_move.expect("no move to describe");
+        format!("{} ({}, depth {})", self.s.pos.to_san(m), res.score, res.depth)
+    }
+
+    fn engine_move(&mut self) {
+        let res = self.think(true);
+        if let Some(m) = res.best_move {
+            println!("engine plays {}", self.describe(&res));
+            self.play(m);
+        }
+        self.announce();
+    }
+
+    /// Lets the engine answer for as long as it plays the side to move.
+    fn reply(&mut self) {
+        while self.engine[self.s.pos.turn] && !self.status().is_over() {
+            self.engine_move();
+        }
+    }
+
+    /// Whether the engine would answer the move to play now, so `undo` and `redo`
+    /// step over its reply as well.
+    fn engine_to_move(&self) -> bool {
+        self.engine[self.s.pos.turn] && !self.engine[!self.s.pos.turn]
+    }
+
+    fn take_back(&mut self) {
+        if let Some(m) = self.s.pos.hist.last().map(|h| h.m) {
+            self.s.pos.unmake_move();
+            self.redo.push(m);
+        }
+    }
+
+    fn replay(&mut self) {
+        if let Some(m) = self.redo.pop() { self.s.pos.make_move(m); }
+    }
+
+    fn save(&self, path: &str) -> io::Result<()> {
+        let mut pos = self.s.pos.clone();
+        let result = pos.status().result();
+        let player = |c: Color| if self.engine[c] { "bitbased" } else { "player" };
+        let tags = [("Event", "bitbased console"), ("Site", "?"), ("Date", "????.??.??"), ("Round", "-"),
+            ("White", player(Color::White)), ("Black", player(Color::Black)), ("Result", result)];
+        let moves: Vec<Move> = self.s.pos.hist.iter().map(|h| h.m).collect();
+        let mut out = std::fs::File::create(path)?;
+        pgn::write_game(&mut out, &tags, &self.start, &moves)
+    }
+
+    fn load(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+        let game = pgn::read_game(&text)?;
+        self.new_game(game.start.variant, Some(&game.start.fen()))?;
+        for &m in game.moves.iter() {
+            self.s.pos.make_move(m);
+        }
+        Ok(())
+    }
+
+    /// Handles one line of input; returns false to leave the console.
+    fn command(&mut self, line: &str) -> bool {
+        let (cmd, arg) = line.split_once(' ').map_or((line, ""), |(c, a)| (c, a.trim()));
+        let num = || arg.parse::<u64>().map_err(|_| format!("expected a number, got '{}'", arg));
+        let res: Result<(), String> = match cmd {
+            "" => Ok(()),
+            "quit" | "exit" => return false,
+            "help" => { println!("{}", HELP); Ok(()) }
+            "new" => match arg {
+                "" => self.new_game(self.s.pos.variant, None),
+                v => v.parse().and_then(|v| self.new_game(v, None)),
+            }.map(|_| println!("{}", self.board())),
+            "fen" if arg.is_empty() => { println!("{}", self.s.pos.fen()); Ok(()) }
+            "fen" => self.new_game(self.s.pos.variant, Some(arg)).map(|_| println!("{}", self.board())),
+            "moves" => {
+                let legal = self.s.pos.legal_moves();
+                let san: Vec<String> = legal.into_iter().map(|m| self.s.pos.to_san(m)).collect();
+                println!("{} legal moves: {}", san.len(), san.join(" "));
+                Ok(())
+            }
+            "undo" if self.s.pos.hist.is_empty() => Err("no move to undo".to_owned()),
+            "undo" => {
+                self.take_back();
+                if self.engine_to_move() { self.take_back(); }
+                println!("{}", self.board());
+                Ok(())
+            }
+            "redo" if self.redo.is_empty() => Err("no move to redo".to_owned()),
+            "redo" => {
+                self.replay();
+                if self.engine_to_move() { self.replay(); }
+                println!("{}", self.board());
+                Ok(())
+            }
+            "go" if self.status().is_over() => Err("the game is over".to_owned()),
+            "go" => { self.engine_move(); println!("{}", self.board()); Ok(()) }
+            "play" => match arg {
+                "white" => Ok([true, false]),
+                "black" => Ok([false, true]),
+                "both" => Ok([true, true]),
+                "none" => Ok([false, false]),
+                _ => Err(format!("expected white, black, both or none, got '{}'", arg)),
+            }.map(|e| {
+                self.engine = e;
+                if self.engine[self.s.pos.turn] {
+                    self.reply();
+                    println!("{}", self.board());
+                }
+            }),
+            "depth" => num().map(|n| self.limits.depth = (n > 0).then_some(n.min(64) as u8)),
+            "nodes" => num().map(|n| self.limits.nodes = (n > 0).then_some(n)),
+            "time" => arg.parse::<f64>().map_err(|_| format!("expected seconds, got '{}'", arg))
+                .map(|t| self.limits.move_time = (t > 0.0).then(|| Duration::from_secs_f64(t))),
+            "skill" => num().map(|n| self.limits.skill = Skill::from_level(n.min(skill::MAX_LEVEL as u64) as u8)),
+            "elo" => num().map(|n| self.limits.skill = Skill::from_elo(n.min(u32::MAX as u64) as u32)),
+            "hint" if self.status().is_over() => Err("the game is over".to_owned()),
+            "hint" => {
+                let res = self.think(false);
+                println!("hint: {}", self.describe(&res));
+                Ok(())
+            }
+            "flip" => { self.flipped = !self.flipped; println!("{}", self.board()); Ok(()) }
+            "unicode" => { self.unicode = !self.unicode; println!("{}", self.board()); Ok(()) }
+            "save" => self.save(arg).map_err(|e| format!("can't write {}: {}", arg, e)),
+            "load" => self.load(arg).map(|_| { println!("{}", self.board()); self.announce(); }),
+            "eval" => { println!("{}", self.s.pos.eval_trace()); Ok(()) }
+            _ if self.status().is_over() => Err("the game is over, use undo or new".to_owned()),
+            _ => self.parse_move(line).map(|m| {
+                self.play(m);
+                self.announce();
+                self.reply();
+                println!("{}", self.board());
+            }).map_err(|e| format!("{}; type help for the commands", e)),
+        };
+        if let Err(e) = res { println!("{}", e); }
+        true
+    }
+}
+
+/// Plays against the engine on the terminal until `quit`, the end of input or `uci`,
+/// which hands the session over to the UCI loop.
+pub fn run() {
+    let mut console = Console::new();
+    println!("{}", console.board());
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        line.clear();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 { break }
+        let line = line.trim();
+        if line == "uci" {
+            return uci::UCI::new(console.s).uci_loop()
+        }
+        if !console.command(line) { break }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_step_over_the_reply() {
+        let mut c = Console::new();
+        for line in ["depth 1", "time 0", "e4"] { assert!(c.command(line)); }
+        assert_eq!(c.s.pos.hist.len(), 2);
+        let after_reply = c.s.pos.fen();
+        c.command("undo");
+        assert_eq!((c.s.pos.hist.len(), c.redo.len()), (0, 2));
+        c.command("redo");
+        assert_eq!((c.s.pos.fen(), c.redo.len()), (after_reply, 0));
+
+        //a new move drops the moves to redo
+        c.command("undo");
+        c.command("d4");
+        assert!(c.redo.is_empty() && c.s.pos.hist.len() == 2);
+
+        //without the engine every move is taken back on its own
+        for line in ["play none", "Nf3", "undo"] { c.command(line); }
+        assert_eq!((c.s.pos.hist.len(), c.redo.len()), (2, 1));
+        assert!(!c.command("quit"));
+    }
+}
@@ -0,0 +1,192 @@
+use super::{bitbrd::*, defs::*, pos::*, search::*, types::Color, uci::START_FEN};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use std::{collections::HashMap, fs::File, io::{self, BufWriter, Write}};
+use std::sync::{Mutex, atomic::{AtomicU64, Ordering}};
+use std::time::{Instant, SystemTime};
+
+const MAX_GAME_PLIES: usize = 400;
+const WIN_ADJUDICATION: i16 = 1500;
+const WIN_ADJUDICATION_PLIES: usize = 4;
+const MATE_BOUND: i16 = INFINITY - 100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Binary,
+}
+
+pub struct Config {
+    pub games: u64,
+    pub threads: usize,
+    pub depth: u8,
+    pub nodes: Option<u64>,
+    pub random_plies: usize,
+    pub hash_mb: usize,
+    pub out: String,
+    pub format: Format,
+}
+
+impl Config {
+    /// Parses `key value` pairs, e.g. `games 1000 threads 4 depth 6 out data.txt`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut cfg = Self {
+            games: 100, threads: 1, depth: 6, nodes: None, random_plies: 8,
+            hash_mb: 16, out: "data.txt".to_owned(), format: Format::Text,
+        };
+        let mut opts = HashMap::new();
+        let mut it = args.iter();
+        while let Some(key) = it.next() {
+            let val = it.next().ok_or(format!("missing value for {}", key))?;
+            opts.insert(key.as_str(), val.as_str());
+        }
+        for (key, val) in opts {
+            let num = || val.parse::<u64>().map_err(|_| format!("bad value for {}: {}", key, val));
+            match key {
+                "games" => cfg.games = num()?,
+                "threads" => cfg.threads = num()?.max(1) as usize,
+                "depth" => cfg.depth = num()?.clamp(1, 64) as u8,
+                "nodes" => cfg.nodes = Some(num()?),
+                "random" => cfg.random_plies = num()? as usize,
+                "hash" => cfg.hash_mb = num()?.max(1) as usize,
+                "out" => cfg.out = val.to_owned(),
+                "format" => cfg.format = match val {
+                    "text" => Format::Text,
+                    "bin" => Format::Binary,
+                    _ => return Err(format!("unknown format {}", val)),
+                },
+                _ => return Err(format!("unknown option {}", key)),
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+/// A quiet position with the search score and the game result, both from white's side.
+pub struct Sample {
+    pub fen: String,
+    pub record: [u8; 32],
+    pub score: i16,
+}
+
+/// Packs a position into 32 bytes: occupancy (8), a nibble per occupied square
+/// in square order (16), score as i16 from white's side (2), result (1, 0 = black
+/// wins, 1 = draw, 2 = white wins), side to move (1), en passant square (1),
+/// castling rights (1), fifty move counter (1) and a padding byte.
+pub fn pack(p: &Position, score: i16) -> [u8; 32] {
+    let mut rec = [0; 32];
+    let occ = p.all_ocupied();
+    rec[0..8].copy_from_slice(&occ.to_le_bytes());
+    for (i, sq) in occ.bits().enumerate().take(32) {
+        let id = p.board[sq].map_or(0, |pc| pc.index() as u8);
+        rec[8 + i / 2] |= id << (4 * (i % 2));
+    }
+    rec[24..26].copy_from_slice(&score.to_le_bytes());
+    rec[27] = p.turn as u8;
+    rec[28] = p.ep.map_or(255, |sq| sq.u8());
+    rec[29] = p.cas.id();
+    rec[30] = p.fty;
+    rec
+}
+
+/// Plays one game and returns its quiet positions and the result for white (0, 1 or 2).
+fn play_game(s: &mut Searcher, cfg: &Config, rng: &mut SmallRng) -> (Vec<Sample>, u8) {
+    loop {
+        s.pos.load_fen(START_FEN);
+        let mut ok = true;
+        for _ in 0..cfg.random_plies {
+            let moves = s.pos.legal_moves();
+            if moves.is_empty() { ok = false; break; }
+            s.pos.make_move(moves[rng.gen_range(0..moves.len())]);
+        }
+        if ok && !s.pos.legal_moves().is_empty() { break; }
+    }
+    s.tt.clear();
+
+    let mut samples = Vec::new();
+    let mut winning_streak = (0, Color::White);
+    //results are 2 * loser: a white loss is 0, a black loss is 2
+    let result;
+    loop {
+        let status = s.pos.status();
+        //every ply played counts, not just the ones sampled
+        if status.is_over() || s.pos.is_material_draw() || s.pos.hist.len() >= MAX_GAME_PLIES {
+            result = status.winner().map_or(1, |w| 2 * !w as u8);
+            break;
+        }
+        let moves = s.pos.legal_moves();
+
+        let mut info = SearchInfo::new(cfg.depth, None).quiet();
+        if let Some(nodes) = cfg.nodes {
+            info = info.with_nodes(nodes);
+        }
+        s.search(&mut info);
+        let m = if info.best_move.is_null() { moves[0] } else { info.best_move };
+        let score = if s.pos.turn == Color::White { info.best_score } else { -info.best_score };
+
+        if score.abs() >= WIN_ADJUDICATION {
+            let winner = if score > 0 { Color::White } else { Color::Black };
+            winning_streak = if winning_streak.1 == winner { (winning_streak.0 + 1, winner) } 
+                else { (1, winner) };
+            if winning_streak.0 >= WIN_ADJUDICATION_PLIES {
+                result = 2 * !winner as u8;
+                break;
+            }
+        } else {
+            winning_streak.0 = 0;
+        }
+
+        let quiet = !m.cap() && m.prom().is_none() && !s.pos.in_check(s.pos.turn) 
+            && score.abs() < MATE_BOUND;
+        if quiet {
+            samples.push(Sample { fen: s.pos.fen(), record: pack(&s.pos, score), score });
+        }
+        s.pos.make_move(m);
+    }
+    (samples, result)
+}
+
+pub fn run(cfg: &Config) -> io::Result<()> {
+    let out = Mutex::new(BufWriter::new(File::create(&cfg.out)?));
+    let started = AtomicU64::new(0);
+    let positions = AtomicU64::new(0);
+    let seed = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64).unwrap_or(0);
+    let start = Instant::now();
+
+    std::thread::scope(|s| {
+        let mut handles = Vec::new();
+        for t in 0..cfg.threads {
+            let (out, started, positions) = (&out, &started, &positions);
+            handles.push(s.spawn(move || -> io::Result<()> {
+                let mut rng = SmallRng::seed_from_u64(seed ^ (t as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+                let mut searcher = Searcher::with_hash_mb(Position::new(), cfg.hash_mb);
+                loop {
+                    let game = started.fetch_add(1, Ordering::Relaxed);
+                    if game >= cfg.games { break; }
+                    let (samples, result) = play_game(&mut searcher, cfg, &mut rng);
+
+                    let mut out = out.lock().unwrap();
+                    for mut smp in samples {
+                        match cfg.format {
+                            Format::Text => writeln!(out, "{} | {} | {}", smp.fen, smp.score, 
+                                ["0.0", "0.5", "1.0"][result as usize])?,
+                            Format::Binary => {
+                                smp.record[26] = result;
+                                out.write_all(&smp.record)?;
+                            }
+                        }
+                        positions.fetch_add(1, Ordering::Relaxed);
+                    }
+                    let done = game + 1;
+                    if done % 10 == 0 || done == cfg.games {
+                        println!("games {} positions {} time {}s", done, 
+                            positions.load(Ordering::Relaxed), start.elapsed().as_secs());
+                    }
+                }
+                Ok(())
+            }));
+        }
+        handles.into_iter().try_for_each(|h| h.join().unwrap())
+    })?;
+    out.into_inner().unwrap().flush()
+}
@@ -0,0 +1,226 @@
+use super::{bitbrd::*, defs::*, eval::*, pos::*};
+use super::types::{Color, File, PieceType, Rank, Square};
+use crate::lazy_static::lazy_static;
+use std::collections::HashMap;
+
+pub const SCALE_NORMAL: i16 = 64;
+pub const KNOWN_WIN: i16 = 10000;
+
+
+type EndgameFn = fn(&Position, Color) -> i16;
+
+lazy_static! {
+    static ref ENDGAMES: HashMap<u64, (EndgameFn, Color, &'static str)> = {
+        let mut m = HashMap::new();
+        add(&mut m, "KBNK", kbnk);
+        add(&mut m, "KRKP", krkp);
+        add(&mut m, "KQKP", kqkp);
+        m
+    };
+}
+
+/// Registers `code` (strong side first, e.g. "KBNK") for both colors.
+fn add(m: &mut HashMap<u64, (EndgameFn, Color, &'static str)>, code: &'static str, f: EndgameFn) {
+    let weak_start = code[1..].find('K').unwrap() + 1;
+    let (strong, weak) = code.split_at(weak_start);
+    for side in Color::ALL {
+        let mut counts = [[0; 6]; 2];
+        for (pieces, c) in [(strong, side), (weak, !side)].iter() {
+            for pt in pieces.chars().filter_map(PieceType::from_char) {
+                counts[*c][pt] += 1;
+            }
+        }
+        m.insert(signature(&counts), (f, side, code));
+    }
+}
+
+fn signature(counts: &[[u64; 6]; 2]) -> u64 {
+    let mut key = 0;
+    for c in Color::ALL {
+        for &pt in &PieceType::ALL[..PieceType::King.index()] {
+            key |= counts[c][pt].min(15) << (4 * (c.index() * 5 + pt.index()));
+        }
+    }
+    key
+}
+
+fn push_to_edge(sq: Square) -> i16 {
+    let (r, f) = (sq.rank() as i16, sq.file() as i16);
+    20 * ((3 - r).max(r - 4) + (3 - f).max(f - 4))
+}
+
+fn push_close(a: Square, b: Square) -> i16 {
+    140 - 20 * distance(a, b) as i16
+}
+
+fn king_sq(p: &Position, side: Color) -> Square {
+    p.king_square(side).expect("no king")
+}
+
+/// The square of the only piece of type `pt` that `side` has.
+fn lone_sq(p: &Position, side: Color, pt: PieceType) -> Square {
+    Square::from_index_unchecked(p.pieces[side][pt].trailing_zeros() as u8)
+}
+
+/// Any mating material against a bare king: drive it to the edge.
+fn kxk(p: &Position, strong: Color) -> i16 {
+    let (sk, wk) = (king_sq(p, strong), king_sq(p, !strong));
+    p.material[strong] + push_to_edge(wk) + push_close(sk, wk) + KNOWN_WIN
+}
+
+/// Bishop and knight mate: drive the king to a corner of the bishop's color.
+fn kbnk(p: &Position, strong: Color) -> i16 {
+    let (sk, wk) = (king_sq(p, strong), king_sq(p, !strong));
+    let light = p.pieces[strong][PieceType::Bishop] & LIGHT_SQUARES != 0;
+    let (c1, c2) = if light { (File::H, File::A) } else { (File::A, File::H) };
+    let (c1, c2) = (Square::new(c1, Rank::R1), Square::new(c2, Rank::R8));
+    let corner = distance(wk, c1).min(distance(wk, c2)) as i16;
+    p.material[strong] + push_close(sk, wk) + 40 * (7 - corner) + KNOWN_WIN
+}
+
+fn krkp(p: &Position, strong: Color) -> i16 {
+    let weak = !strong;
+    //mirrored so that the strong side always plays up the board
+    let sk = king_sq(p, strong).relative(strong);
+    let wk = king_sq(p, weak).relative(strong);
+    let rsq = lone_sq(p, strong, PieceType::Rook).relative(strong);
+    let psq = lone_sq(p, weak, PieceType::Pawn).relative(strong);
+    let queening = Square::new(psq.file(), Rank::R1);
+    let tempo = (p.turn == weak) as u8;
+
+    //the pawn runs down the board from the strong side's point of view
+    if sk.file() == psq.file() && sk < psq {
+        return MATERIAL_TABLE[PieceType::Rook] - distance(sk, psq) as i16;
+    }
+    if distance(wk, psq) >= 3 + tempo && distance(wk, rsq) >= 3 {
+        return MATERIAL_TABLE[PieceType::Rook] - distance(sk, psq) as i16;
+    }
+    if wk.rank() <= Rank::R3 && distance(wk, psq) == 1 && sk.rank() >= Rank::R4
+        && distance(sk, psq) > 2 + (1 - tempo) {
+        return 80 - 8 * distance(sk, psq) as i16;
+    }
+    let stop = if psq.rank() == Rank::R1 { psq } else { psq.down(1) };
+    200 - 8 * (distance(sk, stop) as i16 - distance(wk, stop) as i16 
+        - distance(psq, queening) as i16)
+}
+
+fn kqkp(p: &Position, strong: Color) -> i16 {
+    let weak = !strong;
+    let (sk, wk) = (king_sq(p, strong), king_sq(p, weak));
+    let psq = lone_sq(p, weak, PieceType::Pawn);
+    let mut score = push_close(sk, wk);
+
+    //a rook or bishop pawn on the seventh next to its king holds the draw
+    let file = psq.file();
+    if psq.rank().relative(weak) != Rank::R7 || distance(wk, psq) != 1
+        || !matches!(file, File::A | File::C | File::F | File::H) {
+        score += MATERIAL_TABLE[PieceType::Queen] - MATERIAL_TABLE[PieceType::Pawn];
+    }
+    score
+}
+
+fn non_pawn(p: &Position, side: Color) -> i16 {
+    p.material[side] - MATERIAL_TABLE[PieceType::Pawn] * p.pieces[side][PieceType::Pawn].count_ones() as i16
+}
+
+impl Position {
+    pub fn material_key(&self) -> u64 {
+        let mut counts = [[0; 6]; 2];
+        for c in Color::ALL {
+            for &pt in &PieceType::ALL[..PieceType::King.index()] {
+                counts[c][pt] = self.pieces[c][pt].count_ones() as u64;
+            }
+        }
+        signature(&counts)
+    }
+
+    /// A specialized evaluator for the material on the board, from white's point of view.
+    pub fn endgame_eval(&self) -> Option<(i16, &'static str)> {
+        if self.all_ocupied().count_ones() > 6 { return None }
+        if let Some((f, strong, name)) = ENDGAMES.get(&self.material_key()) {
+            let v = f(self, *strong);
+            return Some((if *strong == Color::White { v } else { -v }, name));
+        }
+        for strong in Color::ALL {
+            let weak = !strong;
+            if self.occupied[weak] == self.pieces[weak][PieceType::King]
+                && non_pawn(self, strong) >= MATERIAL_TABLE[PieceType::Rook] {
+                let v = kxk(self, strong);
+                return Some((if strong == Color::White { v } else { -v }, "KXK"));
+            }
+        }
+        None
+    }
+
+    /// How much of the evaluation the `strong` side can hope to convert, out of `SCALE_NORMAL`.
+    pub fn scale_factor(&self, strong: Color) -> i16 {
+        let weak = !strong;
+        if self.is_material_draw() { return 0 }
+
+        let (npm_strong, npm_weak) = (non_pawn(self, strong), non_pawn(self, weak));
+        if self.pieces[strong][PieceType::Pawn] == 0 && npm_strong - npm_weak <= MATERIAL_TABLE[PieceType::Bishop] {
+            return if npm_strong < MATERIAL_TABLE[PieceType::Rook] { 0 }
+                else if npm_weak <= MATERIAL_TABLE[PieceType::Bishop] { 4 }
+                else { 14 };
+        }
+
+        //rook pawns with a bishop that does not control the queening square
+        let pawns = self.pieces[strong][PieceType::Pawn];
+        let bishops = self.pieces[strong][PieceType::Bishop];
+        if bishops != 0 && npm_strong == MATERIAL_TABLE[PieceType::Bishop] * bishops.count_ones() as i16
+            && pawns != 0 && (pawns & !FILE_A == 0 || pawns & !FILE_H == 0) {
+            let file = Square::from_index_unchecked(pawns.trailing_zeros() as u8).file();
+            let promo = Square::new(file, Rank::R8.relative(strong));
+            let light_bishops = bishops & LIGHT_SQUARES != 0;
+            let dark_bishops = bishops & !LIGHT_SQUARES != 0;
+            let wrong = if LIGHT_SQUARES.chk(promo) { !light_bishops } else { !dark_bishops };
+            if wrong && distance(king_sq(self, weak), promo) <= 1 {
+                return 0;
+            }
+        }
+
+        //opposite-colored bishops
+        let (wb, bb) = (self.pieces[Color::White][PieceType::Bishop], self.pieces[Color::Black][PieceType::Bishop]);
+        if wb.count_ones() == 1 && bb.count_ones() == 1
+            && (wb & LIGHT_SQUARES != 0) != (bb & LIGHT_SQUARES != 0) {
+            if npm_strong == MATERIAL_TABLE[PieceType::Bishop] && npm_weak == MATERIAL_TABLE[PieceType::Bishop] {
+                return 16;
+            }
+            return 46;
+        }
+
+        SCALE_NORMAL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_of(fen: &str) -> (i16, Option<&'static str>) {
+        let mut pos = Position::new();
+        pos.load_fen(fen);
+        (pos.eval(), pos.endgame_eval().map(|e| e.1))
+    }
+
+    #[test]
+    fn recognizes_endgames() {
+        assert_eq!(eval_of("8/8/8/4k3/8/8/8/KBN5 w - - 0 1").1, Some("KBNK"));
+        assert_eq!(eval_of("8/8/8/4k3/8/8/8/KBN5 b - - 0 1").1, Some("KBNK"));
+        assert_eq!(eval_of("knb5/8/8/8/4K3/8/8/8 w - - 0 1").1, Some("KBNK"));
+        assert_eq!(eval_of("8/8/8/4k3/8/8/8/KR6 w - - 0 1").1, Some("KXK"));
+        assert_eq!(eval_of("8/8/8/4k3/8/8/8/KN6 w - - 0 1").1, None);
+        assert!(eval_of("8/8/8/4k3/8/8/8/KBN5 w - - 0 1").0 > KNOWN_WIN);
+        assert!(eval_of("8/8/8/4k3/8/8/8/KBN5 b - - 0 1").0 < -KNOWN_WIN);
+    }
+
+    #[test]
+    fn scales_draws() {
+        //wrong bishop for the h-pawn
+        assert_eq!(eval_of("7k/8/8/8/7P/8/8/KB6 w - - 0 1").0, 0);
+        //right bishop
+        assert!(eval_of("7k/8/8/8/7P/8/8/K1B5 w - - 0 1").0 > 100);
+        //rook versus bishop
+        assert!(eval_of("8/8/3bk3/8/8/3RK3/8/8 w - - 0 1").0.abs() < 50);
+    }
+}
@@ -1,132 +1,57 @@
-use super::pos::*;
+use super::{pos::*, endgame::*};
 use super::defs::*;
 use super::bitbrd::*;
-
-
-const LOCATION_TABLE: [[i8; 64]; 4] = [
-    [
-        0	,	0	,	0	,	0	,	0	,	0	,	0	,	0	,
-        10	,	10	,	0	,	-10	,	-10	,	0	,	10	,	10	,
-        5	,	0	,	0	,	5	,	5	,	0	,	0	,	5	,
-        0	,	0	,	10	,	20	,	20	,	10	,	0	,	0	,
-        5	,	5	,	5	,	10	,	10	,	5	,	5	,	5	,
-        10	,	10	,	10	,	20	,	20	,	10	,	10	,	10	,
-        20	,	20	,	20	,	30	,	30	,	20	,	20	,	20	,
-        0	,	0	,	0	,	0	,	0	,	0	,	0	,	0	
-    ],
-    [
-        0	,	-10	,	0	,	0	,	0	,	0	,	-10	,	0	,
-        0	,	0	,	0	,	5	,	5	,	0	,	0	,	0	,
-        0	,	0	,	10	,	10	,	10	,	10	,	0	,	0	,
-        0	,	0	,	10	,	20	,	20	,	10	,	5	,	0	,
-        5	,	10	,	15	,	20	,	20	,	15	,	10	,	5	,
-        5	,	10	,	10	,	20	,	20	,	10	,	10	,	5	,
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0	,
-        0	,	0	,	0	,	0	,	0	,	0	,	0	,	0		
-    ],
-    [
-        0	,	0	,	-10	,	0	,	0	,	-10	,	0	,	0	,
-        0	,	0	,	0	,	10	,	10	,	0	,	0	,	0	,
-        0	,	0	,	10	,	15	,	15	,	10	,	0	,	0	,
-        0	,	10	,	15	,	20	,	20	,	15	,	10	,	0	,
-        0	,	10	,	15	,	20	,	20	,	15	,	10	,	0	,
-        0	,	0	,	10	,	15	,	15	,	10	,	0	,	0	,
-        0	,	0	,	0	,	10	,	10	,	0	,	0	,	0	,
-        0	,	0	,	0	,	0	,	0	,	0	,	0	,	0	
-    ],
-    [
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0	,
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0	,
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0	,
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0	,
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0	,
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0	,
-        25	,	25	,	25	,	25	,	25	,	25	,	25	,	25	,
-        0	,	0	,	5	,	10	,	10	,	5	,	0	,	0		
-    ]
-];
-
-
-const KING_LOCATION: [[i8; 64]; 2] = [
-[
-	0	,	5	,	5	,	-10	,	-10	,	0	,	10	,	5	,
-	-30	,	-30	,	-30	,	-30	,	-30	,	-30	,	-30	,	-30	,
-	-50	,	-50	,	-50	,	-50	,	-50	,	-50	,	-50	,	-50	,
-	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,
-	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,
-	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,
-	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,
-	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70	,	-70		
-],
-[
-	-50	,	-10	,	0	,	0	,	0	,	0	,	-10	,	-50	,
-	-10,	0	,	10	,	10	,	10	,	10	,	0	,	-10	,
-	0	,	10	,	20	,	20	,	20	,	20	,	10	,	0	,
-	0	,	10	,	20	,	40	,	40	,	20	,	10	,	0	,
-	0	,	10	,	20	,	40	,	40	,	20	,	10	,	0	,
-	0	,	10	,	20	,	20	,	20	,	20	,	10	,	0	,
-	-10,	0	,	10	,	10	,	10	,	10	,	0	,	-10	,
-	-50	,	-10	,	0	,	0	,	0	,	0	,	-10	,	-50	
-],
-];
-
-const KING_SHIELDED: i16 = 15;
-
-const RANK: [u64; 8] = [
+use super::movgen::ATTK_TBL;
+use super::params::EvalParams;
+use super::pawns::PawnTable;
+use super::variant::Variant;
+use super::types::{Color, File, PieceType, Rank, Square};
+use std::{fmt, sync::Arc};
+
+pub(super) const RANK: [u64; 8] = [
     0xFF      , 0xFF << 8 , 0xFF << 16, 0xFF << 24, 
     0xFF << 32, 0xFF << 40, 0xFF << 48, 0xFF << 56,
 ];
 
-const FILE: [u64; 8] = [
+pub(super) const FILE: [u64; 8] = [
     FILE_A     , FILE_A << 1, FILE_A << 2, FILE_A << 3, 
     FILE_A << 4, FILE_A << 5, FILE_A << 6, FILE_A << 7,
 ];
 
-const PASSED_PAWN_SCORE: [i16; 8] = [
-    0, 5, 10, 20, 35, 60, 100, 200
-];
-
-// const MOBILITY_MULT: i16 = 1;
-
-const ISOLATED_PAWN_PENALTY: i16 = -5;
-const DOUBLE_PAWN_PENALTY: i16 = -5;
-
-const ROOK_OPEN_FILE: i16 = 5; 
-const ROOK_SEMI_OPEN_FILE: i16 = 5;
-const QUEEN_OPEN_FILE: i16 = 3;
-const QUEEN_SEMI_OPEN_FILE: i16 = 2;
 
-const CASTLE_PERM_SCORE: i16 = 10;
+const ENDGAME_THRESHOLD: i16 
+    = MATERIAL_TABLE[PieceType::Pawn as usize] * 2
+    + MATERIAL_TABLE[PieceType::Knight as usize] * 2
+    + MATERIAL_TABLE[PieceType::Rook as usize];
 
-const BISHOP_PAIR: i16 = 30;
+const PHASE_WEIGHT: [i16; 6] = [ 0, 1, 1, 2, 4, 0 ];
+pub const PHASE_TOTAL: i16 = 24;
 
-const ENDGAME_THRESHOLD: i16 
-    = MATERIAL_TABLE[PAWNX] * 2
-    + MATERIAL_TABLE[KNIGHTX] * 2
-    + MATERIAL_TABLE[ROOKX] * 1;
+const SAFE_THRESHOLD: i16 = -MATERIAL_TABLE[PieceType::Pawn as usize] * 2;
 
-const SAFE_THRESHOLD: i16 = -MATERIAL_TABLE[PAWNX] * 2;
+/// Everything but pawns and kings, the pieces that make up the game phase.
+const PIECES: [PieceType; 4] = [PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen];
 
-fn north_one(x: u64) -> u64 { (x & !RANK[7]) << 8 }
-fn south_one(x: u64) -> u64 { (x & !RANK[0]) >> 8 }
-fn west_one(x: u64) -> u64 { (x & !FILE[0]) >> 1 }
-fn east_one(x: u64) -> u64 { (x & !FILE[7]) << 1 }
+pub(super) fn north_one(x: u64) -> u64 { (x & !RANK[7]) << 8 }
+pub(super) fn south_one(x: u64) -> u64 { (x & !RANK[0]) >> 8 }
+pub(super) fn west_one(x: u64) -> u64 { (x & !FILE[0]) >> 1 }
+pub(super) fn east_one(x: u64) -> u64 { (x & !FILE[7]) << 1 }
 
-fn north_fill(mut gen: u64) -> u64 {
+pub(super) fn north_fill(mut gen: u64) -> u64 {
     gen |= gen <<  8;
     gen |= gen << 16;
     gen |= gen << 32;
     gen
 }
 
-fn south_fill(mut gen: u64) -> u64 {
+pub(super) fn south_fill(mut gen: u64) -> u64 {
     gen |= gen >>  8;
     gen |= gen >> 16;
     gen |= gen >> 32;
     gen
 }
 
-fn file_fill(x: u64) -> u64 {
+pub(super) fn file_fill(x: u64) -> u64 {
     north_fill(x) | south_fill(x)
 }
 
@@ -136,9 +61,33 @@ fn east_attack_file_fill(x: u64) -> u64 { file_fill(east_one(x)) }
 fn no_neigh_on_west(x: u64) -> u64 { x & !west_attack_file_fill(x)}
 fn no_neigh_on_east(x: u64) -> u64 { x & !east_attack_file_fill(x)}
 
-fn isolanis(x: u64) -> u64 { no_neigh_on_west(x) & no_neigh_on_east(x) }
+pub(super) fn isolanis(x: u64) -> u64 { no_neigh_on_west(x) & no_neigh_on_east(x) }
+
+pub(super) fn pawn_attacks(pawns: u64, side: Color) -> u64 {
+    if side == Color::White {
+        (pawns & !FILE_A) << 7 | (pawns & !FILE_H) << 9
+    } else {
+        (pawns & !FILE_A) >> 9 | (pawns & !FILE_H) >> 7
+    }
+}
+
+/// Every square the pawns could attack by advancing.
+pub(super) fn pawn_attack_span(pawns: u64, side: Color) -> u64 {
+    let atk = pawn_attacks(pawns, side);
+    if side == Color::White { north_fill(atk) } else { south_fill(atk) }
+}
+
+pub(super) fn taper(v: [i16; 2], phase: i16) -> i16 {
+    ((v[0] as i32 * phase as i32 + v[1] as i32 * (PHASE_TOTAL - phase) as i32) 
+        / PHASE_TOTAL as i32) as i16
+}
+
+pub(super) fn distance(a: Square, b: Square) -> u8 {
+    let (ra, fa, rb, fb) = (a.rank() as i8, a.file() as i8, b.rank() as i8, b.file() as i8);
+    (ra - rb).unsigned_abs().max((fa - fb).unsigned_abs())
+}
 
-fn widen(gen: u64) -> u64 {
+pub(super) fn widen(gen: u64) -> u64 {
     gen | (gen & !FILE[0]) >> 1 | (gen & !FILE[7]) << 1
 }
 
@@ -150,126 +99,624 @@ fn bking_shield(k: u64) -> u64 {
     south_one(widen(k))
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    Pst,
+    Mobility,
+    Outposts,
+    BadBishop,
+    RookSeventh,
+    Trapped,
+    PassedPawns,
+    PasserKings,
+    Unstoppable,
+    DoubledPawns,
+    IsolatedPawns,
+    BackwardPawns,
+    ConnectedPawns,
+    CandidatePawns,
+    RookFiles,
+    QueenFiles,
+    KingLocation,
+    KingShield,
+    KingSafety,
+    Castling,
+    BishopPair,
+}
+
+pub const NUM_TERMS: usize = 22;
+
+impl Term {
+    pub const ALL: [Term; NUM_TERMS] = [
+        Term::Material, Term::Pst, Term::Mobility, Term::Outposts, Term::BadBishop,
+        Term::RookSeventh, Term::Trapped, Term::PassedPawns, Term::PasserKings,
+        Term::Unstoppable, Term::DoubledPawns, Term::IsolatedPawns, Term::BackwardPawns,
+        Term::ConnectedPawns, Term::CandidatePawns, Term::RookFiles, Term::QueenFiles,
+        Term::KingLocation, Term::KingShield, Term::KingSafety, Term::Castling,
+        Term::BishopPair,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Pst => "PST",
+            Term::Mobility => "Mobility",
+            Term::Outposts => "Outposts",
+            Term::BadBishop => "Bad bishops",
+            Term::RookSeventh => "Rooks on 7th",
+            Term::Trapped => "Trapped pieces",
+            Term::PassedPawns => "Passed pawns",
+            Term::PasserKings => "Passer kings",
+            Term::Unstoppable => "Unstoppable",
+            Term::DoubledPawns => "Doubled pawns",
+            Term::IsolatedPawns => "Isolated pawns",
+            Term::BackwardPawns => "Backward pawns",
+            Term::ConnectedPawns => "Connected pawns",
+            Term::CandidatePawns => "Candidates",
+            Term::RookFiles => "Rook files",
+            Term::QueenFiles => "Queen files",
+            Term::KingLocation => "King location",
+            Term::KingShield => "King shield",
+            Term::KingSafety => "King safety",
+            Term::Castling => "Castling rights",
+            Term::BishopPair => "Bishop pair",
+        }
+    }
+}
+
+/// Which evaluation produced a score.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Classical,
+    /// A variant's own evaluation, usually the classical terms plus a bonus.
+    Variant,
+    Nnue,
+}
+
+impl Backend {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Classical => "classical",
+            Backend::Variant => "variant",
+            Backend::Nnue => "nnue",
+        }
+    }
+}
+
+/// Receives every term `evaluate` adds up, from white's point of view per side.
+pub trait Tracer {
+    /// Whether the trace is recorded; caches are bypassed for active tracers.
+    const ACTIVE: bool = true;
+    fn add(&mut self, term: Term, side: Color, value: i16);
+    fn endgame(&mut self, _name: &'static str) {}
+    fn scale(&mut self, _scale: i16) {}
+    fn backend(&mut self, _backend: Backend) {}
+    /// What a variant adds to the classical score, for the side to move.
+    fn variant(&mut self, _bonus: i16) {}
+}
+
+pub struct NoTrace;
+
+impl Tracer for NoTrace {
+    const ACTIVE: bool = false;
+    #[inline(always)]
+    fn add(&mut self, _: Term, _: Color, _: i16) {}
+}
+
+pub struct EvalTrace {
+    pub terms: [[i16; 2]; NUM_TERMS],
+    pub endgame: Option<&'static str>,
+    pub scale: i16,
+    pub phase: i16,
+    pub backend: Backend,
+    pub variant: i16,
+    /// The classical score for the side to move, whichever backend gave `score`.
+    pub classical: i16,
+    pub score: i16,
+}
+
+impl EvalTrace {
+    pub fn new() -> Self {
+        Self { 
+            terms: [[0; 2]; NUM_TERMS], endgame: None, scale: SCALE_NORMAL, 
+            phase: 0, backend: Backend::Classical, variant: 0, classical: 0, score: 0,
+        }
+    }
+}
+
+impl Default for EvalTrace {
+    fn default() -> Self { Self::new() }
+}
+
+impl Tracer for EvalTrace {
+    fn add(&mut self, term: Term, side: Color, value: i16) {
+        self.terms[term as usize][side] += value;
+    }
+
+    fn endgame(&mut self, name: &'static str) {
+        self.endgame = Some(name);
+    }
+
+    fn scale(&mut self, scale: i16) {
+        self.scale = scale;
+    }
+
+    fn backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    fn variant(&mut self, bonus: i16) {
+        self.variant = bonus;
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>16} | {:>7} | {:>7} | {:>7}", "Term", "White", "Black", "Total")?;
+        writeln!(f, "{:-<16}-+-{:-<7}-+-{:-<7}-+-{:-<7}", "", "", "", "")?;
+        let mut total = [0; 2];
+        for t in Term::ALL.iter() {
+            let [w, b] = self.terms[*t as usize];
+            total[Color::White] += w;
+            total[Color::Black] += b;
+            writeln!(f, "{:>16} | {:>7} | {:>7} | {:>7}", t.name(), w, b, w - b)?;
+        }
+        writeln!(f, "{:-<16}-+-{:-<7}-+-{:-<7}-+-{:-<7}", "", "", "", "")?;
+        writeln!(f, "{:>16} | {:>7} | {:>7} | {:>7}", 
+            "Total", total[Color::White], total[Color::Black], total[Color::White] - total[Color::Black])?;
+        writeln!(f, "\nphase: {}/{}", self.phase, PHASE_TOTAL)?;
+        if let Some(name) = self.endgame {
+            writeln!(f, "endgame evaluator: {}", name)?;
+        } else {
+            writeln!(f, "scale factor: {}/{}", self.scale, SCALE_NORMAL)?;
+        }
+        if self.backend != Backend::Classical {
+            writeln!(f, "classical score (side to move): {}", self.classical)?;
+        }
+        if self.backend == Backend::Variant {
+            writeln!(f, "variant bonus (side to move): {}", self.variant)?;
+        }
+        write!(f, "score (side to move, {}): {}", self.backend.name(), self.score)
+    }
+}
 
 impl Position {
-    fn is_material_draw(&self) -> bool {
-        let wbs = self.pieces[WHITEX][BISHOPX].count_ones();
-        let bbs = self.pieces[BLACKX][BISHOPX].count_ones();
-        let wns = self.pieces[WHITEX][KNIGHTX].count_ones();
-        let bns = self.pieces[BLACKX][KNIGHTX].count_ones();
-
-        if (self.pieces[WHITEX][ROOKX] | self.pieces[WHITEX][QUEENX]
-            | self.pieces[BLACKX][ROOKX] | self.pieces[BLACKX][QUEENX] 
-            | self.pieces[WHITEX][PAWNX] | self.pieces[BLACKX][PAWNX]) == 0 {
+    /// Material the eval scores as a draw, such as two knights against a bare king.
+    /// Wider than `insufficient_material`, which only covers positions no moves can win.
+    pub fn is_material_draw(&self) -> bool {
+        let wbs = self.pieces[Color::White][PieceType::Bishop].count_ones();
+        let bbs = self.pieces[Color::Black][PieceType::Bishop].count_ones();
+        let wns = self.pieces[Color::White][PieceType::Knight].count_ones();
+        let bns = self.pieces[Color::Black][PieceType::Knight].count_ones();
+
+        if (self.pieces[Color::White][PieceType::Rook] | self.pieces[Color::White][PieceType::Queen]
+            | self.pieces[Color::Black][PieceType::Rook] | self.pieces[Color::Black][PieceType::Queen] 
+            | self.pieces[Color::White][PieceType::Pawn] | self.pieces[Color::Black][PieceType::Pawn]) != 0 {
             return false;
         }
         if wbs == 0 && bbs == 0 {
             if wns < 3 && bns < 3 { return true }
         } else if wns == 0 && bns == 0 {
             if (wbs as i32 - bbs as i32).abs() < 2 { return true }
-        } else if wns < 3 && wbs == 0 || wbs == 1 && wns == 0 {
-            if bns < 3 && bbs == 0 || bbs == 1 && bns == 0 { return true }
+        } else if (wns < 3 && wbs == 0 || wbs == 1 && wns == 0)
+            && (bns < 3 && bbs == 0 || bbs == 1 && bns == 0) { 
+            return true 
         }
         false
     }
 
-    pub fn eval(&self) -> i16 {
-        // let mat_white = self.material(WHITEX);
-        // let mat_black = self.material(BLACKX);
-        // if self.is_material_draw() { return 0 }
-        let mat_white = self.material[WHITEX];
-        let mat_black = self.material[BLACKX];
-        let mat_diff = mat_white - mat_black;
-        let max_mat = mat_white.max(mat_black);
+    /// Attack units against `us`'s king: weighted attackers of the king zone,
+    /// attacked zone squares, safe checks and open files around the king.
+    fn king_danger(&self, us: Color) -> i16 {
+        let p = &*self.params;
+        let them = !us;
+        let occupied = self.all_ocupied();
+        let ksq = self.king_square(us).expect("no king");
+        let mut zone = ATTK_TBL.king_attacks(ksq) | ksq.bb();
+        zone |= if us == Color::White { north_one(zone) } else { south_one(zone) };
+
+        let defended = self.attacked(us);
+        let safe = !defended & !self.occupied[them];
+        let bishop_checks = ATTK_TBL.bishop_attacks(ksq, occupied) & safe;
+        let rook_checks = ATTK_TBL.rook_attacks(ksq, occupied) & safe;
+        let knight_checks = ATTK_TBL.knight_attacks(ksq) & safe;
+
+        let (mut attackers, mut units) = (0, 0);
+        for pt in PIECES {
+            for sq in self.pieces[them][pt].bits() {
+                let atk = match pt {
+                    PieceType::Knight => ATTK_TBL.knight_attacks(sq),
+                    PieceType::Bishop => ATTK_TBL.bishop_attacks(sq, occupied),
+                    PieceType::Rook => ATTK_TBL.rook_attacks(sq, occupied),
+                    _ => ATTK_TBL.bishop_attacks(sq, occupied) 
+                        | ATTK_TBL.rook_attacks(sq, occupied),
+                };
+                let checks = atk & match pt {
+                    PieceType::Knight => knight_checks,
+                    PieceType::Bishop => bishop_checks,
+                    PieceType::Rook => rook_checks,
+                    _ => bishop_checks | rook_checks,
+                };
+                if atk & zone != 0 {
+                    attackers += 1;
+                    units += p.king_attack_weight[pt];
+                    units += p.king_zone_square * (atk & zone).count_ones() as i16;
+                }
+                units += p.safe_check_weight[pt] * checks.count_ones() as i16;
+            }
+        }
+
+        if self.pieces[them][PieceType::Rook] | self.pieces[them][PieceType::Queen] != 0 {
+            let kfile = FILE[ksq.file()];
+            let all_pawns = self.pieces[Color::White][PieceType::Pawn] | self.pieces[Color::Black][PieceType::Pawn];
+            for file in [west_one(kfile), kfile, east_one(kfile)].iter() {
+                if *file == 0 { continue; }
+                if file & all_pawns == 0 {
+                    units += p.king_open_file;
+                } else if file & self.pieces[us][PieceType::Pawn] == 0 {
+                    units += p.king_semi_open_file;
+                }
+            }
+        }
+
+        if attackers < 2 && self.pieces[them][PieceType::Queen] == 0 { return 0 }
+        p.safety_table[(units.max(0) as usize).min(p.safety_table.len() - 1)]
+    }
 
-        let mut score = mat_diff;
+    /// Tapered value of the pieces of `us`, kings aside.
+    fn material_value(&self, us: Color, phase: i16) -> i16 {
+        let p = &*self.params;
+        PieceType::ALL[..PieceType::King.index()].iter()
+            .map(|&pt| taper([p.material[0][pt.index()], p.material[1][pt.index()]], phase)
+                * self.pieces[us][pt].count_ones() as i16)
+            .sum()
+    }
 
-        for tp in PAWNX..QUEENX {
-            for sq in self.pieces[WHITEX][tp].bits() {
-                score += LOCATION_TABLE[tp as usize][sq as usize] as i16;
+    /// Mobility, outposts, bad bishops, rooks on the seventh and trapped pieces of `us`.
+    fn piece_activity<T: Tracer>(&self, us: Color, phase: i16, t: &mut T) -> i16 {
+        let p = &*self.params;
+        let them = !us;
+        let occupied = self.all_ocupied();
+        let our_pawns = self.pieces[us][PieceType::Pawn];
+        let their_pawns = self.pieces[them][PieceType::Pawn];
+        let mob_area = !self.occupied[us] & !pawn_attacks(their_pawns, them);
+        let rel = |sq: Square| sq.relative(us);
+
+        let mut mobility = 0;
+        for sq in self.pieces[us][PieceType::Knight].bits() {
+            let n = (ATTK_TBL.knight_attacks(sq) & mob_area).count_ones() as usize;
+            mobility += taper([p.knight_mobility[0][n], p.knight_mobility[1][n]], phase);
+        }
+        for sq in self.pieces[us][PieceType::Bishop].bits() {
+            let n = (ATTK_TBL.bishop_attacks(sq, occupied) & mob_area).count_ones() as usize;
+            mobility += taper([p.bishop_mobility[0][n], p.bishop_mobility[1][n]], phase);
+        }
+        let mut trapped = 0;
+        let ksq = self.king_square(us).expect("no king");
+        for sq in self.pieces[us][PieceType::Rook].bits() {
+            let n = (ATTK_TBL.rook_attacks(sq, occupied) & mob_area).count_ones() as usize;
+            mobility += taper([p.rook_mobility[0][n], p.rook_mobility[1][n]], phase);
+
+            //a rook boxed in by its own uncastled king
+            let (rsq, rk) = (rel(sq), rel(ksq));
+            if n <= 3 && rsq.rank() == Rank::R1 && rk.rank() == Rank::R1
+                && ((rk.file() >= File::E && rsq > rk) || (rk.file() < File::E && rsq < rk)) {
+                let mult = if self.cas.any(us) { 1 } else { 2 };
+                trapped += mult * taper(p.trapped_rook, phase);
             }
-            for sq in self.pieces[BLACKX][tp].bits() {
-                score -= LOCATION_TABLE[tp as usize][(sq ^ 56) as usize] as i16;
+        }
+        for sq in self.pieces[us][PieceType::Queen].bits() {
+            let atk = ATTK_TBL.bishop_attacks(sq, occupied) | ATTK_TBL.rook_attacks(sq, occupied);
+            let n = (atk & mob_area).count_ones() as usize;
+            mobility += taper([p.queen_mobility[0][n], p.queen_mobility[1][n]], phase);
+        }
+        t.add(Term::Mobility, us, mobility);
+
+        let outpost_ranks = if us == Color::White { RANK[3] | RANK[4] | RANK[5] } 
+            else { RANK[2] | RANK[3] | RANK[4] };
+        let outposts = outpost_ranks & pawn_attacks(our_pawns, us) 
+            & !pawn_attack_span(their_pawns, them);
+        let outpost = taper(p.knight_outpost, phase) 
+                * (self.pieces[us][PieceType::Knight] & outposts).count_ones() as i16
+            + taper(p.bishop_outpost, phase) 
+                * (self.pieces[us][PieceType::Bishop] & outposts).count_ones() as i16;
+        t.add(Term::Outposts, us, outpost);
+
+        let mut bad_bishop = 0;
+        for sq in self.pieces[us][PieceType::Bishop].bits() {
+            let color = if LIGHT_SQUARES.chk(sq) { LIGHT_SQUARES } else { !LIGHT_SQUARES };
+            bad_bishop += taper(p.bad_bishop_pawn, phase) * (our_pawns & color).count_ones() as i16;
+
+            //a bishop grabbing the a7/h7 pawn and getting cut off by b6/g6
+            let pawn_sq = match (rel(sq).file(), rel(sq).rank()) {
+                (File::A, Rank::R7) => Some(Square::new(File::B, Rank::R6)),
+                (File::H, Rank::R7) => Some(Square::new(File::G, Rank::R6)),
+                _ => None,
+            };
+            if pawn_sq.is_some_and(|psq| their_pawns.chk(rel(psq))) {
+                trapped += taper(p.trapped_bishop, phase);
             }
         }
+        t.add(Term::BadBishop, us, bad_bishop);
 
-        // score += MOBILITY_MULT * self.mobility::<WHITEX>() as i16;
-        // score -= MOBILITY_MULT * self.mobility::<BLACKX>() as i16;
+        let (seventh, eighth) = if us == Color::White { (RANK[6], RANK[7]) } else { (RANK[1], RANK[0]) };
+        let mut seventh_bonus = 0;
+        if their_pawns & seventh != 0 || self.pieces[them][PieceType::King] & eighth != 0 {
+            seventh_bonus = taper(p.rook_on_seventh, phase) 
+                * (self.pieces[us][PieceType::Rook] & seventh).count_ones() as i16;
+        }
+        t.add(Term::RookSeventh, us, seventh_bonus);
+        t.add(Term::Trapped, us, trapped);
 
-        //pawns
-        let (wps, bps) = (self.pieces[WHITEX][PAWNX], self.pieces[BLACKX][PAWNX]);
-        let wfront = north_fill(north_one(wps));
-        let bfront = south_fill(south_one(bps));
+        mobility + outpost + bad_bishop + seventh_bonus + trapped
+    }
+
+    /// King proximity of the passers and the rule of the square against a bare king.
+    fn passers<T: Tracer>(&self, us: Color, passed: BitBoard, phase: i16, t: &mut T) -> i16 {
+        let p = &*self.params;
+        let them = !us;
+        let occupied = self.all_ocupied();
+        let our_king = self.king_square(us).expect("no king");
+        let their_king = self.king_square(them).expect("no king");
+        let pawns_only = self.occupied[them] 
+            & !self.pieces[them][PieceType::Pawn] & !self.pieces[them][PieceType::King] == 0;
+
+        let (mut kings, mut unstoppable) = (0, 0);
+        for sq in passed.bits() {
+            let rank = sq.rank().relative(us) as u8;
+            let stop = sq.forward(us, 1);
+            let promo = Square::new(sq.file(), Rank::R8.relative(us));
+            let w = p.passer_king_weight[rank as usize];
+            let v = w * (p.passer_their_king * distance(their_king, stop) as i16 
+                - p.passer_our_king * distance(our_king, stop) as i16);
+            kings += taper([0, v], phase);
+
+            let path = if us == Color::White { north_fill(north_one(sq.bb())) } 
+                else { south_fill(south_one(sq.bb())) };
+            if pawns_only && path & occupied == 0 {
+                let to_go = if rank == 1 { 5 } else { 7 - rank };
+                let tempo = (self.turn == them) as u8;
+                if distance(their_king, promo) - tempo > to_go {
+                    unstoppable += p.unstoppable_passer;
+                }
+            }
+        }
+        t.add(Term::PasserKings, us, kings);
+        t.add(Term::Unstoppable, us, unstoppable);
+        kings + unstoppable
+    }
+
+    pub fn eval(&self) -> i16 {
+        self.eval_cached(None)
+    }
+
+    /// `eval`, looking pawn structures up in `pawns` when given.
+    pub fn eval_cached(&self, pawns: Option<&PawnTable>) -> i16 {
+        self.eval_with(&mut NoTrace, pawns)
+    }
 
-        for wpasser in (wps & !widen(bfront)).bits() {
-            let rank = wpasser / 8;
-            score += PASSED_PAWN_SCORE[rank as usize];
+    /// The score `eval` returns, with the terms behind it.
+    pub fn eval_trace(&self) -> EvalTrace {
+        let mut t = EvalTrace::new();
+        t.score = self.eval_with(&mut t, None);
+        t.classical = match t.backend {
+            Backend::Classical => t.score,
+            Backend::Variant => t.score - t.variant,
+            //the network has no terms, so the classical ones are shown next to it
+            Backend::Nnue => self.evaluate(&mut t),
+        };
+        t.phase = self.phase();
+        t
+    }
+
+    /// Picks the backend for this position: the variant's evaluation, the network
+    /// when one is loaded, or the classical terms.
+    fn eval_with<T: Tracer>(&self, t: &mut T, pawns: Option<&PawnTable>) -> i16 {
+        if self.variant != Variant::Standard { return self.variant_eval(t, pawns) }
+        #[cfg(feature = "nnue")]
+        if let Some(score) = self.nnue_eval() {
+            t.backend(Backend::Nnue);
+            return score
         }
-        for bpasser in (bps & !widen(wfront)).bits() {
-            let rank = 7 - bpasser / 8;
-            score -= PASSED_PAWN_SCORE[rank as usize];
+        self.evaluate_cached(t, pawns)
+    }
+
+    /// Swaps the evaluation weights; a pawn cache filled with the old ones must be cleared.
+    pub fn set_params(&mut self, params: Arc<EvalParams>) {
+        self.params = params;
+    }
+
+    /// Game phase from the remaining non-pawn material, `PHASE_TOTAL` at the start.
+    pub fn phase(&self) -> i16 {
+        let mut phase = 0;
+        for c in Color::ALL {
+            for pt in PIECES {
+                phase += PHASE_WEIGHT[pt] * self.pieces[c][pt].count_ones() as i16;
+            }
         }
+        phase.min(PHASE_TOTAL)
+    }
 
-        score += DOUBLE_PAWN_PENALTY * (wfront & wps).count_ones() as i16;
-        score -= DOUBLE_PAWN_PENALTY * (bfront & bps).count_ones() as i16;
+    pub fn evaluate<T: Tracer>(&self, t: &mut T) -> i16 {
+        self.evaluate_cached(t, None)
+    }
+
+    pub fn evaluate_cached<T: Tracer>(&self, t: &mut T, pawns: Option<&PawnTable>) -> i16 {
+        let p = &*self.params;
+        if let Some((score, name)) = self.endgame_eval() {
+            t.endgame(name);
+            return if self.turn == Color::Black { -score } else { score };
+        }
+        let mat_white = self.material[Color::White];
+        let mat_black = self.material[Color::Black];
+        let max_mat = mat_white.max(mat_black);
+        let phase = self.phase();
+
+        let (w, b) = (self.material_value(Color::White, phase), self.material_value(Color::Black, phase));
+        let mut score = w - b;
+        t.add(Term::Material, Color::White, w);
+        t.add(Term::Material, Color::Black, b);
+
+        for &tp in &PieceType::ALL[..PieceType::Queen.index()] {
+            for sq in self.pieces[Color::White][tp].bits() {
+                let v = p.pst[tp.index()][sq];
+                score += v;
+                t.add(Term::Pst, Color::White, v);
+            }
+            for sq in self.pieces[Color::Black][tp].bits() {
+                let v = p.pst[tp.index()][sq.flip()];
+                score -= v;
+                t.add(Term::Pst, Color::Black, v);
+            }
+        }
 
-        score += ISOLATED_PAWN_PENALTY * isolanis(wps).count_ones() as i16;
-        score -= ISOLATED_PAWN_PENALTY * isolanis(bps).count_ones() as i16;
+        score += self.piece_activity(Color::White, phase, t);
+        score -= self.piece_activity(Color::Black, phase, t);
+
+        //pawns
+        let (wps, bps) = (self.pieces[Color::White][PieceType::Pawn], self.pieces[Color::Black][PieceType::Pawn]);
+        let pe = match pawns {
+            Some(tbl) if !T::ACTIVE => self.pawn_entry(tbl),
+            _ => self.pawn_structure(t),
+        };
+        score += pe.score[Color::White] - pe.score[Color::Black];
+        score += self.passers(Color::White, pe.passed[Color::White], phase, t);
+        score -= self.passers(Color::Black, pe.passed[Color::Black], phase, t);
 
         //rooks
         let occupied = self.all_ocupied();
-        let wrks = self.pieces[WHITEX][ROOKX];
-        let brks = self.pieces[BLACKX][ROOKX];
+        let wrks = self.pieces[Color::White][PieceType::Rook];
+        let brks = self.pieces[Color::Black][PieceType::Rook];
         let wocc_files = file_fill(occupied & !wrks);
         let bocc_files = file_fill(occupied & !brks);
 
         let wof = (!wocc_files & wrks).count_ones();
         let bof = (!bocc_files & brks).count_ones();
 
-        score += ROOK_OPEN_FILE * wof as i16;
-        score += ROOK_SEMI_OPEN_FILE * (!file_fill(wps) & wrks).count_ones() as i16;
-        score -= ROOK_OPEN_FILE * bof as i16;
-        score -= ROOK_SEMI_OPEN_FILE * (!file_fill(bps) & brks).count_ones() as i16;
+        let w = p.rook_open_file * wof as i16
+            + p.rook_semi_open_file * (!file_fill(wps) & wrks).count_ones() as i16;
+        let b = p.rook_open_file * bof as i16
+            + p.rook_semi_open_file * (!file_fill(bps) & brks).count_ones() as i16;
+        score += w - b;
+        t.add(Term::RookFiles, Color::White, w);
+        t.add(Term::RookFiles, Color::Black, b);
 
         //queens
-        let wqs = self.pieces[WHITEX][QUEENX];
-        let bqs = self.pieces[BLACKX][QUEENX];
-
-        score += QUEEN_OPEN_FILE * (!file_fill(occupied & !wqs) & wqs).count_ones() as i16;
-        score += QUEEN_SEMI_OPEN_FILE * (!file_fill(wps) & wqs).count_ones() as i16;
-        score -= QUEEN_OPEN_FILE * (!file_fill(occupied & !bqs) & bqs).count_ones() as i16;
-        score -= QUEEN_SEMI_OPEN_FILE * (!file_fill(bps) & bqs).count_ones() as i16;
-        
+        let wqs = self.pieces[Color::White][PieceType::Queen];
+        let bqs = self.pieces[Color::Black][PieceType::Queen];
+
+        let w = p.queen_open_file * (!file_fill(occupied & !wqs) & wqs).count_ones() as i16
+            + p.queen_semi_open_file * (!file_fill(wps) & wqs).count_ones() as i16;
+        let b = p.queen_open_file * (!file_fill(occupied & !bqs) & bqs).count_ones() as i16
+            + p.queen_semi_open_file * (!file_fill(bps) & bqs).count_ones() as i16;
+        score += w - b;
+        t.add(Term::QueenFiles, Color::White, w);
+        t.add(Term::QueenFiles, Color::Black, b);
         
         let wking_tactic = ((mat_white - mat_black >= SAFE_THRESHOLD) 
             && self.is_endgame()) as usize;
         let bking_tactic = ((mat_black - mat_white >= SAFE_THRESHOLD) 
             && self.is_endgame()) as usize;
 
-        let wk = self.pieces[WHITEX][KINGX].trailing_zeros() as usize;
-        let bk = self.pieces[BLACKX][KINGX].trailing_zeros() as usize;
-        score += KING_LOCATION[wking_tactic][wk] as i16;
-        score -= KING_LOCATION[bking_tactic][bk ^ 56] as i16;
-
-       if max_mat > ENDGAME_THRESHOLD {
-            score += CASTLE_PERM_SCORE * self.cas.any(WHITE) as i16;
-            score -= CASTLE_PERM_SCORE * self.cas.any(BLACK) as i16;
-
-            let wkmask = 1<<wk;
-            let bkmask = 1<<bk;
-
-            score += KING_SHIELDED * (wking_shield(wkmask) & wps).count_ones() as i16;
-            score -= KING_SHIELDED * (bking_shield(bkmask) & bps).count_ones() as i16;
+        let wk = self.king_square(Color::White).expect("no king");
+        let bk = self.king_square(Color::Black).expect("no king");
+        let (w, b) = (
+            p.king_location[wking_tactic][wk],
+            p.king_location[bking_tactic][bk.flip()],
+        );
+        score += w - b;
+        t.add(Term::KingLocation, Color::White, w);
+        t.add(Term::KingLocation, Color::Black, b);
+
+        if max_mat > ENDGAME_THRESHOLD {
+            let (w, b) = (
+                p.castle_rights * self.cas.any(Color::White) as i16,
+                p.castle_rights * self.cas.any(Color::Black) as i16,
+            );
+            score += w - b;
+            t.add(Term::Castling, Color::White, w);
+            t.add(Term::Castling, Color::Black, b);
+
+            let wkmask = wk.bb();
+            let bkmask = bk.bb();
+
+            let (w, b) = (
+                p.king_shielded * (wking_shield(wkmask) & wps).count_ones() as i16,
+                p.king_shielded * (bking_shield(bkmask) & bps).count_ones() as i16,
+            );
+            score += w - b;
+            t.add(Term::KingShield, Color::White, w);
+            t.add(Term::KingShield, Color::Black, b);
         }
 
-        score += BISHOP_PAIR * (self.pieces[WHITEX][BISHOPX].count_ones() >= 2) as i16;
-        score -= BISHOP_PAIR * (self.pieces[BLACKX][BISHOPX].count_ones() >= 2) as i16;
-
-        if self.turn == BLACK { score *= -1; }
+        let (w, b) = (
+            taper([-self.king_danger(Color::White), 0], phase),
+            taper([-self.king_danger(Color::Black), 0], phase),
+        );
+        score += w - b;
+        t.add(Term::KingSafety, Color::White, w);
+        t.add(Term::KingSafety, Color::Black, b);
+
+        let (w, b) = (
+            p.bishop_pair * (self.pieces[Color::White][PieceType::Bishop].count_ones() >= 2) as i16,
+            p.bishop_pair * (self.pieces[Color::Black][PieceType::Bishop].count_ones() >= 2) as i16,
+        );
+        score += w - b;
+        t.add(Term::BishopPair, Color::White, w);
+        t.add(Term::BishopPair, Color::Black, b);
+
+        let strong = if score > 0 { Color::White } else { Color::Black };
+        let scale = self.scale_factor(strong);
+        t.scale(scale);
+        score = (score as i32 * scale as i32 / SCALE_NORMAL as i32) as i16;
+
+        if self.turn == Color::Black { score *= -1; }
         score
     }
 
     pub fn is_endgame(&self) -> bool {
-        self.material[WHITEX].min(self.material[BLACKX]) < ENDGAME_THRESHOLD
+        self.material[Color::White].min(self.material[Color::Black]) < ENDGAME_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::perft::POSITIONS;
+
+    #[test]
+    fn trace_matches_eval() {
+        let mut pos = Position::new();
+        for fen in POSITIONS.iter() {
+            pos.load_fen(fen);
+            let t = pos.eval_trace();
+            let mut total = 0;
+            for term in t.terms.iter() {
+                total += term[Color::White] - term[Color::Black];
+            }
+            total = (total as i32 * t.scale as i32 / SCALE_NORMAL as i32) as i16;
+            if pos.turn == Color::Black { total = -total; }
+            assert_eq!(t.score, pos.eval());
+            assert_eq!(total, t.score);
+        }
+    }
+
+    #[test]
+    fn trace_adds_variant_bonus() {
+        let mut pos = Position::new();
+        for (variant, fen) in [
+            (Variant::ThreeCheck, "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 +2+0"),
+            (Variant::KingOfTheHill, "8/8/8/3K4/8/8/8/k7 w - - 0 1"),
+            (Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4K3[Qn] b - - 0 1"),
+        ] {
+            pos.variant = variant;
+            pos.load_fen(fen);
+            let t = pos.eval_trace();
+            assert_eq!((t.backend, t.score), (Backend::Variant, pos.eval()));
+            assert!(t.variant != 0, "{}", fen);
+            assert_eq!(t.classical + t.variant, t.score);
+            assert_eq!(t.classical, pos.evaluate(&mut NoTrace));
+        }
     }
 }
@@ -1,12 +1,88 @@
 use super::bitbrd::*;
-use bitintr::Pdep;
 use super::magics::*;
+use super::types::Square;
+use std::{fmt, str::FromStr, sync::atomic::{AtomicBool, Ordering}};
 
+const SLIDER_TABLE_SIZE: usize = 107648;
+
+/// How slider attacks are looked up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliderBackend {
+    Magic,
+    /// BMI2 `pext` indexing, only available if the CPU supports it.
+    Pext,
+}
+
+impl SliderBackend {
+    pub const ALL: [SliderBackend; 2] = [SliderBackend::Magic, SliderBackend::Pext];
+}
+
+impl fmt::Display for SliderBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self { SliderBackend::Magic => "magic", SliderBackend::Pext => "pext" })
+    }
+}
+
+impl FromStr for SliderBackend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "magic" => Ok(SliderBackend::Magic),
+            "pext" => Ok(SliderBackend::Pext),
+            _ => Err(format!("unknown slider backend {}", s)),
+        }
+    }
+}
+
+pub fn pext_supported() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    { is_x86_feature_detected!("bmi2") }
+    #[cfg(not(target_arch = "x86_64"))]
+    { false }
+}
+
+//inline asm rather than a #[target_feature] fn, which could not be inlined into the lookups;
+//only reached once pext_supported() said yes
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn pext(a: u64, mask: u64) -> u64 {
+    let res;
+    unsafe {
+        std::arch::asm!("pext {}, {}, {}", lateout(reg) res, in(reg) a, in(reg) mask,
+                        options(pure, nomem, nostack));
+    }
+    res
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn pext(_: u64, _: u64) -> u64 { unreachable!() }
+
+/// Software `pext`, used to fill the pext table.
+fn soft_pext(a: u64, mut mask: u64) -> u64 {
+    let (mut res, mut bit) = (0, 1);
+    while mask != 0 {
+        if a & mask & mask.wrapping_neg() != 0 { res |= bit; }
+        mask &= mask - 1;
+        bit <<= 1;
+    }
+    res
+}
+
+fn slider_mask(sq: usize, attacks: fn(usize, BitBoard) -> BitBoard) -> u64 {
+    const RANKS: u64 = 0xff00_0000_0000_00ff;
+    const FILES: u64 = 0x8181_8181_8181_8181;
+    let (rank, file) = (0xffu64 << (sq / 8 * 8), 0x0101_0101_0101_0101u64 << (sq % 8));
+    attacks(sq, 0) & !(RANKS & !rank) & !(FILES & !file)
+}
 
 pub struct AttackTable {
     knights: [BitBoard; 64],
     kings: [BitBoard; 64],
-    attacks: [BitBoard; 88772],
+    rooks: [Magic; 64],
+    bishops: [Magic; 64],
+    magic_attacks: Vec<BitBoard>,
+    pext_attacks: Vec<BitBoard>,
+    use_pext: AtomicBool,
 }
 
 fn calc_rook_attacks(sq: usize, blockers: BitBoard) -> BitBoard {
@@ -14,20 +90,20 @@ fn calc_rook_attacks(sq: usize, blockers: BitBoard) -> BitBoard {
     let (rank, file) = ((sq as u8)/8, (sq as u8)%8);
 
     for r in rank+1..8 {
-        atks.set(r*8+file);
-        if blockers.chk(r*8+file) { break; }
+        atks |= 1 << (r*8+file);
+        if blockers & 1 << (r*8+file) != 0 { break; }
     }
     for r in (0..rank).rev() {
-        atks.set(r*8+file);
-        if blockers.chk(r*8+file) { break; }
+        atks |= 1 << (r*8+file);
+        if blockers & 1 << (r*8+file) != 0 { break; }
     }
     for f in file+1..8 {
-        atks.set(rank*8+f);
-        if blockers.chk(rank*8+f) { break; }
+        atks |= 1 << (rank*8+f);
+        if blockers & 1 << (rank*8+f) != 0 { break; }
     }
     for f in (0..file).rev() {
-        atks.set(rank*8+f);
-        if blockers.chk(rank*8+f) { break; }
+        atks |= 1 << (rank*8+f);
+        if blockers & 1 << (rank*8+f) != 0 { break; }
     }
 
     atks
@@ -38,29 +114,29 @@ fn calc_bishop_attacks(sq: usize, blockers: BitBoard) -> BitBoard {
     let (rank, file) = (sq / 8, sq % 8);
     let (mut r, mut f) = (rank as i8 + 1, file as i8 + 1);
     while r < 8 && f < 8  {
-        atks.set((r * 8 + f) as u8);
-        if blockers.chk((r*8+f) as u8) { break }
+        atks |= 1 << ((r * 8 + f) as u8);
+        if blockers & 1 << ((r*8+f) as u8) != 0 { break }
         r += 1;
         f += 1;
     }
     let (mut r, mut f) = (rank as i8 - 1, file as i8 - 1);
     while r >= 0 && f >= 0 {
-        atks.set((r * 8 + f) as u8);
-        if blockers.chk((r*8+f) as u8) { break }
+        atks |= 1 << ((r * 8 + f) as u8);
+        if blockers & 1 << ((r*8+f) as u8) != 0 { break }
         r -= 1;
         f -= 1;
     }
     let (mut r, mut f) = (rank as i8 + 1, file as i8 - 1);
     while r < 8 && f >= 0 {
-        atks.set((r * 8 + f) as u8);
-        if blockers.chk((r*8+f) as u8) { break }
+        atks |= 1 << ((r * 8 + f) as u8);
+        if blockers & 1 << ((r*8+f) as u8) != 0 { break }
         r += 1;
         f -= 1;
     }
     let (mut r, mut f) = (rank as i8 - 1, file as i8 + 1);
     while r >= 0 && f < 8 {
-        atks.set((r * 8 + f) as u8);
-        if blockers.chk((r*8+f) as u8) { break }
+        atks |= 1 << ((r * 8 + f) as u8);
+        if blockers & 1 << ((r*8+f) as u8) != 0 { break }
         r -= 1;
         f += 1;
     }
@@ -82,7 +158,7 @@ impl AttackTable {
                 for m in possibles.iter() {
                     if m.0 < 0 || m.0 > 7 || m.1 < 0 || m.1 > 7 { continue; }
                     let to = (m.0 * 8 + m.1) as u8;
-                    self.knights[from].set(to);
+                    self.knights[from] |= 1 << to;
                 }
             }
         }
@@ -101,7 +177,7 @@ impl AttackTable {
                 for m in possibles.iter() {
                     if m.0 < 0 || m.0 > 7 || m.1 < 0 || m.1 > 7 { continue; }
                     let to = (m.0 * 8 + m.1) as u8;
-                    self.kings[from].set(to);
+                    self.kings[from] |= 1 << to;
                 }
             }
         }
@@ -109,67 +185,105 @@ impl AttackTable {
 
 
 
-    fn init_rooks(&mut self) {
-        for sq in 0..64 {
-            let m = &ROOK_MAGICS[sq];
-            for blocker_idx in 0..1<<12 {
-                let blockers = blocker_idx.pdep(m.mask);
-                let attack = calc_rook_attacks(sq, blockers);
-                let idx = (m.factor.wrapping_mul(blockers) >> (64-12)) as usize + m.offset;
-                assert!(self.attacks[idx] == 0 || self.attacks[idx] == attack);
-                self.attacks[idx] = attack;
+    fn init_sliders(&mut self, magics: [Magic; 64], attacks: fn(usize, BitBoard) -> BitBoard) {
+        for (sq, m) in magics.iter().enumerate() {
+            for blockers in subsets(m.mask) {
+                let atk = attacks(sq, blockers);
+                self.magic_attacks[m.offset + m.index(blockers)] = atk;
+                if !self.pext_attacks.is_empty() {
+                    self.pext_attacks[m.offset + soft_pext(blockers, m.mask) as usize] = atk;
+                }
             }
         }
     }
 
-    fn init_bishops(&mut self) {
+    /// Builds the tables, finding magics at startup, and picks pext when the CPU has BMI2.
+    pub fn new() -> Box<Self> {
+        let pext = pext_supported();
+        let mut inst = Box::new(AttackTable {
+            knights: [0; 64],
+            kings: [0; 64],
+            rooks: [Magic::default(); 64],
+            bishops: [Magic::default(); 64],
+            magic_attacks: vec![0; SLIDER_TABLE_SIZE],
+            pext_attacks: if pext { vec![0; SLIDER_TABLE_SIZE] } else { Vec::new() },
+            use_pext: AtomicBool::new(pext),
+        });
+        let mut rook_masks = [0; 64];
+        let mut bishop_masks = [0; 64];
         for sq in 0..64 {
-            let m = &BISHOP_MAGICS[sq];
-            for blocker_idx in 0..1<<9 {
-                let blockers = blocker_idx.pdep(m.mask);
-                let attack = calc_bishop_attacks(sq, blockers);
-                let idx = (m.factor.wrapping_mul(blockers) >> (64-9)) as usize + m.offset;
-                assert!(self.attacks[idx] == 0 || self.attacks[idx] == attack);
-                self.attacks[idx] = attack;
-            }
+            rook_masks[sq] = slider_mask(sq, calc_rook_attacks);
+            bishop_masks[sq] = slider_mask(sq, calc_bishop_attacks);
         }
-    }
+        let mut rng = magic_rng();
+        inst.rooks = find_magics(&rook_masks, calc_rook_attacks, &mut rng, 0);
+        let end = inst.rooks[63].offset + inst.rooks[63].size();
+        inst.bishops = find_magics(&bishop_masks, calc_bishop_attacks, &mut rng, end);
+        assert_eq!(inst.bishops[63].offset + inst.bishops[63].size(), SLIDER_TABLE_SIZE);
 
-    pub fn new() -> Box<Self> {
-        use std::alloc::{alloc, Layout};
-        let layout = Layout::new::<AttackTable>();
-        let mut inst = unsafe {
-            let ptr = alloc(layout);
-            std::ptr::write_bytes(ptr, 0, layout.size());
-            Box::from_raw(ptr as *mut AttackTable)
-        };
-        inst.init_rooks();
-        inst.init_bishops();
+        inst.init_sliders(inst.rooks, calc_rook_attacks);
+        inst.init_sliders(inst.bishops, calc_bishop_attacks);
         inst.init_knights();
         inst.init_kings();
 
         inst
     }
 
-    pub fn rook_attacks(&self, sq: u8, blockers: BitBoard) -> BitBoard {
-        let sq = sq as usize;
-        let m = &ROOK_MAGICS[sq];
-        let idx = (m.factor.wrapping_mul(blockers & m.mask) >> (64-12)) as usize + m.offset;
-        self.attacks[idx]
+    pub fn backend(&self) -> SliderBackend {
+        if self.use_pext.load(Ordering::Relaxed) { SliderBackend::Pext } else { SliderBackend::Magic }
     }
 
-    pub fn bishop_attacks(&self, sq: u8, blockers: BitBoard) -> BitBoard {
-        let sq = sq as usize;
-        let m = &BISHOP_MAGICS[sq];
-        let idx = (m.factor.wrapping_mul(blockers & m.mask) >> (64-9)) as usize + m.offset;
-        self.attacks[idx]
+    /// Switches the slider lookup, returning false if the backend is unavailable here.
+    pub fn set_backend(&self, backend: SliderBackend) -> bool {
+        if backend == SliderBackend::Pext && self.pext_attacks.is_empty() { return false }
+        self.use_pext.store(backend == SliderBackend::Pext, Ordering::Relaxed);
+        true
     }
 
-    pub fn knight_attacks(&self, sq: u8) -> BitBoard { self.knights[sq as usize] }
-    pub fn king_attacks(&self, sq: u8) -> BitBoard { self.kings[sq as usize] }
+    #[inline(always)]
+    fn slider_attacks(&self, m: &Magic, blockers: BitBoard) -> BitBoard {
+        if self.use_pext.load(Ordering::Relaxed) {
+            self.pext_attacks[m.offset + pext(blockers, m.mask) as usize]
+        } else {
+            self.magic_attacks[m.offset + m.index(blockers)]
+        }
+    }
+
+    pub fn rook_attacks(&self, sq: Square, blockers: BitBoard) -> BitBoard {
+        self.slider_attacks(&self.rooks[sq], blockers)
+    }
+
+    pub fn bishop_attacks(&self, sq: Square, blockers: BitBoard) -> BitBoard {
+        self.slider_attacks(&self.bishops[sq], blockers)
+    }
+
+    pub fn knight_attacks(&self, sq: Square) -> BitBoard { self.knights[sq] }
+    pub fn king_attacks(&self, sq: Square) -> BitBoard { self.kings[sq] }
 }
 
-// #[test]
-// fn create_magics() {
-    // AttackTable::new();
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_agree() {
+        let tbl = AttackTable::new();
+        for backend in SliderBackend::ALL.iter().copied() {
+            if !tbl.set_backend(backend) { continue }
+            for sq in Square::all() {
+                let (r, b) = (&tbl.rooks[sq], &tbl.bishops[sq]);
+                //bits outside the mask must not change the lookup
+                let noise = 0x1234_5678_9abc_def0;
+                for occ in subsets(r.mask) {
+                    let got = tbl.rook_attacks(sq, occ | noise & !r.mask);
+                    assert_eq!(got, calc_rook_attacks(sq.index(), occ), "{} sq {}", backend, sq);
+                }
+                for occ in subsets(b.mask) {
+                    let got = tbl.bishop_attacks(sq, occ | noise & !b.mask);
+                    assert_eq!(got, calc_bishop_attacks(sq.index(), occ), "{} sq {}", backend, sq);
+                }
+            }
+        }
+        assert_eq!(soft_pext(0b1011_0110, 0b1111_0000), 0b1011);
+    }
+}
@@ -0,0 +1,645 @@
+use super::{defs::*, movgen::*, pos::*, search::*, uci::START_FEN};
+use super::types::Color;
+use super::{endgame::KNOWN_WIN, params::EvalParams, pvtable::MAX_DEPTH, skill::Skill};
+use super::weights::DEFAULT_PARAMS;
+#[cfg(feature = "nnue")]
+use super::nnue::Network;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU64, Ordering}};
+use std::time::{Duration, Instant};
+
+const MAX_GAME_PLIES: usize = 1000;
+/// How far an engine's score must favour the side the endgame evaluator calls winning.
+const MATERIAL_AGREE_SCORE: i16 = 400;
+
+/// How an engine taking part in the match is started: `self[:key=value,...]` for this
+/// engine with its own hash, eval params, network or strength (`skill=0..19` or `elo=N`),
+/// anything else is the path of a UCI binary.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineSpec {
+    Internal { hash_mb: usize, params: Option<String>, network: Option<String>, skill: Option<Skill> },
+    External { path: String },
+}
+
+impl EngineSpec {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let opts = match s.strip_prefix("self") {
+            Some(rest) => rest.strip_prefix(':').unwrap_or(rest),
+            None => return Ok(Self::External { path: s.to_owned() }),
+        };
+        let (mut hash_mb, mut params, mut network, mut skill) = (16, None, None, None);
+        for opt in opts.split(',').filter(|o| !o.is_empty()) {
+            let (key, val) = opt.split_once('=').ok_or(format!("bad engine option {}", opt))?;
+            match key {
+                "hash" => hash_mb = val.parse().map_err(|_| format!("bad hash size {}", val))?,
+                "params" => params = Some(val.to_owned()),
+                "nnue" => network = Some(val.to_owned()),
+                "skill" => skill = Skill::from_level(val.parse().map_err(|_| format!("bad skill level {}", val))?),
+                "elo" => skill = Skill::from_elo(val.parse().map_err(|_| format!("bad elo {}", val))?),
+                _ => return Err(format!("unknown engine option {}", key)),
+            }
+        }
+        Ok(Self::Internal { hash_mb, params, network, skill })
+    }
+
+    pub fn start(&self) -> io::Result<Box<dyn Player>> {
+        Ok(match self {
+            Self::Internal { hash_mb, params, network, skill } => {
+                let mut engine = Internal::new(*hash_mb, params.as_deref(), network.as_deref())?;
+                engine.skill = *skill;
+                Box::new(engine)
+            }
+            Self::External { path } => Box::new(External::start(path)?),
+        })
+    }
+}
+
+/// Clocks in milliseconds and optional fixed node or depth limits.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub time: [u64; 2],
+    pub inc: [u64; 2],
+    pub nodes: Option<u64>,
+    pub depth: Option<u8>,
+}
+
+pub trait Player: Send {
+    fn new_game(&mut self) -> io::Result<()>;
+    /// Returns the chosen move in UCI notation and the last score reported for it,
+    /// from the side to move.
+    fn go(&mut self, fen: &str, moves: &[String], limits: &Limits) -> io::Result<(String, Option<i16>)>;
+}
+
+struct Internal {
+    searcher: Searcher,
+    skill: Option<Skill>,
+}
+
+impl Internal {
+    fn new(hash_mb: usize, params: Option<&str>, network: Option<&str>) -> io::Result<Self> {
+        let mut searcher = Searcher::with_hash_mb(Position::new(), hash_mb);
+        if let Some(path) = params {
+            let mut p: EvalParams = DEFAULT_PARAMS;
+            p.load(path)?;
+            searcher.set_params(Arc::new(p));
+        }
+        #[cfg(feature = "nnue")]
+        if let Some(path) = network {
+            searcher.pos.set_network(Some(Arc::new(Network::load(path)?)));
+        }
+        #[cfg(not(feature = "nnue"))]
+        if network.is_some() {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "built without nnue support"))
+        }
+        Ok(Self { searcher, skill: None })
+    }
+}
+
+impl Player for Internal {
+    fn new_game(&mut self) -> io::Result<()> {
+        self.searcher.new_game();
+        Ok(())
+    }
+
+    fn go(&mut self, fen: &str, moves: &[String], limits: &Limits) -> io::Result<(String, Option<i16>)> {
+        self.searcher.pos.load_fen(fen);
+        for m in moves {
+            let m = Move::from_uci(&self.searcher.pos, m)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.searcher.pos.make_move(m);
+        }
+        let us = self.searcher.pos.turn;
+        let move_time = match (limits.nodes, limits.depth) {
+            (None, None) => {
+                let (time, inc) = (limits.time[us], limits.inc[us]);
+                let budget = (time / 25 + inc * 3 / 4).min(time.saturating_sub(50)).max(1);
+                Some(Duration::from_millis(budget))
+            }
+            _ => None,
+        };
+        let depth = limits.depth.unwrap_or(MAX_DEPTH as u8);
+        let mut info = SearchInfo::new(depth, move_time).quiet();
+        if let Some(nodes) = limits.nodes {
+            info = info.with_nodes(nodes);
+        }
+        if let Some(skill) = self.skill {
+            info = info.with_skill(skill);
+        }
+        self.searcher.search(&mut info);
+        Ok((info.best_move.to_string(), Some(info.best_score)))
+    }
+}
+
+/// A UCI engine running as a child process.
+struct External {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl External {
+    fn start(path: &str) -> io::Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut e = Self { child, stdin, stdout };
+        e.send("uci")?;
+        e.wait_for("uciok")?;
+        Ok(e)
+    }
+
+    fn send(&mut self, cmd: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", cmd)?;
+        self.stdin.flush()
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited"))
+        }
+        Ok(line)
+    }
+
+    fn wait_for(&mut self, token: &str) -> io::Result<()> {
+        while self.read_line()?.split_whitespace().next() != Some(token) {}
+        Ok(())
+    }
+}
+
+/// The score of an exact `info` line, mates as the search scores them.
+fn info_score(line: &str) -> Option<i16> {
+    let toks: Vec<&str> = line.split_whitespace().collect();
+    if toks.iter().any(|&t| t == "lowerbound" || t == "upperbound") { return None }
+    let i = toks.iter().position(|&t| t == "score")?;
+    let v = toks.get(i + 2)?.parse::<i32>().ok()?;
+    //mates further than the search looks are cut to its depth so they stay mates
+    let (inf, moves) = (INFINITY as i32, MAX_DEPTH as i32 / 2);
+    let v = match toks[i + 1] {
+        "cp" => v,
+        "mate" if v > 0 => inf - 2 * v.min(moves) + 1,
+        "mate" => -inf - 2 * v.max(-moves),
+        _ => return None,
+    };
+    Some(v.clamp(-inf, inf) as i16)
+}
+
+impl Player for External {
+    fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok")
+    }
+
+    fn go(&mut self, fen: &str, moves: &[String], limits: &Limits) -> io::Result<(String, Option<i16>)> {
+        let mut cmd = format!("position fen {}", fen);
+        if !moves.is_empty() {
+            cmd.push_str(" moves ");
+            cmd.push_str(&moves.join(" "));
+        }
+        self.send(&cmd)?;
+        let go = match (limits.nodes, limits.depth) {
+            (Some(n), _) => format!("go nodes {}", n),
+            (None, Some(d)) => format!("go depth {}", d),
+            (None, None) => format!("go wtime {} btime {} winc {} binc {}",
+                limits.time[0], limits.time[1], limits.inc[0], limits.inc[1]),
+        };
+        self.send(&go)?;
+        let mut score = None;
+        loop {
+            let line = self.read_line()?;
+            let mut it = line.split_whitespace();
+            match it.next() {
+                Some("bestmove") => {
+                    let m = it.next().unwrap_or("0000").to_owned();
+                    return Ok((m, score))
+                }
+                Some("info") => score = info_score(&line).or(score),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        if self.child.try_wait().ok().flatten().is_none() {
+            std::thread::sleep(Duration::from_millis(50));
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+pub struct Config {
+    pub engines: [EngineSpec; 2],
+    pub games: u64,
+    pub concurrency: usize,
+    pub openings: Option<String>,
+    pub time_ms: u64,
+    pub inc_ms: u64,
+    pub nodes: Option<u64>,
+    pub depth: Option<u8>,
+    pub resign_score: Option<i16>,
+    pub resign_plies: usize,
+    pub draw_score: Option<i16>,
+    pub draw_plies: usize,
+    pub draw_start: usize,
+    /// With this many pieces or fewer on the board a known win from the endgame
+    /// evaluator ends the game, once `material_plies` engine scores in a row agree.
+    /// There are no tablebases, so the engines confirm what the evaluator claims.
+    pub material_pieces: u32,
+    pub material_plies: usize,
+    pub sprt: Option<Sprt>,
+    pub pgn: Option<String>,
+}
+
+impl Config {
+    /// Parses `key value` pairs, e.g.
+    /// `engine2 ./old games 1000 concurrency 4 tc 10+0.1 elo0 0 elo1 5`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut cfg = Self {
+            engines: [EngineSpec::parse("self")?, EngineSpec::parse("self")?],
+            games: 100, concurrency: 1, openings: None,
+            time_ms: 10_000, inc_ms: 100, nodes: None, depth: None,
+            resign_score: None, resign_plies: 6,
+            draw_score: None, draw_plies: 10, draw_start: 80,
+            material_pieces: 0, material_plies: 4, sprt: None, pgn: None,
+        };
+        let mut opts = HashMap::new();
+        let mut it = args.iter();
+        while let Some(key) = it.next() {
+            let val = it.next().ok_or(format!("missing value for {}", key))?;
+            opts.insert(key.as_str(), val.as_str());
+        }
+        let mut sprt = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 };
+        let mut use_sprt = false;
+        for (key, val) in opts {
+            let bad = || format!("bad value for {}: {}", key, val);
+            let num = || val.parse::<u64>().map_err(|_| bad());
+            let float = || val.parse::<f64>().map_err(|_| bad());
+            let score = || val.parse::<i16>().ok().filter(|&s| s >= 0).ok_or_else(bad);
+            match key {
+                "engine1" => cfg.engines[0] = EngineSpec::parse(val)?,
+                "engine2" => cfg.engines[1] = EngineSpec::parse(val)?,
+                "games" => cfg.games = num()?,
+                "concurrency" => cfg.concurrency = num()?.max(1) as usize,
+                "openings" => cfg.openings = Some(val.to_owned()),
+                "tc" => {
+                    let (base, inc) = val.split_once('+').unwrap_or((val, "0"));
+                    let secs = |s: &str| s.parse::<f64>().map(|v| (v * 1000.0) as u64).map_err(|_| bad());
+                    cfg.time_ms = secs(base)?;
+                    cfg.inc_ms = secs(inc)?;
+                }
+                "nodes" => cfg.nodes = Some(num()?),
+                "depth" => cfg.depth = Some(num()?.clamp(1, MAX_DEPTH as u64) as u8),
+                "resign" => cfg.resign_score = Some(score()?),
+                "resign-plies" => cfg.resign_plies = num()? as usize,
+                "draw" => cfg.draw_score = Some(score()?),
+                "draw-plies" => cfg.draw_plies = num()? as usize,
+                "draw-start" => cfg.draw_start = num()? as usize,
+                "material" => cfg.material_pieces = num()? as u32,
+                "material-plies" => cfg.material_plies = num()? as usize,
+                "pgn" => cfg.pgn = Some(val.to_owned()),
+                "elo0" => { sprt.elo0 = float()?; use_sprt = true; }
+                "elo1" => { sprt.elo1 = float()?; use_sprt = true; }
+                "alpha" => sprt.alpha = float()?,
+                "beta" => sprt.beta = float()?,
+                _ => return Err(format!("unknown option {}", key)),
+            }
+        }
+        if use_sprt { cfg.sprt = Some(sprt); }
+        Ok(cfg)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+/// Reads FENs or EPD lines; EPDs get their move counters filled in.
+pub fn load_openings(path: &str) -> io::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(text.lines()
+        .map(|l| l.split(';').next().unwrap_or("").trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let fields: Vec<&str> = l.split_whitespace().collect();
+            if fields.len() >= 6 { fields[..6].join(" ") }
+            else { format!("{} 0 1", fields[..fields.len().min(4)].join(" ")) }
+        })
+        .collect())
+}
+
+/// Plays one game from `fen` between `white` and `black`.
+pub fn play_game(board: &mut Position, fen: &str, mut players: [&mut dyn Player; 2], cfg: &Config)
+    -> io::Result<(Outcome, &'static str)>
+{
+    for p in players.iter_mut() { p.new_game()?; }
+    board.load_fen(fen);
+    let mut moves = Vec::new();
+    let mut limits = Limits {
+        time: [cfg.time_ms; 2], inc: [cfg.inc_ms; 2], nodes: cfg.nodes, depth: cfg.depth,
+    };
+    let timed = cfg.nodes.is_none() && cfg.depth.is_none();
+    let (mut resign_streak, mut draw_streak, mut material_streak) = (0, 0, 0);
+    let win_for = |side: Color| if side == Color::White { Outcome::WhiteWins } else { Outcome::BlackWins };
+
+    loop {
+        let us = board.turn;
+        let status = board.status();
+        if status.is_over() { return Ok((status.winner().map_or(Outcome::Draw, win_for), status.as_str())) }
+        if moves.len() >= MAX_GAME_PLIES { return Ok((Outcome::Draw, "move limit")) }
+
+        let start = Instant::now();
+        let (m, score) = players[us].go(fen, &moves, &limits)?;
+        let spent = start.elapsed().as_millis() as u64;
+        if timed {
+            if spent > limits.time[us] { return Ok((win_for(!us), "time forfeit")) }
+            limits.time[us] = limits.time[us] - spent + limits.inc[us];
+        }
+        let mv = match Move::from_uci(board, &m) {
+            Ok(mv) => mv,
+            Err(_) => return Ok((win_for(!us), "illegal move")),
+        };
+        board.make_move(mv);
+        moves.push(m);
+
+        //scores are converted to white's side
+        let score = score.map(|s| if us == Color::White { s } else { -s });
+        //insufficient material already ends the game through the status
+        let winner = (cfg.material_pieces > 0 && board.all_ocupied().count_ones() <= cfg.material_pieces)
+            .then(|| board.endgame_eval()).flatten()
+            .filter(|&(s, _)| s.abs() >= KNOWN_WIN)
+            .map(|(s, _)| if s > 0 { Color::White } else { Color::Black });
+        let agrees = |side: Color| score.is_some_and(|s| match side {
+            Color::White => s >= MATERIAL_AGREE_SCORE,
+            Color::Black => s <= -MATERIAL_AGREE_SCORE,
+        });
+        material_streak = match winner {
+            Some(side) if agrees(side) => material_streak + 1,
+            _ => 0,
+        };
+        //at least one score from each engine
+        if let Some(side) = winner.filter(|_| material_streak >= cfg.material_plies.max(2)) {
+            return Ok((win_for(side), "material win"))
+        }
+        if let (Some(limit), Some(s)) = (cfg.resign_score, score) {
+            resign_streak = if s.abs() < limit { 0 }
+                else if resign_streak != 0 && (resign_streak > 0) == (s > 0) { resign_streak + s.signum() as i32 }
+                else { s.signum() as i32 };
+            if resign_streak.unsigned_abs() as usize >= cfg.resign_plies {
+                return Ok((win_for(if resign_streak > 0 { Color::White } else { Color::Black }), "adjudication"))
+            }
+        }
+        if let (Some(limit), Some(s)) = (cfg.draw_score, score) {
+            draw_streak = if moves.len() >= cfg.draw_start && s.abs() <= limit { draw_streak + 1 }
+                else { 0 };
+            if draw_streak >= cfg.draw_plies { return Ok((Outcome::Draw, "adjudication")) }
+        }
+    }
+}
+
+/// Sequential probability ratio test bounds, in logistic Elo.
+#[derive(Clone, Copy, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    /// Lower and upper log likelihood ratio bounds.
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+}
+
+/// Wins, draws and losses of the first engine.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub w: u64,
+    pub d: u64,
+    pub l: u64,
+}
+
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn score_to_elo(s: f64) -> f64 {
+    400.0 * (s / (1.0 - s)).log10()
+}
+
+impl Stats {
+    pub fn games(&self) -> u64 { self.w + self.d + self.l }
+
+    /// Mean score and its per game variance.
+    fn score(&self) -> (f64, f64) {
+        let n = self.games() as f64;
+        let (w, d) = (self.w as f64 / n, self.d as f64 / n);
+        let s = w + d / 2.0;
+        (s, w + d / 4.0 - s * s)
+    }
+
+    /// Elo difference and the half width of its 95% confidence interval.
+    pub fn elo(&self) -> (f64, f64) {
+        if self.games() == 0 { return (0.0, 0.0) }
+        let (s, var) = self.score();
+        let dev = 1.959964 * (var / self.games() as f64).sqrt();
+        let lo = score_to_elo((s - dev).max(1e-6));
+        let hi = score_to_elo((s + dev).min(1.0 - 1e-6));
+        (score_to_elo(s.clamp(1e-6, 1.0 - 1e-6)), (hi - lo) / 2.0)
+    }
+
+    /// Log likelihood ratio of `elo1` against `elo0` with the normal approximation.
+    pub fn llr(&self, sprt: &Sprt) -> f64 {
+        if self.games() == 0 { return 0.0 }
+        let (s, var) = self.score();
+        if var <= 0.0 { return 0.0 }
+        let (s0, s1) = (elo_to_score(sprt.elo0), elo_to_score(sprt.elo1));
+        (s1 - s0) * (2.0 * s - s0 - s1) / (2.0 * var / self.games() as f64)
+    }
+}
+
+pub fn run(cfg: &Config) -> io::Result<()> {
+    let openings = match &cfg.openings {
+        Some(path) => load_openings(path)?,
+        None => vec![START_FEN.to_owned()],
+    };
+    if openings.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no openings"))
+    }
+    let next = AtomicU64::new(0);
+    let done = AtomicBool::new(false);
+    let stats = Mutex::new(Stats::default());
+    let pgn = match &cfg.pgn {
+        Some(path) => Some(Mutex::new(BufWriter::new(File::create(path)?))),
+        None => None,
+    };
+
+    std::thread::scope(|s| {
+        let mut handles = Vec::new();
+        for _ in 0..cfg.concurrency {
+            let (next, done, stats, openings, pgn) = (&next, &done, &stats, &openings, &pgn);
+            handles.push(s.spawn(move || -> io::Result<()> {
+                let mut engines = [cfg.engines[0].start()?, cfg.engines[1].start()?];
+                let mut board = Position::new();
+                while !done.load(Ordering::Relaxed) {
+                    let game = next.fetch_add(1, Ordering::Relaxed);
+                    if game >= cfg.games { break }
+                    //every opening is played twice with colors reversed
+                    let fen = &openings[(game / 2) as usize % openings.len()];
+                    let swap = game % 2 == 1;
+                    let [e1, e2] = &mut engines;
+                    let players: [&mut dyn Player; 2] = if swap { [&mut **e2, &mut **e1] }
+                        else { [&mut **e1, &mut **e2] };
+                    let (outcome, reason) = play_game(&mut board, fen, players, cfg)?;
+
+                    let mut st = stats.lock().unwrap();
+                    let first_won = match outcome {
+                        Outcome::Draw => { st.d += 1; None }
+                        Outcome::WhiteWins => Some(!swap),
+                        Outcome::BlackWins => Some(swap),
+                    };
+                    match first_won {
+                        Some(true) => st.w += 1,
+                        Some(false) => st.l += 1,
+                        None => (),
+                    }
+                    let result = match outcome {
+                        Outcome::WhiteWins => "1-0",
+                        Outcome::BlackWins => "0-1",
+                        Outcome::Draw => "1/2-1/2",
+                    };
+                    let (white, black) = if swap { (2, 1) } else { (1, 2) };
+                    println!("Game {} (engine{} vs engine{}): {} {{{}}}", game + 1, white, black, result, reason);
+                    report(&st, cfg, done);
+                    drop(st);
+
+                    if let Some(pgn) = pgn {
+                        let mut start = Position::new();
+                        start.load_fen(fen);
+                        let moves: Vec<Move> = board.hist.iter().map(|h| h.m).collect();
+                        let (round, white, black) = ((game + 1).to_string(), format!("engine{}", white),
+                            format!("engine{}", black));
+                        let tags = [("Event", "bitbased match"), ("Site", "?"), ("Date", "????.??.??"),
+                            ("Round", round.as_str()), ("White", white.as_str()), ("Black", black.as_str()),
+                            ("Result", result), ("Termination", reason)];
+                        let mut out = pgn.lock().unwrap();
+                        super::pgn::write_game(&mut *out, &tags, &start, &moves)?;
+                        out.flush()?;
+                    }
+                }
+                Ok(())
+            }));
+        }
+        handles.into_iter().try_for_each(|h| h.join().unwrap())
+    })?;
+    Ok(())
+}
+
+fn report(st: &Stats, cfg: &Config, done: &AtomicBool) {
+    let (elo, err) = st.elo();
+    print!("Score {} - {} - {} [{:.3}] Elo {:.1} +/- {:.1}", st.w, st.l, st.d,
+        (st.w as f64 + st.d as f64 / 2.0) / st.games() as f64, elo, err);
+    if let Some(sprt) = &cfg.sprt {
+        let llr = st.llr(sprt);
+        let (lo, hi) = sprt.bounds();
+        print!(" LLR {:.2} ({:.2}, {:.2}) [{}, {}]", llr, lo, hi, sprt.elo0, sprt.elo1);
+        if !done.load(Ordering::Relaxed) && (llr <= lo || llr >= hi) {
+            done.store(true, Ordering::Relaxed);
+            println!();
+            print!("SPRT: {} accepted", if llr >= hi { "H1" } else { "H0" });
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_statistics() {
+        let even = Stats { w: 30, d: 40, l: 30 };
+        assert!(even.elo().0.abs() < 1e-9);
+        let sprt = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 };
+        assert!(even.llr(&sprt) < 0.0);
+        let strong = Stats { w: 600, d: 300, l: 100 };
+        let (elo, err) = strong.elo();
+        assert!((elo - score_to_elo(0.75)).abs() < 1e-9 && err > 0.0 && err < 50.0);
+        assert!(strong.llr(&sprt) > sprt.bounds().1);
+    }
+
+    #[test]
+    fn plays_a_game() {
+        let cfg = Config::from_args(&["depth".to_owned(), "2".to_owned()]).unwrap();
+        let (mut a, mut b) = (Internal::new(1, None, None).unwrap(), Internal::new(1, None, None).unwrap());
+        let mut board = Position::new();
+        //mate in one for white
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        let (outcome, reason) = play_game(&mut board, fen, [&mut a, &mut b], &cfg).unwrap();
+        assert_eq!((outcome, reason), (Outcome::WhiteWins, "checkmate"));
+
+        let args: Vec<String> = "depth 2 material 3".split_whitespace().map(str::to_owned).collect();
+        let cfg = Config::from_args(&args).unwrap();
+        for bad in ["resign 40000", "draw -5"] {
+            let args: Vec<String> = bad.split_whitespace().map(str::to_owned).collect();
+            assert!(Config::from_args(&args).is_err(), "{}", bad);
+        }
+        let fen = "8/8/4k3/8/8/8/8/R3K3 w - - 0 1";
+        let (outcome, reason) = play_game(&mut board, fen, [&mut a, &mut b], &cfg).unwrap();
+        assert_eq!((outcome, reason), (Outcome::WhiteWins, "material win"));
+        assert_eq!(board.hist.len(), 4);
+        let fen = "8/8/4k3/8/8/8/8/N3K3 w - - 0 1";
+        let (outcome, reason) = play_game(&mut board, fen, [&mut a, &mut b], &cfg).unwrap();
+        assert_eq!((outcome, reason), (Outcome::Draw, "insufficient material"));
+
+        //an engine that doesn't see the win keeps the game going
+        struct Unsure(Internal);
+        impl Player for Unsure {
+            fn new_game(&mut self) -> io::Result<()> { self.0.new_game() }
+            fn go(&mut self, fen: &str, moves: &[String], limits: &Limits) -> io::Result<(String, Option<i16>)> {
+                self.0.go(fen, moves, limits).map(|(m, _)| (m, Some(0)))
+            }
+        }
+        let mut b = Unsure(Internal::new(1, None, None).unwrap());
+        let fen = "8/8/4k3/8/8/8/8/R3K3 w - - 0 1";
+        let (_, reason) = play_game(&mut board, fen, [&mut a, &mut b], &cfg).unwrap();
+        assert_ne!(reason, "material win");
+    }
+
+    #[test]
+    fn parses_info_scores() {
+        assert_eq!(info_score("info depth 9 score cp -35 nodes 800 pv e2e4"), Some(-35));
+        assert_eq!(info_score("info depth 9 score cp 40 lowerbound nodes 800"), None);
+        assert_eq!(info_score("info depth 9 score mate 2 pv a1a8"), Some(INFINITY - 3));
+        assert_eq!(info_score("info depth 9 score mate -1 pv a1a8"), Some(-INFINITY + 2));
+        assert_eq!(info_score("info score mate 30000"), Some(INFINITY - MAX_DEPTH as i16 + 1));
+        assert_eq!(info_score("info score mate -30000"), Some(-INFINITY + MAX_DEPTH as i16));
+        assert_eq!(info_score("info score cp 99999"), Some(INFINITY));
+    }
+
+    #[test]
+    fn shallow_searches_return_a_move() {
+        //razoring used to end the depth 2 root search without a best move here
+        let moves: Vec<String> = "d2d4 d7d5 g1f3 g8f6 c1f4 c8f5 b1c3 b8c6 c3b5 a8c8 d1d2 a7a6 b5c3 c6b4 \
+            a1c1 e7e6 e2e3 f8d6 f1d3 b4d3 c2d3 d6f4".split_whitespace().map(str::to_owned).collect();
+        let limits = Limits { time: [0; 2], inc: [0; 2], nodes: None, depth: Some(2) };
+        let (m, _) = Internal::new(1, None, None).unwrap().go(START_FEN, &moves, &limits).unwrap();
+        assert_ne!(m, "0000");
+    }
+}
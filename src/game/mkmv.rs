@@ -1,30 +1,25 @@
-use super::{defs::*, movgen::*, pos::*, zobrist::*};
-
-const KING_MASK: [u64; 2] = [ 7 << 4, 7 << 60 ]; //TODO: Check this!
-const QUEEN_MASK: [u64; 2] = [ 7 << 2, 7 << 58 ]; //and this!
+use super::{movgen::*, pos::*, variant::Variant, zobrist::*};
+use super::types::{PieceType, Square};
 
 impl Position {
     pub fn make_null_move(&mut self) { 
         // self.verify();
         let hist = Hist {
-            m: Move::new(), cap: Piece::none(), cas: self.cas, 
-            ep: self.ep, fty: self.fty, key: self.key
+            m: Move::new(), cap: None, cas: self.cas, 
+            ep: self.ep, fty: self.fty, key: self.key, vstate: self.vstate, blasts: 0,
         };
-        self.hist[self.hist_ply as usize] = hist;
-        self.turn ^= 1; 
-        self.hist_ply += 1;
-        self.ply += 1;
-        self.ep = NS;
-        self.key ^= ZOBRIST.side() ^ ZOBRIST.en_passant(hist.ep) ^ ZOBRIST.en_passant(NS);
+        self.hist.push(hist);
+        self.turn = !self.turn; 
+        self.fty = 0;
+        self.ep = None;
+        self.key ^= ZOBRIST.side() ^ ZOBRIST.en_passant(hist.ep) ^ ZOBRIST.en_passant(None);
         // self.verify();
     }
 
     pub fn unmake_null_move(&mut self) { 
         // self.verify();
-        self.hist_ply -= 1;
-        self.ply -= 1;
-        self.turn ^= 1;
-        let hist = &self.hist[self.hist_ply as usize];
+        self.turn = !self.turn;
+        let hist = self.hist.pop().expect("no null move to unmake");
         self.key = hist.key;
         self.cas = hist.cas;
         self.fty = hist.fty;
@@ -35,117 +30,130 @@ impl Position {
     pub fn make_move(&mut self, m: Move) -> bool {
         // self.verify();
         let mut hist = Hist {
-            m, cap: self.board[m.to() as usize], cas: self.cas, 
-            ep: self.ep, fty: self.fty, key: self.key
+            m, cap: self.board[m.to()], cas: self.cas, 
+            ep: self.ep, fty: self.fty, key: self.key, vstate: self.vstate, blasts: 0,
         };
 
         let (f, t) = (m.from(), m.to());
-        let turnx = self.turnx();
-        let kind = m.kind();
-        let mut ep = NS;
-        if kind.en_passant() {
-            assert!(self.ep != NS);
-            let (rk, fl) = (f / 8, self.ep % 8);
-            let sq = rk * 8 + fl;
-            hist.cap = self.board[sq as usize];
-            self.remove_piece(sq);
-            self.move_piece(f, t);
-            self.fty = 0;
-        } else if kind.castle() {
-            let atk = self.attacked(turnx ^ 1);
-            if t > f {
-                if atk & KING_MASK[turnx] != 0 { return false }
-                self.do_castling(f, t);
-            } else {
-                if atk & QUEEN_MASK[turnx] != 0 { return false }
-                // if self.turn == WHITE { println!("{}", self); }
-                self.do_castling(f, t);
-                // if self.turn == WHITE { println!("{}", self); }
+        let us = self.turn;
+        let mut ep = None;
+        let mut capsq = t;
+        match m.kind() {
+            MoveKind::Drop => {
+                let pt = m.dropped();
+                hist.cap = None;
+                self.set_pocket(us, pt, self.vstate.pocket[us][pt] - 1);
+                self.add_piece(us, pt, t);
+                self.fty = self.fty.saturating_add(1);
             }
-            self.fty += 1;
-        } else if kind.long_push() {
-            self.move_piece(f, t);
-            if self.turn == WHITE { ep = f + 8; }
-            else { ep = t + 8; }
-            self.fty = 0;
-        } else {
-            self.fty += 1;
-            if m.prom() != 0 {
-                self.remove_piece(f);
-                self.add_piece(turnx, m.prom() as usize, f);
+            MoveKind::EnPassant => {
+                let sq = Square::new(self.ep.expect("en passant without a target").file(), f.rank());
+                capsq = sq;
+                hist.cap = self.board[sq];
+                self.remove_piece(sq);
+                self.move_piece(f, t);
                 self.fty = 0;
             }
-            if m.cap() {
-                self.remove_piece(t);
+            MoveKind::Castle => {
+                let side = (t > f) as usize;
+                let blocked = if self.variant == Variant::Atomic { self.atomic_castle_attacked(f, t) }
+                    else { self.attacked(!us) & self.castling.safe[us][side] != 0 };
+                if blocked { return false }
+                hist.cap = None;
+                self.do_castling(f, t);
+                self.fty = self.fty.saturating_add(1);
+            }
+            MoveKind::DoublePush => {
+                self.move_piece(f, t);
+                ep = Some(f.forward(us, 1));
                 self.fty = 0;
             }
-            self.move_piece(f, t);
+            _ => {
+                self.fty = self.fty.saturating_add(1);
+                if let Some(pt) = m.prom() {
+                    self.remove_piece(f);
+                    self.add_piece(us, pt, f);
+                    self.fty = 0;
+                }
+                if m.cap() {
+                    self.remove_piece(t);
+                    self.fty = 0;
+                }
+                self.move_piece(f, t);
 
-            if f == 4 || t == 4 { self.cas.dis_both(WHITE); }
-            if f == 60 || t == 60 { self.cas.dis_both(BLACK); }
-            if f == 0 || t == 0 { self.cas.dis_queen(WHITE); }
-            if f == 7 || t == 7 { self.cas.dis_king(WHITE); }
-            if f == 56 || t == 56 { self.cas.dis_queen(BLACK); }
-            if f == 63 || t == 63 { self.cas.dis_king(BLACK); }
+                self.cas.retain(self.castling.mask[f] & self.castling.mask[t]);
+            }
+        }
+        match self.variant {
+            Variant::Atomic if hist.cap.is_some() => hist.blasts = self.explode(t),
+            Variant::Crazyhouse => self.crazyhouse_make(m, hist.cap, capsq),
+            _ => (),
         }
         self.ep = ep;
-        self.hist[self.hist_ply as usize] = hist;
-        self.hist_ply += 1;
-        self.turn ^= 1;
+        self.hist.push(hist);
+        self.turn = !us;
 
         self.key ^= ZOBRIST.castling(hist.cas) ^ ZOBRIST.castling(self.cas)
             ^ ZOBRIST.side() ^ ZOBRIST.en_passant(hist.ep) ^ ZOBRIST.en_passant(self.ep);
 
+        #[cfg(feature = "nnue")]
+        self.nnue_refresh();
+
         // self.verify();
-        if self.in_check(turnx) { 
+        let legal = match self.variant {
+            //blowing up the enemy king wins on the spot, losing our own is never allowed
+            Variant::Atomic => self.pieces[us][PieceType::King] != 0
+                && (self.pieces[!us][PieceType::King] == 0 || !self.in_check(us)),
+            _ => !self.in_check(us),
+        };
+        if !legal {
             self.unmake_move();
-            false
-        } else {
-            true
+            return false
         }
+        if self.variant == Variant::ThreeCheck && self.in_check(!us) {
+            self.add_check(us);
+        }
+        true
     }
 
     pub fn unmake_move(&mut self) {
         // self.verify();
-        assert!(self.hist_ply != 0);
-        self.turn ^= 1;
-        self.hist_ply -= 1;
-        let hist = self.hist[self.hist_ply as usize];
+        let hist = self.hist.pop().expect("no move to unmake");
+        self.turn = !self.turn;
         let m  = hist.m;
         
         self.fty = hist.fty;
         self.ep = hist.ep;
         self.cas = hist.cas;
+        self.vstate = hist.vstate;
 
-        let (kind, f, t) = (m.kind(), m.from(), m.to());
-        let turnx = self.turnx();
-        if kind.en_passant() {
-            if self.turn == WHITE {
-                self.add_piece(BLACKX, PAWNX, self.ep - 8);
-            } else {
-                self.add_piece(WHITEX, PAWNX, self.ep + 8);
-            }
-            self.move_piece(t, f);
-        } else if kind.castle() {
-            const ROOK_FROM: [[u8; 2]; 2] = [ [0, 7], [56, 63] ];
-            const ROOK_TO: [[u8; 2]; 2] = [ [3, 5], [59, 61] ];
-            let x = (t > f) as usize;
-            self.move_piece(ROOK_TO[turnx][x], ROOK_FROM[turnx][x]);
-            self.move_piece(t, f);
-        } else if m.prom() != 0 {
-            self.remove_piece(t);
-            self.add_piece(turnx, PAWNX, f);
-            if m.cap() {
-                self.add_piece(turnx ^ 1, hist.cap.get_type() as usize, t);
+        let (f, t) = (m.from(), m.to());
+        let us = self.turn;
+        self.unexplode(hist.blasts);
+        match m.kind() {
+            MoveKind::Drop => self.remove_piece(t),
+            MoveKind::EnPassant => {
+                let ep = self.ep.expect("en passant without a target");
+                self.add_piece(!us, PieceType::Pawn, ep.forward(!us, 1));
+                self.move_piece(t, f);
             }
-        } else {
-            self.move_piece(t, f);
-            if m.cap() {
-                self.add_piece(turnx ^ 1, hist.cap.get_type() as usize, t);
+            MoveKind::Castle => self.undo_castling(f, t),
+            kind => {
+                if matches!(kind, MoveKind::Promotion | MoveKind::PromotionCapture) {
+                    self.remove_piece(t);
+                    self.add_piece(us, PieceType::Pawn, f);
+                } else {
+                    self.move_piece(t, f);
+                }
+                if let Some(cap) = hist.cap {
+                    self.add_piece(!us, cap.kind(), t);
+                }
             }
         }
 
         self.key = hist.key;
+        #[cfg(feature = "nnue")]
+        self.nnue_refresh();
         // self.verify();
     }
 }
@@ -1,14 +1,34 @@
 pub mod defs;
+pub mod types;
 pub mod bitbrd;
 //pub mod atktbl;
 pub mod movgen;
+pub mod notation;
+pub mod pgn;
 pub mod pos;
+pub mod variant;
 pub mod mkmv;
+pub mod rules;
 pub mod perft;
 pub mod magics;
 pub mod magic_table;
 pub mod zobrist;
 pub mod eval;
+pub mod pawns;
+pub mod endgame;
+pub mod params;
+pub mod weights;
+#[cfg(feature = "nnue")]
+pub mod nnue;
 pub mod pvtable;
+pub mod tunables;
 pub mod search;
+pub mod skill;
+pub mod wdl;
 pub mod uci;
+pub mod datagen;
+pub mod tune;
+pub mod matchup;
+pub mod bench;
+pub mod cli;
+pub mod console;
@@ -1,94 +1,162 @@
 use lazy_static::lazy_static;
 
-use super::{bitbrd::*, defs::*, pos::*, magic_table::*};
+use super::{bitbrd::*, defs::*, pos::*, magic_table::*, tunables, variant::Variant};
+use super::types::{Color, Piece, PieceType, Square};
 use std::fmt;
-
-
-const CAPTURE_BASE: u16 = 10000;
-const PROM_BASE: u16 = 8000;
-const KILLER_BASE_CUR: u16 = 5000;
-const KILLER_BASE_PREV: u16 = 4500;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 lazy_static! {
     pub static ref ATTK_TBL: Box<AttackTable> = AttackTable::new();
 }
 
-pub struct KindBits(u8);
-
-impl KindBits {
-    pub fn castle(&self) -> bool { self.0 & 1 != 0 }
-    pub fn en_passant(&self) -> bool { self.0 & 2 != 0 }
-    pub fn long_push(&self) -> bool { self.0 & 4 != 0 }
+/// Set by `UCI_Chess960`: castling moves are printed as king takes rook.
+pub static CHESS960: AtomicBool = AtomicBool::new(false);
+
+/// What a move does beyond moving a piece, decoded from its flag bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+    Quiet,
+    DoublePush,
+    Castle,
+    Drop,
+    Capture,
+    EnPassant,
+    Promotion,
+    PromotionCapture,
 }
 
 #[derive(Clone, Copy)]
 pub struct OrderedMove(pub Move, pub u16);
 
+/// From square in bits 0-5, to square in bits 6-11 and a 4-bit flag on top.
+/// Drops store the piece type in place of the from square and castling is
+/// encoded as king takes rook.
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Move(u32);
+pub struct Move(u16);
+
+const LONG: u16 = 1;
+const CASTLE: u16 = 2;
+const DROP: u16 = 3;
+const CAPTURE: u16 = 4;
+const EN_PASSANT: u16 = 5;
+//antichess only
+const KING_PROM: u16 = 6;
+const KING_PROM_CAP: u16 = 7;
+//plus the piece type index minus one
+const PROM: u16 = 8;
+const PROM_CAP: u16 = 12;
+//one bit per capturing flag
+const CAPTURE_FLAGS: u16 = 1 << CAPTURE | 1 << EN_PASSANT | 1 << KING_PROM_CAP | 0xF << PROM_CAP;
 
 impl Move {
     pub fn new() -> Self { Self(0) }
 
-    pub fn new_long(from: u8, to: u8) -> Self {
-        Self( 4 << 16
-            | (to as u32) << 8
-            | (from as u32))
+    fn pack(from: u8, to: Square, flags: u16) -> Self {
+        Self(flags << 12 | (to.u8() as u16) << 6 | from as u16)
     }
 
-    pub fn new_usual(from: u8, to: u8, cap: bool) -> Self {
-        Self((cap as u32) << 31 | (to as u32) << 8 | (from as u32))
-    }
+    pub fn new_long(from: Square, to: Square) -> Self { Self::pack(from.u8(), to, LONG) }
 
-    pub fn new_prom(from: u8, to: u8, cap: bool, tp: u8) -> Self {
-        Self( (cap as u32) << 31 
-            | (tp as u32) << 24
-            | (to as u32) << 8
-            | (from as u32))
+    pub fn new_usual(from: Square, to: Square, cap: bool) -> Self {
+        Self::pack(from.u8(), to, if cap { CAPTURE } else { 0 })
     }
 
-    pub fn new_castle(from: u8, to: u8) -> Self {
-        Self(1 << 16 | (to as u32) << 8 | (from as u32))
+    pub fn new_prom(from: Square, to: Square, cap: bool, pt: PieceType) -> Self {
+        let flags = match pt {
+            PieceType::King => KING_PROM + cap as u16,
+            _ => (if cap { PROM_CAP } else { PROM }) + pt.index() as u16 - 1,
+        };
+        Self::pack(from.u8(), to, flags)
     }
 
-    pub fn new_enpassant(from: u8, to: u8) -> Self {
-        Self( 1 << 31 
-            | 2 << 16
-            | (to as u32) << 8
-            | (from as u32))
+    pub fn new_castle(from: Square, to: Square) -> Self { Self::pack(from.u8(), to, CASTLE) }
+
+    pub fn new_enpassant(from: Square, to: Square) -> Self { Self::pack(from.u8(), to, EN_PASSANT) }
+
+    /// A Crazyhouse drop; the piece type takes the place of the origin square.
+    pub fn new_drop(pt: PieceType, to: Square) -> Self { Self::pack(pt.index() as u8, to, DROP) }
+
+    #[inline(always)]
+    fn flags(&self) -> u16 { self.0 >> 12 }
+    #[inline(always)]
+    pub fn from(&self) -> Square { Square::from_index_unchecked((self.0 & 0x3F) as u8) }
+    #[inline(always)]
+    pub fn to(&self) -> Square { Square::from_index_unchecked((self.0 >> 6 & 0x3F) as u8) }
+
+    pub fn kind(&self) -> MoveKind {
+        match self.flags() {
+            LONG => MoveKind::DoublePush,
+            CASTLE => MoveKind::Castle,
+            DROP => MoveKind::Drop,
+            CAPTURE => MoveKind::Capture,
+            EN_PASSANT => MoveKind::EnPassant,
+            KING_PROM | PROM..=11 => MoveKind::Promotion,
+            KING_PROM_CAP | PROM_CAP..=15 => MoveKind::PromotionCapture,
+            _ => MoveKind::Quiet,
+        }
     }
 
-    pub fn from(&self) -> u8 { (self.0 & (0xFF)) as u8 }
-    pub fn to(&self) -> u8 { ((self.0 >> 8) & 0xFF) as u8 }
-    pub fn kind(&self) -> KindBits {
-        KindBits(((self.0 >> 16) & 0xFF) as u8)
+    #[inline(always)]
+    pub fn prom(&self) -> Option<PieceType> {
+        match self.flags() {
+            KING_PROM | KING_PROM_CAP => Some(PieceType::King),
+            f if f >= PROM => Some(PieceType::from_index((f & 3) as usize + 1)),
+            _ => None,
+        }
     }
-    pub fn prom(&self) -> u8 { (self.0 >> 24 & 0b111) as u8 }
-    pub fn cap(&self) -> bool { self.0 >> 31 != 0 }
+
+    #[inline(always)]
+    pub fn cap(&self) -> bool { CAPTURE_FLAGS >> self.flags() & 1 != 0 }
+
+    /// The piece type a drop puts on the board.
+    pub fn dropped(&self) -> PieceType { PieceType::from_index((self.0 & 0x3F) as usize) }
 
     pub fn is_null(&self) -> bool { self.0 == 0 }
+
+    /// Finds the legal move written in UCI notation; castling may be given either as
+    /// the king's destination or as king takes rook.
+    pub fn from_uci(pos: &Position, uci: &str) -> Result<Self, String> {
+        let uci = uci.trim();
+        let mut moves = MoveList::new();
+        pos.gen_moves::<false>(&mut moves);
+        moves.iter().map(|om| om.0)
+            .find(|m| m.to_uci(false) == uci || m.to_uci(true) == uci)
+            .filter(|&m| pos.clone().make_move(m))
+            .ok_or_else(|| format!("illegal move {}", uci))
+    }
+}
+
+impl Default for Move {
+    fn default() -> Self { Self::new() }
 }
 
 
+impl Move {
+    /// UCI notation; castling is king takes rook in Chess960 and the king's
+    /// destination otherwise.
+    pub fn to_uci(&self, chess960: bool) -> String {
+        if self.kind() == MoveKind::Drop {
+            return format!("{}@{}", self.dropped().char().to_ascii_uppercase(), self.to())
+        }
+        let to = if self.kind() == MoveKind::Castle && !chess960 { castle_targets(self.from(), self.to()).0 }
+            else { self.to() };
+        match self.prom() {
+            Some(pt) => format!("{}{}{}", self.from(), to, pt),
+            None => format!("{}{}", self.from(), to),
+        }
+    }
+}
+
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}{}{}{}",
-            ('a' as u8 + self.from()%8) as char,
-            ('1' as u8 + self.from()/8) as char,
-            ('a' as u8 + self.to()%8) as char,
-            ('1' as u8 + self.to()/8) as char,
-        )?;
-        write!(f, "{}", match self.prom() {
-            KNIGHT => "n",
-            BISHOP => "b",
-            ROOK => "r",
-            QUEEN => "q",
-            _ => "",
-        })
-        // write!(f, "{}", match self.cap() {
-            // true => "x",
-            // false => "",
-        // })
+        f.write_str(&self.to_uci(CHESS960.load(Ordering::Relaxed)))
+    }
+}
+
+impl fmt::Debug for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_null() { return f.write_str("Move(null)") }
+        write!(f, "Move({}, {:?})", self, self.kind())
     }
 }
 
@@ -104,8 +172,10 @@ pub struct PickyIter<'a> {
     k: usize,
 }
 
-impl<'a> PickyIter<'a> {
-    pub fn next(&mut self) -> Option<&OrderedMove> {
+impl Iterator for PickyIter<'_> {
+    type Item = OrderedMove;
+
+    fn next(&mut self) -> Option<OrderedMove> {
         if self.k >= self.n { return None }
         let (mut best_score, mut best_idx) = (0, self.k);
         for i in self.k..self.n {
@@ -117,21 +187,19 @@ impl<'a> PickyIter<'a> {
         let k = self.k;
         self.moves.swap(self.k, best_idx);
         self.k += 1;
-        Some(&self.moves[k])
+        Some(self.moves[k])
     }
 }
 
 impl MoveList {
     pub fn new() -> Self {
-        use std::mem::MaybeUninit;
         Self {
-            moves: unsafe { MaybeUninit::uninit().assume_init() },
-            // moves: [PriorityMove::default(); 256],
+            moves: [OrderedMove(Move::new(), 0); MAX_MOVES],
             n: 0,
         }
     }
 
-    pub fn iter_picky(&mut self) -> PickyIter {
+    pub fn iter_picky(&mut self) -> PickyIter<'_> {
         PickyIter {
             moves: &mut self.moves,
             n: self.n,
@@ -147,36 +215,47 @@ impl MoveList {
         self.moves[0..self.n].iter_mut()
     }
 
-    pub fn push<const CAP: bool, const ATK: u8>(&mut self, m: Move, p: &Position) {
+    /// Adds `m`, made by a piece of type `atk`, with captures scored MVV-LVA.
+    #[inline(always)]
+    pub fn push<const CAP: bool>(&mut self, m: Move, atk: PieceType, p: &Position) {
         let score = if CAP {
-            let vic = p.board[m.to() as usize].get_type() as u16;
-            (vic + 1) * 100 + 6 - (ATK as u16+1) + CAPTURE_BASE
+            let vic = p.board[m.to()].map_or(0, |v| v.kind() as u16);
+            (vic + 1) * 100 + 6 - (atk as u16 + 1) + tunables::capture_base()
         } else {
-            if p.search_killers[0][p.ply as usize] == m {
-                KILLER_BASE_CUR
-            } else if p.search_killers[1][p.ply as usize] == m {
-                KILLER_BASE_PREV
-            } else {
-                p.search_hist[p.board[m.from() as usize].id() as usize][m.to() as usize]
-            }
+            //quiet moves are ordered by the searcher
+            0
         };
         self.moves[self.n] = OrderedMove(m, score);
         self.n += 1;
     }
 
-    pub fn push_prom<const CAP: bool, const PT: u8>(&mut self, m: Move, p: &Position) {
+    #[inline(always)]
+    pub fn push_prom<const CAP: bool>(&mut self, m: Move, pt: PieceType, p: &Position) {
         if CAP {
-            self.push::<true, PAWN>(m, p);
+            self.push::<true>(m, PieceType::Pawn, p);
         } else {
-            self.moves[self.n] = OrderedMove(m, PROM_BASE + PT as u16);
+            self.moves[self.n] = OrderedMove(m, tunables::prom_base() + pt as u16);
             self.n += 1;
         }
     }
 
     pub fn clear(&mut self) { self.n = 0; }
 
+    pub fn retain(&mut self, mut keep: impl FnMut(Move) -> bool) {
+        let mut n = 0;
+        for i in 0..self.n {
+            if keep(self.moves[i].0) {
+                self.moves[n] = self.moves[i];
+                n += 1;
+            }
+        }
+        self.n = n;
+    }
+
     pub fn len(&self) -> usize { self.n }
 
+    pub fn is_empty(&self) -> bool { self.n == 0 }
+
     pub fn get(&self, idx: usize) -> &OrderedMove { 
         debug_assert!(idx < self.n);
         &self.moves[idx]
@@ -188,22 +267,37 @@ impl MoveList {
     }
 }
 
+impl Default for MoveList {
+    fn default() -> Self { Self::new() }
+}
+
 impl Position {
-    fn gen_proms<const CAP: bool>(&self, from: u8, to: u8, moves: &mut MoveList) {
-        moves.push_prom::<CAP, KNIGHT>(Move::new_prom(from, to, CAP, KNIGHT), self);
-        moves.push_prom::<CAP, BISHOP>(Move::new_prom(from, to, CAP, BISHOP), self);
-        moves.push_prom::<CAP, ROOK>(Move::new_prom(from, to, CAP, ROOK), self);
-        moves.push_prom::<CAP, QUEEN>(Move::new_prom(from, to, CAP, QUEEN), self);
+    /// The piece that `m` puts on its destination square.
+    pub fn moved_piece(&self, m: Move) -> Piece {
+        if m.kind() == MoveKind::Drop { Piece::new(m.dropped(), self.turn) }
+        else { self.board[m.from()].expect("no piece to move") }
     }
 
-    fn gen_pawn_moves<const TURN: u8, const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
-        let brd = self.pieces[self.turn as usize][PAWNX];
-        let enemies = self.occupied[(self.turn ^ 1) as usize];
+    fn gen_proms<const CAP: bool>(&self, from: Square, to: Square, moves: &mut MoveList) {
+        let kinds: &[PieceType] = if self.variant == Variant::Antichess {
+            &[PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen, PieceType::King]
+        } else {
+            &[PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen]
+        };
+        for &pt in kinds {
+            moves.push_prom::<CAP>(Move::new_prom(from, to, CAP, pt), pt, self);
+        }
+    }
+
+    fn gen_pawn_moves<const WHITE: bool, const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
+        const PAWN: PieceType = PieceType::Pawn;
+        let brd = self.pieces[self.turn][PAWN];
+        let enemies = self.occupied[!self.turn];
         let (r7, notr7) = (brd & RANK_7, brd & !RANK_7);
         let (r2, notr2) = (brd & RANK_2, brd & !RANK_2);
         let free = !self.all_ocupied();
 
-        if TURN == WHITE {
+        if WHITE {
             let promcaps7 = (r7 & !FILE_A) << 7 & enemies;
             let promcaps9 = (r7 & !FILE_H) << 9 & enemies;
             let caps7 = (notr7 & !FILE_A) << 7 & enemies;
@@ -214,33 +308,40 @@ impl Position {
                 let shorts = (notr7 << 8) & free;
                 let longs = (((r2 << 8) & free) << 8) & free;
                 for sq in proms.bits() {
-                    self.gen_proms::<false>(sq - 8, sq, moves);
+                    self.gen_proms::<false>(sq.down(1), sq, moves);
                 }
                 for sq in shorts.bits() {
-                    moves.push::<false, PAWN>(Move::new_usual(sq - 8, sq, false), self)
+                    moves.push::<false>(Move::new_usual(sq.down(1), sq, false), PAWN, self)
                 }
                 for sq in longs.bits() {
-                    moves.push::<false, PAWN>(Move::new_long(sq - 16, sq), self);
+                    moves.push::<false>(Move::new_long(sq.down(2), sq), PAWN, self);
+                }
+                if self.variant == Variant::Horde {
+                    //horde pawns on the first rank may advance two squares too, without en passant
+                    let r1 = brd & 0xFF;
+                    for sq in ((((r1 << 8) & free) << 8) & free).bits() {
+                        moves.push::<false>(Move::new_usual(sq.down(2), sq, false), PAWN, self);
+                    }
                 }
             }
 
             for sq in promcaps7.bits() {
-                self.gen_proms::<true>(sq - 7, sq, moves);
+                self.gen_proms::<true>(sq.offset(-7), sq, moves);
             }
             for sq in promcaps9.bits() {
-                self.gen_proms::<true>(sq - 9, sq, moves);
+                self.gen_proms::<true>(sq.offset(-9), sq, moves);
             }
-                        for sq in caps7.bits() {
-                moves.push::<true, PAWN>(Move::new_usual(sq - 7, sq, true), self)
+            for sq in caps7.bits() {
+                moves.push::<true>(Move::new_usual(sq.offset(-7), sq, true), PAWN, self)
             }
             for sq in caps9.bits() {
-                moves.push::<true, PAWN>(Move::new_usual(sq - 9, sq, true), self)
+                moves.push::<true>(Move::new_usual(sq.offset(-9), sq, true), PAWN, self)
             }
 
-            if self.ep != NS {
-                let b = ((1<<self.ep & !FILE_A) >> 9 | (1<<self.ep & !FILE_H) >> 7) & brd;
+            if let Some(ep) = self.ep {
+                let b = ((ep.bb() & !FILE_A) >> 9 | (ep.bb() & !FILE_H) >> 7) & brd;
                 for sq in b.bits() {
-                    moves.push::<false, PAWN>(Move::new_enpassant(sq, self.ep), self);
+                    moves.push::<false>(Move::new_enpassant(sq, ep), PAWN, self);
                 }
             }
         } else {
@@ -254,234 +355,222 @@ impl Position {
                 let shorts = (notr2 >> 8) & free;
                 let longs = ((r7 >> 8) & free) >> 8 & free;
                 for sq in proms.bits() {
-                    self.gen_proms::<false>(sq + 8, sq, moves);
+                    self.gen_proms::<false>(sq.up(1), sq, moves);
                 }
                 for sq in shorts.bits() {
-                    moves.push::<false, PAWN>(Move::new_usual(sq + 8, sq, false), self)
+                    moves.push::<false>(Move::new_usual(sq.up(1), sq, false), PAWN, self)
                 }
                 for sq in longs.bits() {
-                    moves.push::<false, PAWN>(Move::new_long(sq + 16, sq), self);
+                    moves.push::<false>(Move::new_long(sq.up(2), sq), PAWN, self);
                 }
             }
 
             for sq in promcaps7.bits() {
-                self.gen_proms::<true>(sq + 7, sq, moves);
+                self.gen_proms::<true>(sq.offset(7), sq, moves);
             }
             for sq in promcaps9.bits() {
-                self.gen_proms::<true>(sq + 9, sq, moves);
+                self.gen_proms::<true>(sq.offset(9), sq, moves);
             }
             for sq in caps7.bits() {
-                moves.push::<true, PAWN>(Move::new_usual(sq + 7, sq, true), self)
+                moves.push::<true>(Move::new_usual(sq.offset(7), sq, true), PAWN, self)
             }
             for sq in caps9.bits() {
-                moves.push::<true, PAWN>(Move::new_usual(sq + 9, sq, true), self)
+                moves.push::<true>(Move::new_usual(sq.offset(9), sq, true), PAWN, self)
             }
-            if self.ep != NS {
-                let b = ((1<<self.ep & !FILE_H) << 9 | (1<<self.ep & !FILE_A) << 7) & brd;
+            if let Some(ep) = self.ep {
+                let b = ((ep.bb() & !FILE_H) << 9 | (ep.bb() & !FILE_A) << 7) & brd;
                 for sq in b.bits() {
-                    moves.push::<false, PAWN>(Move::new_enpassant(sq, self.ep), self);
+                    moves.push::<false>(Move::new_enpassant(sq, ep), PAWN, self);
                 }
             }
         }
     }
 
-    pub fn gen_king_moves<const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
+    /// Moves of the pieces of type `pt` that attack `attacks(from)`.
+    #[inline(always)]
+    fn gen_piece_moves<const ONLY_CAPS: bool>(
+        &self, pt: PieceType, moves: &mut MoveList, attacks: impl Fn(Square) -> BitBoard,
+    ) {
         let free  = !self.all_ocupied();
-        let enemies = self.occupied[(self.turn ^ 1) as usize];
-        for from in self.pieces[self.turn as usize][KINGX].bits() {
-            let bb = ATTK_TBL.king_attacks(from);
+        let enemies = self.occupied[!self.turn];
+        for from in self.pieces[self.turn][pt].bits() {
+            let bb = attacks(from);
             for to in (bb & enemies).bits() {
-                moves.push::<true, KING>(Move::new_usual(from, to, true), self);
+                moves.push::<true>(Move::new_usual(from, to, true), pt, self);
             }
             if !ONLY_CAPS {
                 for to in (bb & free).bits() {
-                    moves.push::<false, KING>(Move::new_usual(from, to, false), self);
+                    moves.push::<false>(Move::new_usual(from, to, false), pt, self);
                 }
             }
         }
     }
 
+    pub fn gen_king_moves<const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
+        self.gen_piece_moves::<ONLY_CAPS>(PieceType::King, moves, |sq| ATTK_TBL.king_attacks(sq));
+    }
+
     pub fn gen_knight_moves<const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
-        let free  = !self.all_ocupied();
-        let enemies = self.occupied[(self.turn ^ 1) as usize];
-        for from in self.pieces[self.turn as usize][KNIGHTX].bits() {
-            let bb = ATTK_TBL.knight_attacks(from);
-            for to in (bb & enemies).bits() {
-                moves.push::<true, KNIGHT>(Move::new_usual(from, to, true), self);
-            }
-            if !ONLY_CAPS {
-                for to in (bb & free).bits() {
-                    moves.push::<false, KNIGHT>(Move::new_usual(from, to, false), self); 
-                }
-            }
-        }
+        self.gen_piece_moves::<ONLY_CAPS>(PieceType::Knight, moves, |sq| ATTK_TBL.knight_attacks(sq));
     }
 
     pub fn gen_bishop_moves<const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
         let blockers = self.all_ocupied();
-        let free  = !blockers;
-        let enemies = self.occupied[(self.turn ^ 1) as usize];
-        for from in self.pieces[self.turn as usize][BISHOPX].bits() {
-            let bb = ATTK_TBL.bishop_attacks(from, blockers);
-            for to in (bb & enemies).bits() {
-                moves.push::<true, BISHOP>(Move::new_usual(from, to, true), self);
-            }
-            if !ONLY_CAPS {
-                for to in (bb & free).bits() {
-                    moves.push::<false, BISHOP>(Move::new_usual(from, to, false), self);
-                }
-            }
-        }
+        self.gen_piece_moves::<ONLY_CAPS>(PieceType::Bishop, moves, |sq| ATTK_TBL.bishop_attacks(sq, blockers));
     }
 
     pub fn gen_rook_moves<const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
         let blockers = self.all_ocupied();
-        let free  = !blockers;
-        let enemies = self.occupied[(self.turn ^ 1) as usize];
-        for from in self.pieces[self.turn as usize][ROOKX].bits() {
-            let bb = ATTK_TBL.rook_attacks(from, blockers);
-            for to in (bb & enemies).bits() {
-                moves.push::<true, ROOK>(Move::new_usual(from, to, true), self);
-            }
-            if !ONLY_CAPS {
-                for to in (bb & free).bits() {
-                    moves.push::<false, ROOK>(Move::new_usual(from, to, false), self);
-                }
-            }
-        }
+        self.gen_piece_moves::<ONLY_CAPS>(PieceType::Rook, moves, |sq| ATTK_TBL.rook_attacks(sq, blockers));
     }
 
     pub fn gen_queen_moves<const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
         let blockers = self.all_ocupied();
-        let free  = !blockers;
-        let enemies = self.occupied[(self.turn ^ 1) as usize];
-        for from in self.pieces[self.turn as usize][QUEENX].bits() {
-            let bb = ATTK_TBL.bishop_attacks(from, blockers)
-                | ATTK_TBL.rook_attacks(from, blockers);
-            for to in (bb & enemies).bits() {
-                moves.push::<true, QUEEN>(Move::new_usual(from, to, true), self);
-            }
-            if !ONLY_CAPS {
-                for to in (bb & free).bits() {
-                    moves.push::<false, QUEEN>(Move::new_usual(from, to, false), self);
-                }
-            }
-        }
+        self.gen_piece_moves::<ONLY_CAPS>(PieceType::Queen, moves, |sq| {
+            ATTK_TBL.bishop_attacks(sq, blockers) | ATTK_TBL.rook_attacks(sq, blockers)
+        });
     }
 
-    fn gen_castling_moves<const TURN: u8>(&self, moves: &mut MoveList) {
-        const KING_MASK: [u64; 2] = [ 3 << 5, 3 << 61 ]; //TODO: Check this!
-        const QUEEN_MASK: [u64; 2] = [ 7 << 1, 7 << 57 ]; //and this!
-        const KING_MOVE: [(u8, u8); 2] = [ (4, 6), (60, 62) ];
-        const QUEEN_MOVE: [(u8, u8); 2] = [ (4, 2), (60, 58) ];
-        let b = self.all_ocupied();
-        let (f, tk) = KING_MOVE[TURN as usize];
-        let tq = QUEEN_MOVE[TURN as usize].1;
-        if self.cas.king(TURN) && b & KING_MASK[TURN as usize] == 0 {
-            moves.push::<false, 0>(Move::new_castle(f, tk), self)
+    /// Castling moves are encoded as the king capturing its own rook.
+    fn gen_castling_moves(&self, moves: &mut MoveList) {
+        let rules = &self.castling;
+        let (b, c) = (self.all_ocupied(), self.turn);
+        let king = rules.king[c];
+        if self.cas.king(c) && b & rules.path[c][1] == 0 {
+            moves.push::<false>(Move::new_castle(king, rules.rooks[c][1]), PieceType::King, self)
         }
-        if self.cas.queen(TURN) && b & QUEEN_MASK[TURN as usize] == 0 {
-            moves.push::<false, 0>(Move::new_castle(f, tq), self)
+        if self.cas.queen(c) && b & rules.path[c][0] == 0 {
+            moves.push::<false>(Move::new_castle(king, rules.rooks[c][0]), PieceType::King, self)
         }
     }
 
 
     pub fn gen_moves<const ONLY_CAPS: bool>(&self, moves: &mut MoveList) {
+        if self.variant != Variant::Standard && self.variant_winner().is_some() { return }
         self.gen_knight_moves::<ONLY_CAPS>(moves);
         self.gen_bishop_moves::<ONLY_CAPS>(moves);
         self.gen_rook_moves::<ONLY_CAPS>(moves);
         self.gen_queen_moves::<ONLY_CAPS>(moves);
         self.gen_king_moves::<ONLY_CAPS>(moves);
-        if self.turn == WHITE {
-            self.gen_pawn_moves::<WHITE, ONLY_CAPS>(moves);
-            self.gen_castling_moves::<WHITE>(moves);
+        if self.turn == Color::White {
+            self.gen_pawn_moves::<true, ONLY_CAPS>(moves);
         } else {
-            self.gen_pawn_moves::<BLACK, ONLY_CAPS>(moves);
-            self.gen_castling_moves::<BLACK>(moves);
+            self.gen_pawn_moves::<false, ONLY_CAPS>(moves);
+        }
+        self.gen_castling_moves(moves);
+        match self.variant {
+            Variant::Crazyhouse if !ONLY_CAPS => self.gen_drops(moves),
+            //captures are compulsory
+            Variant::Antichess if moves.iter().any(|m| m.0.cap()) => moves.retain(|m| m.cap()),
+            _ => (),
         }
     }
 
+    /// The legal moves in generation order.
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        let mut moves = MoveList::new();
+        self.gen_moves::<false>(&mut moves);
+        let mut legal = Vec::with_capacity(moves.len());
+        for om in moves.iter() {
+            if self.make_move(om.0) {
+                self.unmake_move();
+                legal.push(om.0);
+            }
+        }
+        legal
+    }
 
     // #[inline(never)]
-    pub fn in_check(&self, us: usize) -> bool {
-        let them = us ^ 1;
-        let mask = self.pieces[us][KINGX];
-        let sq = mask.trailing_zeros() as u8;
+    pub fn in_check(&self, us: Color) -> bool {
+        let them = !us;
+        let sq = match self.king_square(us) {
+            Some(sq) if self.variant != Variant::Antichess => sq,
+            _ => return false,
+        };
+        //kings can not capture each other in Atomic, so touching kings are safe
+        if self.variant == Variant::Atomic && self.kings_touch() { return false }
+        let mask = sq.bb();
         let mut bb = 0;
 
-        if them == WHITEX {
-            let pwns = self.pieces[WHITEX][PAWNX];
+        let pwns = self.pieces[them][PieceType::Pawn];
+        if them == Color::White {
             bb |= (pwns & !FILE_A) << 7 | (pwns & !FILE_H) << 9;
         } else {
-            let pwns = self.pieces[BLACKX][PAWNX];
             bb |= (pwns & !FILE_A) >> 9 | (pwns & !FILE_H) >> 7;
         }
         bb &= mask;
-        bb |= ATTK_TBL.king_attacks(sq) & self.pieces[them][KINGX];
-        bb |= ATTK_TBL.knight_attacks(sq) & self.pieces[them][KNIGHTX];
+        bb |= ATTK_TBL.king_attacks(sq) & self.pieces[them][PieceType::King];
+        bb |= ATTK_TBL.knight_attacks(sq) & self.pieces[them][PieceType::Knight];
 
         let blockers = self.all_ocupied();
         let bp = ATTK_TBL.bishop_attacks(sq, blockers);
         let rk = ATTK_TBL.rook_attacks(sq, blockers);
 
-        bb |= bp & self.pieces[them][BISHOPX] | rk & self.pieces[them][ROOKX];
-        bb |= (bp | rk) & self.pieces[them][QUEENX];
+        bb |= bp & self.pieces[them][PieceType::Bishop] | rk & self.pieces[them][PieceType::Rook];
+        bb |= (bp | rk) & self.pieces[them][PieceType::Queen];
         bb != 0
     }
 
-    pub fn attacked(&self, sidex: usize) -> BitBoard {
+    pub fn attacked(&self, side: Color) -> BitBoard {
+        self.attacked_through(side, self.all_ocupied())
+    }
+
+    /// Squares `side` attacks when only `blockers` stop the sliders.
+    pub fn attacked_through(&self, side: Color, blockers: BitBoard) -> BitBoard {
         let mut bb = 0;
-        if sidex == WHITEX {
-            let pwns = self.pieces[WHITEX][PAWNX];
+        let pwns = self.pieces[side][PieceType::Pawn];
+        if side == Color::White {
             bb |=(pwns & !FILE_A) << 7 | (pwns & !FILE_H) << 9 ;
         } else {
-            let pwns = self.pieces[BLACKX][PAWNX];
             bb |= (pwns & !FILE_A) >> 9 | (pwns & !FILE_H) >> 7;
         }
 
-        for sq in self.pieces[sidex][KNIGHTX].bits() {
+        let pieces = &self.pieces[side];
+        for sq in pieces[PieceType::Knight].bits() {
             bb |= ATTK_TBL.knight_attacks(sq);
         }
-        for sq in self.pieces[sidex][KINGX].bits() {
+        for sq in pieces[PieceType::King].bits() {
             bb |= ATTK_TBL.king_attacks(sq);
         }
-        let blockers = self.all_ocupied();
-        for sq in self.pieces[sidex][BISHOPX].bits() {
+        for sq in pieces[PieceType::Bishop].bits() {
             bb |= ATTK_TBL.bishop_attacks(sq, blockers);
         }
-        for sq in self.pieces[sidex][ROOKX].bits() {
+        for sq in pieces[PieceType::Rook].bits() {
             bb |= ATTK_TBL.rook_attacks(sq, blockers);
         }
-        for sq in self.pieces[sidex][QUEENX].bits() {
+        for sq in pieces[PieceType::Queen].bits() {
             bb |= ATTK_TBL.bishop_attacks(sq, blockers);
             bb |= ATTK_TBL.rook_attacks(sq, blockers);
         }
 
         bb
     }
+}
 
-    pub fn mobility<const COLOR: usize>(&self) -> u8 {
-        let knights = self.pieces[COLOR][KNIGHTX];
-        let my_pieces = self.occupied[COLOR];
-        let l1 = (knights >> 1) & 0x7f7f7f7f7f7f7f7f;
-        let l2 = (knights >> 2) & 0x3f3f3f3f3f3f3f3f;
-        let r1 = (knights << 1) & 0xfefefefefefefefe;
-        let r2 = (knights << 2) & 0xfcfcfcfcfcfcfcfc;
-        let h1 = l1 | r1;
-        let h2 = l2 | r2;
-        let mut n = (((h1<<16) | (h1>>16) | (h2<<8) | (h2>>8)) & !my_pieces).count_ones();
-        let blockers = self.all_ocupied();
-        for sq in self.pieces[COLOR][BISHOPX].bits() {
-            n += ATTK_TBL.bishop_attacks(sq, blockers).count_ones();
-        }
-        for sq in self.pieces[COLOR][ROOKX].bits() {
-            n += ATTK_TBL.rook_attacks(sq, blockers).count_ones();
-        }
-        for sq in self.pieces[COLOR][QUEENX].bits() {
-            n += ATTK_TBL.bishop_attacks(sq, blockers).count_ones();
-            n += ATTK_TBL.rook_attacks(sq, blockers).count_ones();
-        }
-
-        n as u8
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_encoding() {
+        assert_eq!(std::mem::size_of::<Move>(), 2);
+        let sq = |s: &str| s.parse::<Square>().unwrap();
+        let m = Move::new_prom(sq("b7"), sq("a8"), true, PieceType::Knight);
+        assert_eq!((m.from(), m.to(), m.prom(), m.cap()), (sq("b7"), sq("a8"), Some(PieceType::Knight), true));
+        assert_eq!(format!("{:?}", m), "Move(b7a8n, PromotionCapture)");
+        let k = Move::new_prom(sq("e7"), sq("e8"), false, PieceType::King);
+        assert_eq!((k.prom(), k.cap(), k.kind()), (Some(PieceType::King), false, MoveKind::Promotion));
+        let d = Move::new_drop(PieceType::Queen, sq("d4"));
+        assert_eq!((d.dropped(), d.to(), d.cap(), d.kind()), (PieceType::Queen, sq("d4"), false, MoveKind::Drop));
+        assert!(Move::new_enpassant(sq("e5"), sq("d6")).cap());
+        assert_eq!(format!("{:?}", Move::new()), "Move(null)");
+
+        let mut pos = Position::new();
+        pos.load_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(Move::from_uci(&pos, "e1g1").map(|m| m.kind()), Ok(MoveKind::Castle));
+        assert!(Move::from_uci(&pos, "e1e3").is_err());
+        //pinned knight
+        pos.load_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert_eq!(Move::from_uci(&pos, "e2c3"), Err("illegal move e2c3".to_owned()));
     }
 }
@@ -0,0 +1,369 @@
+use super::{bitbrd::*, defs::INFINITY, pos::*};
+use super::types::{Color, Piece, PieceType, Square};
+use std::{fs::File, io::{self, Read, Write}, path::Path, sync::Arc};
+
+//HalfKP: our king square x (10 non-king pieces x 64 squares)
+pub const INPUTS: usize = 64 * 640;
+pub const HIDDEN: usize = 256;
+pub const L1: usize = 32;
+pub const L2: usize = 32;
+
+const MAGIC: u32 = 0x4E4E_4242; //"BBNN"
+const VERSION: u32 = 1;
+const WEIGHT_SHIFT: i32 = 6;
+const OUTPUT_SCALE: i32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Simd {
+    Scalar,
+    #[cfg(target_arch = "x86_64")]
+    Sse,
+    #[cfg(target_arch = "x86_64")]
+    Avx2,
+}
+
+impl Simd {
+    fn detect() -> Self {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") { return Simd::Avx2 }
+            if is_x86_feature_detected!("ssse3") { return Simd::Sse }
+        }
+        Simd::Scalar
+    }
+}
+
+pub struct Network {
+    ft_bias: Vec<i16>,
+    ft_weights: Vec<i16>,
+    l1_bias: Vec<i32>,
+    l1_weights: Vec<i8>,
+    l2_bias: Vec<i32>,
+    l2_weights: Vec<i8>,
+    out_bias: i32,
+    out_weights: Vec<i8>,
+    simd: Simd,
+}
+
+#[derive(Clone, Copy)]
+#[repr(align(32))]
+pub struct Accumulator {
+    pub v: [[i16; HIDDEN]; 2],
+    pub dirty: [bool; 2],
+}
+
+impl Accumulator {
+    pub fn new() -> Self {
+        Self { v: [[0; HIDDEN]; 2], dirty: [true; 2] }
+    }
+}
+
+impl Default for Accumulator {
+    fn default() -> Self { Self::new() }
+}
+
+fn read_vec<R: Read, T: Copy + Default, const N: usize>(
+    r: &mut R, n: usize, conv: fn([u8; N]) -> T
+) -> io::Result<Vec<T>> {
+    let mut out = vec![T::default(); n];
+    let mut buf = [0; N];
+    for x in out.iter_mut() {
+        r.read_exact(&mut buf)?;
+        *x = conv(buf);
+    }
+    Ok(out)
+}
+
+fn bad_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+}
+
+/// Index of `p` standing on `sq` as seen by `persp` with its king on `ksq`.
+pub fn feature(persp: Color, ksq: Square, p: Piece, sq: Square) -> usize {
+    let (ksq, sq) = (ksq.relative(persp), sq.relative(persp));
+    let pidx = p.kind().index() * 2 + (p.color() != persp) as usize;
+    ksq.index() * 640 + pidx * 64 + sq.index()
+}
+
+impl Network {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut r = io::BufReader::new(File::open(path)?);
+        let header = read_vec(&mut r, 2, u32::from_le_bytes)?;
+        if header[0] != MAGIC { return Err(bad_data("not a network file")) }
+        if header[1] != VERSION { return Err(bad_data("unsupported network version")) }
+
+        let net = Self {
+            ft_bias: read_vec(&mut r, HIDDEN, i16::from_le_bytes)?,
+            ft_weights: read_vec(&mut r, INPUTS * HIDDEN, i16::from_le_bytes)?,
+            l1_bias: read_vec(&mut r, L1, i32::from_le_bytes)?,
+            l1_weights: read_vec(&mut r, L1 * 2 * HIDDEN, i8::from_le_bytes)?,
+            l2_bias: read_vec(&mut r, L2, i32::from_le_bytes)?,
+            l2_weights: read_vec(&mut r, L2 * L1, i8::from_le_bytes)?,
+            out_bias: read_vec(&mut r, 1, i32::from_le_bytes)?[0],
+            out_weights: read_vec(&mut r, L2, i8::from_le_bytes)?,
+            simd: Simd::detect(),
+        };
+        if r.read(&mut [0])? != 0 { return Err(bad_data("trailing data in network file")) }
+        Ok(net)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = io::BufWriter::new(File::create(path)?);
+        w.write_all(&MAGIC.to_le_bytes())?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        for x in self.ft_bias.iter().chain(self.ft_weights.iter()) {
+            w.write_all(&x.to_le_bytes())?;
+        }
+        for x in self.l1_bias.iter() { w.write_all(&x.to_le_bytes())?; }
+        for x in self.l1_weights.iter() { w.write_all(&x.to_le_bytes())?; }
+        for x in self.l2_bias.iter() { w.write_all(&x.to_le_bytes())?; }
+        for x in self.l2_weights.iter() { w.write_all(&x.to_le_bytes())?; }
+        w.write_all(&self.out_bias.to_le_bytes())?;
+        for x in self.out_weights.iter() { w.write_all(&x.to_le_bytes())?; }
+        w.flush()
+    }
+
+    fn ft_row(&self, idx: usize) -> &[i16] {
+        &self.ft_weights[idx * HIDDEN..(idx + 1) * HIDDEN]
+    }
+
+    fn add_feature(&self, acc: &mut [i16; HIDDEN], idx: usize) {
+        let w = self.ft_row(idx);
+        for (a, w) in acc.iter_mut().zip(w.iter()) {
+            *a = a.wrapping_add(*w);
+        }
+    }
+
+    fn sub_feature(&self, acc: &mut [i16; HIDDEN], idx: usize) {
+        let w = self.ft_row(idx);
+        for (a, w) in acc.iter_mut().zip(w.iter()) {
+            *a = a.wrapping_sub(*w);
+        }
+    }
+
+    fn dot(&self, input: &[u8], weights: &[i8]) -> i32 {
+        debug_assert_eq!(input.len(), weights.len());
+        debug_assert!(input.len().is_multiple_of(32));
+        match self.simd {
+            #[cfg(target_arch = "x86_64")]
+            Simd::Avx2 => unsafe { simd::dot_avx2(input, weights) },
+            #[cfg(target_arch = "x86_64")]
+            Simd::Sse => unsafe { simd::dot_ssse3(input, weights) },
+            Simd::Scalar => dot_scalar(input, weights),
+        }
+    }
+
+    fn affine<const OUT: usize>(&self, input: &[u8], weights: &[i8], bias: &[i32]) -> [u8; OUT] {
+        let n = input.len();
+        let mut out = [0; OUT];
+        for (o, x) in out.iter_mut().enumerate() {
+            let sum = bias[o] + self.dot(input, &weights[o * n..(o + 1) * n]);
+            *x = (sum >> WEIGHT_SHIFT).clamp(0, 127) as u8;
+        }
+        out
+    }
+
+    pub fn evaluate(&self, acc: &Accumulator, stm: Color) -> i16 {
+        let mut input = [0u8; 2 * HIDDEN];
+        for (half, persp) in [stm, !stm].iter().enumerate() {
+            for (x, v) in input[half * HIDDEN..].iter_mut().zip(acc.v[*persp].iter()) {
+                *x = (*v).clamp(0, 127) as u8;
+            }
+        }
+        let h1 = self.affine::<L1>(&input, &self.l1_weights, &self.l1_bias);
+        let h2 = self.affine::<L2>(&h1, &self.l2_weights, &self.l2_bias);
+        let out = self.out_bias + self.dot(&h2, &self.out_weights);
+        (out / OUTPUT_SCALE).clamp(-(INFINITY as i32) / 2, INFINITY as i32 / 2) as i16
+    }
+}
+
+fn dot_scalar(input: &[u8], weights: &[i8]) -> i32 {
+    input.iter().zip(weights.iter()).map(|(a, b)| *a as i32 * *b as i32).sum()
+}
+
+#[cfg(target_arch = "x86_64")]
+mod simd {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn dot_avx2(input: &[u8], weights: &[i8]) -> i32 {
+        let ones = _mm256_set1_epi16(1);
+        let mut sum = _mm256_setzero_si256();
+        for i in (0..input.len()).step_by(32) {
+            let a = _mm256_loadu_si256(input.as_ptr().add(i) as *const __m256i);
+            let b = _mm256_loadu_si256(weights.as_ptr().add(i) as *const __m256i);
+            let prod = _mm256_madd_epi16(_mm256_maddubs_epi16(a, b), ones);
+            sum = _mm256_add_epi32(sum, prod);
+        }
+        let sum128 = _mm_add_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256(sum, 1));
+        hsum128(sum128)
+    }
+
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn dot_ssse3(input: &[u8], weights: &[i8]) -> i32 {
+        let ones = _mm_set1_epi16(1);
+        let mut sum = _mm_setzero_si128();
+        for i in (0..input.len()).step_by(16) {
+            let a = _mm_loadu_si128(input.as_ptr().add(i) as *const __m128i);
+            let b = _mm_loadu_si128(weights.as_ptr().add(i) as *const __m128i);
+            sum = _mm_add_epi32(sum, _mm_madd_epi16(_mm_maddubs_epi16(a, b), ones));
+        }
+        hsum128(sum)
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn hsum128(x: __m128i) -> i32 {
+        let hi = _mm_shuffle_epi32(x, 0b01_00_11_10);
+        let x = _mm_add_epi32(x, hi);
+        let hi = _mm_shuffle_epi32(x, 0b10_11_00_01);
+        _mm_cvtsi128_si32(_mm_add_epi32(x, hi))
+    }
+}
+
+impl Position {
+    pub fn set_network(&mut self, net: Option<Arc<Network>>) {
+        self.nnue = net;
+        self.nnue_acc.dirty = [true; 2];
+        self.nnue_refresh();
+    }
+
+    /// Rebuilds the perspectives that were invalidated by a king move.
+    pub fn nnue_refresh(&mut self) {
+        if !self.nnue_acc.dirty[Color::White] && !self.nnue_acc.dirty[Color::Black] { return }
+        let net = match &self.nnue {
+            Some(net) => net.clone(),
+            None => return,
+        };
+        for persp in Color::ALL {
+            if !self.nnue_acc.dirty[persp] { continue; }
+            let ksq = self.king_square(persp).expect("no king");
+            let acc = &mut self.nnue_acc.v[persp];
+            acc.copy_from_slice(&net.ft_bias);
+            for c in Color::ALL {
+                for &pt in &PieceType::ALL[..PieceType::King.index()] {
+                    for sq in self.pieces[c][pt].bits() {
+                        let p = Piece::new(pt, c);
+                        net.add_feature(acc, feature(persp, ksq, p, sq));
+                    }
+                }
+            }
+            self.nnue_acc.dirty[persp] = false;
+        }
+    }
+
+    pub(super) fn nnue_add(&mut self, p: Piece, sq: Square) {
+        if let Some(net) = &self.nnue {
+            if p.kind() == PieceType::King { return }
+            for persp in Color::ALL {
+                if self.nnue_acc.dirty[persp] { continue; }
+                let ksq = self.king_square(persp).expect("no king");
+                net.add_feature(&mut self.nnue_acc.v[persp], feature(persp, ksq, p, sq));
+            }
+        }
+    }
+
+    pub(super) fn nnue_sub(&mut self, p: Piece, sq: Square) {
+        if let Some(net) = &self.nnue {
+            if p.kind() == PieceType::King { return }
+            for persp in Color::ALL {
+                if self.nnue_acc.dirty[persp] { continue; }
+                let ksq = self.king_square(persp).expect("no king");
+                net.sub_feature(&mut self.nnue_acc.v[persp], feature(persp, ksq, p, sq));
+            }
+        }
+    }
+
+    pub(super) fn nnue_move(&mut self, p: Piece, from: Square, to: Square) {
+        if self.nnue.is_none() { return }
+        if p.kind() == PieceType::King {
+            self.nnue_acc.dirty[p.color()] = true;
+        } else {
+            self.nnue_sub(p, from);
+            self.nnue_add(p, to);
+        }
+    }
+
+    pub fn nnue_eval(&self) -> Option<i16> {
+        let net = self.nnue.as_ref()?;
+        debug_assert!(!self.nnue_acc.dirty[Color::White] && !self.nnue_acc.dirty[Color::Black]);
+        Some(net.evaluate(&self.nnue_acc, self.turn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{eval::Backend, movgen::*, perft::POSITIONS};
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    fn random_net() -> Network {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let mut i16s = |n: usize, r: i16| (0..n).map(|_| rng.gen_range(-r..=r)).collect::<Vec<_>>();
+        let ft_bias = i16s(HIDDEN, 64);
+        let ft_weights = i16s(INPUTS * HIDDEN, 16);
+        let mut i8s = |n: usize| (0..n).map(|_| rng.gen_range(-64..=64)).collect::<Vec<i8>>();
+        Network {
+            ft_bias, ft_weights,
+            l1_bias: vec![100; L1], l1_weights: i8s(L1 * 2 * HIDDEN),
+            l2_bias: vec![-50; L2], l2_weights: i8s(L2 * L1),
+            out_bias: 7, out_weights: i8s(L2),
+            simd: Simd::detect(),
+        }
+    }
+
+    #[test]
+    fn incremental_matches_refresh() {
+        let net = Arc::new(random_net());
+        let mut pos = Position::new();
+        for fen in POSITIONS.iter() {
+            pos.load_fen(fen);
+            pos.set_network(Some(net.clone()));
+            let t = pos.eval_trace();
+            assert_eq!((t.backend, t.score), (Backend::Nnue, pos.eval()));
+            let mut moves = MoveList::new();
+            pos.gen_moves::<false>(&mut moves);
+            let before = pos.nnue_acc.v;
+            for m in moves.iter() {
+                if !pos.make_move(m.0) { continue; }
+                let (inc, score) = (pos.nnue_acc.v, pos.nnue_eval());
+                pos.nnue_acc.dirty = [true; 2];
+                pos.nnue_refresh();
+                assert!(inc == pos.nnue_acc.v, "{} after {}", fen, m.0);
+                assert_eq!(score, pos.nnue_eval());
+                pos.unmake_move();
+                assert!(before == pos.nnue_acc.v);
+            }
+        }
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        let mut net = random_net();
+        let mut pos = Position::new();
+        pos.load_fen(POSITIONS[1]);
+        let net_arc = Arc::new(random_net());
+        pos.set_network(Some(net_arc));
+        let expected = {
+            net.simd = Simd::Scalar;
+            net.evaluate(&pos.nnue_acc, Color::White)
+        };
+        net.simd = Simd::detect();
+        assert_eq!(net.evaluate(&pos.nnue_acc, Color::White), expected);
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("ssse3") {
+            net.simd = Simd::Sse;
+            assert_eq!(net.evaluate(&pos.nnue_acc, Color::White), expected);
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let net = random_net();
+        let path = std::env::temp_dir().join("bitbased_test.nnue");
+        net.save(&path).unwrap();
+        let loaded = Network::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(net.ft_weights == loaded.ft_weights && net.l1_weights == loaded.l1_weights);
+        assert_eq!(net.out_bias, loaded.out_bias);
+    }
+}
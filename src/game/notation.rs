@@ -0,0 +1,137 @@
+use super::{movgen::*, pos::*};
+use super::types::{File, PieceType, Rank, Square};
+
+fn san_letter(pt: PieceType) -> char { pt.char().to_ascii_uppercase() }
+
+impl Position {
+    /// Standard algebraic notation of the legal move `m`, with `+` or `#` appended.
+    pub fn to_san(&mut self, m: Move) -> String {
+        let mut s = String::new();
+        if m.kind() == MoveKind::Castle {
+            s.push_str(if m.to() > m.from() { "O-O" } else { "O-O-O" });
+        } else if m.kind() == MoveKind::Drop {
+            s.push(san_letter(m.dropped()));
+            s.push('@');
+            s.push_str(&m.to().to_string());
+        } else {
+            let pt = self.moved_piece(m).kind();
+            if pt == PieceType::Pawn {
+                if m.cap() { s.push(m.from().file().char()); }
+            } else {
+                s.push(san_letter(pt));
+                //other pieces of the same kind that could go there too
+                let others: Vec<Square> = self.legal_moves().into_iter()
+                    .filter(|o| o.to() == m.to() && o.from() != m.from() && o.kind() != MoveKind::Castle
+                        && o.kind() != MoveKind::Drop && self.moved_piece(*o).kind() == pt)
+                    .map(|o| o.from()).collect();
+                if !others.is_empty() {
+                    let same_file = others.iter().any(|sq| sq.file() == m.from().file());
+                    let same_rank = others.iter().any(|sq| sq.rank() == m.from().rank());
+                    if !same_file {
+                        s.push(m.from().file().char());
+                    } else if !same_rank {
+                        s.push(m.from().rank().char());
+                    } else {
+                        s.push_str(&m.from().to_string());
+                    }
+                }
+            }
+            if m.cap() { s.push('x'); }
+            s.push_str(&m.to().to_string());
+            if let Some(pt) = m.prom() {
+                s.push('=');
+                s.push(san_letter(pt));
+            }
+        }
+        if self.make_move(m) {
+            if self.in_check(self.turn) {
+                s.push(if self.legal_moves().is_empty() { '#' } else { '+' });
+            }
+            self.unmake_move();
+        }
+        s
+    }
+
+    /// Finds the legal move written in SAN. Check marks, annotations, missing or extra
+    /// disambiguation and `0-0` style castling are accepted.
+    pub fn parse_san(&mut self, san: &str) -> Result<Move, String> {
+        let s = san.trim().trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+        let legal = self.legal_moves();
+        let found: Vec<Move> = match s.as_str() {
+            "O-O" | "O-O-O" => legal.into_iter()
+                .filter(|m| m.kind() == MoveKind::Castle && (m.to() > m.from()) == (s == "O-O")).collect(),
+            _ if s.contains('@') => {
+                let (piece, to) = s.split_at(s.find('@').unwrap());
+                let pt = if piece.is_empty() { PieceType::Pawn } else { piece.parse()? };
+                let to: Square = to[1..].parse().map_err(|_| format!("bad square in {}", san))?;
+                legal.into_iter().filter(|m| m.kind() == MoveKind::Drop && m.dropped() == pt && m.to() == to)
+                    .collect()
+            }
+            _ => {
+                let mut rest: String = s.chars().filter(|&c| c != 'x' && c != '-' && c != '=').collect();
+                let pt = match rest.chars().next() {
+                    Some(c @ ('N' | 'B' | 'R' | 'Q' | 'K')) => {
+                        rest.remove(0);
+                        c.to_string().parse()?
+                    }
+                    _ => PieceType::Pawn,
+                };
+                let prom = match rest.chars().last() {
+                    Some(c) if c.is_ascii_alphabetic() && File::from_char(c).is_none() => {
+                        rest.pop();
+                        Some(c.to_string().parse::<PieceType>()?)
+                    }
+                    _ => None,
+                };
+                if rest.len() < 2 || !rest.is_char_boundary(rest.len() - 2) {
+                    return Err(format!("bad move {}", san))
+                }
+                let (hint, to) = rest.split_at(rest.len() - 2);
+                let to: Square = to.parse().map_err(|_| format!("bad square in {}", san))?;
+                let file = hint.chars().find_map(File::from_char);
+                let rank = hint.chars().find_map(Rank::from_char);
+                legal.into_iter().filter(|m| {
+                    m.kind() != MoveKind::Castle && m.kind() != MoveKind::Drop && m.to() == to && m.prom() == prom
+                        && self.moved_piece(*m).kind() == pt
+                        && file.is_none_or(|f| m.from().file() == f)
+                        && rank.is_none_or(|r| m.from().rank() == r)
+                }).collect()
+            }
+        };
+        match found.as_slice() {
+            [m] => Ok(*m),
+            [] => Err(format!("illegal move {}", san)),
+            _ => Err(format!("ambiguous move {}", san)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn san_round_trip() {
+        let mut pos = Position::new();
+        for fen in super::super::perft::POSITIONS.iter() {
+            pos.load_fen(fen);
+            for m in pos.legal_moves() {
+                let san = pos.to_san(m);
+                assert_eq!(pos.parse_san(&san), Ok(m), "{} in {}", san, fen);
+                assert_eq!(Move::from_uci(&pos, &m.to_uci(false)), Ok(m));
+            }
+        }
+        pos.load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let castle = pos.parse_san("0-0-0").unwrap();
+        assert_eq!((pos.to_san(castle), Move::from_uci(&pos, "e1a1")), ("O-O-O".to_owned(), Ok(castle)));
+        pos.load_fen("7k/8/8/4R3/8/8/8/R3R1K1 w - - 0 1");
+        let san: Vec<String> = ["e1d1", "e5e3", "e5e8"].iter()
+            .map(|m| { let m = Move::from_uci(&pos, m).unwrap(); pos.to_san(m) }).collect();
+        assert_eq!(san, ["Red1", "R5e3", "Re8+"]);
+        assert_eq!(pos.parse_san("R1e3"), Move::from_uci(&pos, "e1e3"));
+        assert!(pos.parse_san("Rd1").unwrap_err().starts_with("ambiguous"));
+        pos.load_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let mate = Move::from_uci(&pos, "a1a8").unwrap();
+        assert_eq!(pos.to_san(mate), "Ra8#");
+    }
+}
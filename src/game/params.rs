@@ -0,0 +1,165 @@
+use std::{fmt::Write as FmtWrite, fs, io, path::Path};
+
+/// A tunable evaluation weight: a scalar or an (up to two dimensional) array of them.
+pub trait Param {
+    fn values(&self) -> &[i16];
+    fn values_mut(&mut self) -> &mut [i16];
+    fn write_rust(&self, s: &mut String, indent: usize);
+}
+
+impl Param for i16 {
+    fn values(&self) -> &[i16] { std::slice::from_ref(self) }
+    fn values_mut(&mut self) -> &mut [i16] { std::slice::from_mut(self) }
+    fn write_rust(&self, s: &mut String, _: usize) { write!(s, "{}", self).unwrap(); }
+}
+
+impl<const N: usize> Param for [i16; N] {
+    fn values(&self) -> &[i16] { self }
+    fn values_mut(&mut self) -> &mut [i16] { self }
+    fn write_rust(&self, s: &mut String, indent: usize) {
+        if N <= 8 {
+            let vals: Vec<String> = self.iter().map(|v| v.to_string()).collect();
+            write!(s, "[ {} ]", vals.join(", ")).unwrap();
+            return;
+        }
+        s.push('[');
+        for row in self.chunks(8) {
+            let vals: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+            write!(s, "\n{:w$}{},", "", vals.join(", "), w = indent + 4).unwrap();
+        }
+        write!(s, "\n{:w$}]", "", w = indent).unwrap();
+    }
+}
+
+impl<const N: usize, const M: usize> Param for [[i16; N]; M] {
+    fn values(&self) -> &[i16] { self.as_flattened() }
+    fn values_mut(&mut self) -> &mut [i16] { self.as_flattened_mut() }
+    fn write_rust(&self, s: &mut String, indent: usize) {
+        s.push('[');
+        for row in self.iter() {
+            write!(s, "\n{:w$}", "", w = indent + 4).unwrap();
+            row.write_rust(s, indent + 4);
+            s.push(',');
+        }
+        write!(s, "\n{:w$}]", "", w = indent).unwrap();
+    }
+}
+
+macro_rules! eval_params {
+    ($($name:ident: $ty:ty,)*) => {
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct EvalParams {
+            $(pub $name: $ty,)*
+        }
+
+        impl EvalParams {
+            pub fn for_each(&self, mut f: impl FnMut(&'static str, &dyn Param)) {
+                $(f(stringify!($name), &self.$name);)*
+            }
+
+            pub fn for_each_mut(&mut self, mut f: impl FnMut(&'static str, &mut dyn Param)) {
+                $(f(stringify!($name), &mut self.$name);)*
+            }
+        }
+    };
+}
+
+eval_params! {
+    material: [[i16; 5]; 2],
+    pst: [[i16; 64]; 4],
+    king_location: [[i16; 64]; 2],
+    king_shielded: i16,
+    king_attack_weight: [i16; 6],
+    safe_check_weight: [i16; 6],
+    king_zone_square: i16,
+    king_semi_open_file: i16,
+    king_open_file: i16,
+    safety_table: [i16; 100],
+    passed_pawn: [i16; 8],
+    passer_king_weight: [i16; 8],
+    passer_their_king: i16,
+    passer_our_king: i16,
+    unstoppable_passer: i16,
+    isolated_pawn: i16,
+    doubled_pawn: i16,
+    backward_pawn: i16,
+    connected_pawn: [i16; 8],
+    phalanx_pawn: [i16; 8],
+    candidate_passer: [i16; 8],
+    knight_mobility: [[i16; 9]; 2],
+    bishop_mobility: [[i16; 14]; 2],
+    rook_mobility: [[i16; 15]; 2],
+    queen_mobility: [[i16; 28]; 2],
+    knight_outpost: [i16; 2],
+    bishop_outpost: [i16; 2],
+    bad_bishop_pawn: [i16; 2],
+    rook_on_seventh: [i16; 2],
+    trapped_bishop: [i16; 2],
+    trapped_rook: [i16; 2],
+    rook_open_file: i16,
+    rook_semi_open_file: i16,
+    queen_open_file: i16,
+    queen_semi_open_file: i16,
+    castle_rights: i16,
+    bishop_pair: i16,
+}
+
+impl EvalParams {
+    /// Number of individual weights.
+    #[allow(clippy::len_without_is_empty)] //never empty
+    pub fn len(&self) -> usize {
+        let mut n = 0;
+        self.for_each(|_, p| n += p.values().len());
+        n
+    }
+
+    /// Reads `name v1 v2 ...` lines; names that are missing keep their current values.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let text = fs::read_to_string(path)?;
+        let bad = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let mut it = line.split_whitespace();
+            let name = it.next().unwrap();
+            let vals = it.map(|v| v.parse::<i16>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| bad(format!("{}: {}", name, e)))?;
+            let mut found = Ok(false);
+            self.for_each_mut(|n, p| {
+                if n != name { return }
+                let dst = p.values_mut();
+                found = if dst.len() == vals.len() {
+                    dst.copy_from_slice(&vals);
+                    Ok(true)
+                } else {
+                    Err(bad(format!("{}: expected {} values", name, dst.len())))
+                };
+            });
+            if !found? { return Err(bad(format!("unknown parameter {}", name))) }
+        }
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut s = String::new();
+        self.for_each(|name, p| {
+            s.push_str(name);
+            for v in p.values() { write!(s, " {}", v).unwrap(); }
+            s.push('\n');
+        });
+        fs::write(path, s)
+    }
+
+    /// The weights as a `weights.rs` source file.
+    pub fn to_rust(&self) -> String {
+        let mut s = String::from("// Evaluation weights. Generated by `bitbased tune`.\n\n");
+        s.push_str("use super::params::EvalParams;\n\n");
+        s.push_str("pub const DEFAULT_PARAMS: EvalParams = EvalParams {\n");
+        self.for_each(|name, p| {
+            write!(s, "    {}: ", name).unwrap();
+            p.write_rust(&mut s, 4);
+            s.push_str(",\n");
+        });
+        s.push_str("};\n");
+        s
+    }
+}
@@ -0,0 +1,163 @@
+use super::{bitbrd::*, eval::*, pos::*};
+use super::types::{Color, PieceType, Square};
+use std::cell::Cell;
+
+pub const PAWN_ENTRIES: usize = 1 << 16;
+
+#[derive(Clone, Copy)]
+pub struct PawnEntry {
+    pub key: u64,
+    pub score: [i16; 2],
+    pub passed: [BitBoard; 2],
+}
+
+impl PawnEntry {
+    fn new() -> Self {
+        Self { key: 0, score: [0; 2], passed: [0; 2] }
+    }
+}
+
+/// Pawn structure cache keyed by `Position::pawn_key`. Entries live in `Cell`s
+/// so that `eval` can fill it through a shared reference.
+pub struct PawnTable {
+    entries: Vec<Cell<PawnEntry>>,
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![Cell::new(PawnEntry::new()); PAWN_ENTRIES],
+        }
+    }
+
+    pub fn clear(&self) {
+        for e in self.entries.iter() {
+            e.set(PawnEntry::new());
+        }
+    }
+
+    pub fn probe(&self, pk: u64) -> Option<PawnEntry> {
+        let e = self.entries[pk as usize % PAWN_ENTRIES].get();
+        if e.key == pk { Some(e) } else { None }
+    }
+
+    pub fn store(&self, e: PawnEntry) {
+        self.entries[e.key as usize % PAWN_ENTRIES].set(e);
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> Self { Self::new() }
+}
+
+impl Position {
+    /// Looks the pawn structure up in the pawn table, evaluating it on a miss.
+    pub fn pawn_entry(&self, table: &PawnTable) -> PawnEntry {
+        if let Some(e) = table.probe(self.pawn_key) {
+            return e;
+        }
+        let e = self.pawn_structure(&mut NoTrace);
+        table.store(e);
+        e
+    }
+
+    /// Everything about the pawns that does not depend on other pieces.
+    pub fn pawn_structure<T: Tracer>(&self, t: &mut T) -> PawnEntry {
+        let p = &*self.params;
+        let mut e = PawnEntry { key: self.pawn_key, ..PawnEntry::new() };
+        for us in Color::ALL {
+            let them = !us;
+            let (ours, theirs) = (self.pieces[us][PieceType::Pawn], self.pieces[them][PieceType::Pawn]);
+            let rank_of = |sq: Square| sq.rank().relative(us);
+            let (our_front, their_front) = if us == Color::White {
+                (north_fill(north_one(ours)), south_fill(south_one(theirs)))
+            } else {
+                (south_fill(south_one(ours)), north_fill(north_one(theirs)))
+            };
+            let their_attacks = pawn_attacks(theirs, them);
+            let our_span = pawn_attack_span(ours, us);
+
+            let passed = ours & !widen(their_front);
+            e.passed[us] = passed;
+            let mut v = 0;
+            for sq in passed.bits() {
+                v += p.passed_pawn[rank_of(sq)];
+            }
+            t.add(Term::PassedPawns, us, v);
+            let mut score = v;
+
+            let v = p.doubled_pawn * (our_front & ours).count_ones() as i16;
+            t.add(Term::DoubledPawns, us, v);
+            score += v;
+
+            let v = p.isolated_pawn * isolanis(ours).count_ones() as i16;
+            t.add(Term::IsolatedPawns, us, v);
+            score += v;
+
+            //the stop square is controlled by an enemy pawn and no pawn of ours can ever defend it
+            let stops = if us == Color::White { north_one(ours) } else { south_one(ours) };
+            let backward_stops = stops & their_attacks & !our_span;
+            let backward = if us == Color::White { south_one(backward_stops) } 
+                else { north_one(backward_stops) };
+            let v = p.backward_pawn * (backward & ours).count_ones() as i16;
+            t.add(Term::BackwardPawns, us, v);
+            score += v;
+
+            let defended = ours & pawn_attacks(ours, us);
+            let phalanx = ours & (west_one(ours) | east_one(ours));
+            let mut v = 0;
+            for sq in defended.bits() {
+                v += p.connected_pawn[rank_of(sq)];
+            }
+            for sq in phalanx.bits() {
+                v += p.phalanx_pawn[rank_of(sq)];
+            }
+            t.add(Term::ConnectedPawns, us, v);
+            score += v;
+
+            //not passed yet, but with an open file ahead and enough helpers to force it through
+            let mut v = 0;
+            for sq in (ours & !passed & !their_front).bits() {
+                let bb = sq.bb();
+                let sentries = theirs & pawn_attack_span(bb, us);
+                let neighbours = west_one(file_fill(bb)) | east_one(file_fill(bb));
+                let behind = if us == Color::White { south_fill(bb) } else { north_fill(bb) };
+                let helpers = ours & neighbours & widen(behind);
+                if helpers.count_ones() >= sentries.count_ones() {
+                    v += p.candidate_passer[rank_of(sq)];
+                }
+            }
+            t.add(Term::CandidatePawns, us, v);
+            score += v;
+
+            e.score[us] = score;
+        }
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{movgen::*, perft::POSITIONS, zobrist::ZOBRIST};
+
+    #[test]
+    fn pawn_key_and_cache() {
+        let (mut pos, table) = (Position::new(), PawnTable::new());
+        for fen in POSITIONS.iter() {
+            pos.load_fen(fen);
+            let mut moves = MoveList::new();
+            pos.gen_moves::<false>(&mut moves);
+            for m in moves.iter() {
+                if !pos.make_move(m.0) { continue; }
+                assert_eq!(pos.pawn_key, ZOBRIST.gen_pawn_key(&pos));
+                let cached = pos.pawn_entry(&table);
+                let fresh = pos.pawn_structure(&mut NoTrace);
+                assert_eq!(cached.score, fresh.score);
+                assert_eq!(cached.passed, fresh.passed);
+                pos.unmake_move();
+                assert_eq!(pos.pawn_key, ZOBRIST.gen_pawn_key(&pos));
+            }
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use super::{movgen::*, pos::*};
+use super::{movgen::*, pos::*, variant::Variant};
+use std::io;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
 
-pub const POSITIONS: [&'static str; 6] = [
+pub const POSITIONS: [&str; 6] = [
     "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
     "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 
     "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
@@ -9,6 +12,7 @@ pub const POSITIONS: [&'static str; 6] = [
     "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
 ];
 
+#[cfg(test)]
 pub const RESULTS: [[u64; 6]; 6] = [
     [20, 400, 8902, 197281, 4_865_609, 119_060_324],
     [48, 2039, 97_862, 4_085_603, 193_690_690, 8_031_647_685],
@@ -18,10 +22,47 @@ pub const RESULTS: [[u64; 6]; 6] = [
     [46, 2_079, 89_890, 3_894_594, 164_075_551, 6_923_051_137],
 ];
 
+const NODES_MASK: u64 = (1 << 56) - 1;
+
+/// Perft results cached by Zobrist key and remaining depth. Entries are two atomics
+/// with the key xored into the first, so that threads can share the table without locks.
+pub struct PerftTable {
+    entries: Vec<[AtomicU64; 2]>,
+}
+
+impl PerftTable {
+    pub fn with_size_mb(mb: usize) -> Self {
+        let n = (mb * 1024 * 1024 / std::mem::size_of::<[AtomicU64; 2]>()).max(1);
+        Self { entries: (0..n).map(|_| [AtomicU64::new(0), AtomicU64::new(0)]).collect() }
+    }
 
+    fn probe(&self, key: u64, depth: u8) -> Option<u64> {
+        let e = &self.entries[(key % self.entries.len() as u64) as usize];
+        let data = e[1].load(Ordering::Relaxed);
+        let stored = e[0].load(Ordering::Relaxed) ^ data;
+        (stored == key && data >> 56 == depth as u64).then_some(data & NODES_MASK)
+    }
+
+    fn store(&self, key: u64, depth: u8, nodes: u64) {
+        let e = &self.entries[(key % self.entries.len() as u64) as usize];
+        let data = (depth as u64) << 56 | nodes & NODES_MASK;
+        e[0].store(key ^ data, Ordering::Relaxed);
+        e[1].store(data, Ordering::Relaxed);
+    }
+}
 
 pub fn perft(p: &mut Position, depth: u8) -> u64 {
+    perft_with(p, depth, None)
+}
+
+/// Leaf nodes at `depth`, counting the legal moves at the last ply instead of making them.
+pub fn perft_with(p: &mut Position, depth: u8, tt: Option<&PerftTable>) -> u64 {
     if depth == 0 { return 1 }
+    if depth > 1 {
+        if let Some(nodes) = tt.and_then(|tt| tt.probe(p.key, depth)) {
+            return nodes
+        }
+    }
 
     let mut moves = MoveList::new();
     let mut nodes = 0;
@@ -29,14 +70,102 @@ pub fn perft(p: &mut Position, depth: u8) -> u64 {
     p.gen_moves::<false>(&mut moves);
     for m in moves.iter() {
         if p.make_move(m.0) {
-            nodes += perft(p, depth - 1);
+            nodes += if depth == 1 { 1 } else { perft_with(p, depth - 1, tt) };
             p.unmake_move();
         }
     }
-    
+
+    if depth > 1 {
+        if let Some(tt) = tt { tt.store(p.key, depth, nodes); }
+    }
     nodes
 }
 
+/// Node counts below each legal root move, with the root moves split among `threads`.
+pub fn divide(p: &mut Position, depth: u8, threads: usize, tt: Option<&PerftTable>) -> Vec<(Move, u64)> {
+    let mut moves = MoveList::new();
+    p.gen_moves::<false>(&mut moves);
+    let roots: Vec<Move> = moves.iter().map(|m| m.0).filter(|&m| {
+        let legal = p.make_move(m);
+        if legal { p.unmake_move(); }
+        legal
+    }).collect();
+    let (fen, variant) = (p.fen(), p.variant);
+    let next = AtomicUsize::new(0);
+    let counts: Vec<AtomicU64> = roots.iter().map(|_| AtomicU64::new(0)).collect();
+
+    std::thread::scope(|s| {
+        for _ in 0..threads.clamp(1, roots.len().max(1)) {
+            let (roots, counts, next, fen) = (&roots, &counts, &next, &fen);
+            s.spawn(move || {
+                let mut pos = Position::new();
+                pos.variant = variant;
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= roots.len() { break }
+                    pos.load_fen(fen);
+                    pos.make_move(roots[i]);
+                    let n = perft_with(&mut pos, depth.saturating_sub(1), tt);
+                    counts[i].store(n, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    roots.into_iter().zip(counts.into_iter().map(|c| c.into_inner())).collect()
+}
+
+/// Prints the divide output: one `move: nodes` line per root move and the total.
+pub fn run(p: &mut Position, depth: u8, threads: usize, hash_mb: usize) -> u64 {
+    let tt = (hash_mb > 0).then(|| PerftTable::with_size_mb(hash_mb));
+    let start = Instant::now();
+    let counts = divide(p, depth.max(1), threads, tt.as_ref());
+    let total: u64 = counts.iter().map(|c| c.1).sum();
+    for (m, n) in counts.iter() {
+        println!("{}: {}", m, n);
+    }
+    let ms = start.elapsed().as_millis().max(1);
+    println!();
+    println!("Nodes: {}", total);
+    println!("Time: {}ms, {} nps", ms, total as u128 * 1000 / ms);
+    total
+}
+
+/// A line of the standard perft suite: `fen ;D1 20 ;D2 400 ...`.
+pub fn parse_epd(line: &str) -> Option<(String, Vec<(u8, u64)>)> {
+    let mut parts = line.split(';');
+    let fen = parts.next()?.trim();
+    if fen.is_empty() || fen.starts_with('#') { return None }
+    let expected = parts.filter_map(|p| {
+        let mut it = p.split_whitespace();
+        let depth = it.next()?.strip_prefix('D')?.parse().ok()?;
+        Some((depth, it.next()?.parse().ok()?))
+    }).collect();
+    Some((fen.to_owned(), expected))
+}
+
+/// Checks every position of an EPD suite up to `max_depth` and returns the number of mismatches.
+pub fn run_suite(path: &str, variant: Variant, max_depth: u8, threads: usize, hash_mb: usize) -> io::Result<usize> {
+    let text = std::fs::read_to_string(path)?;
+    let tt = (hash_mb > 0).then(|| PerftTable::with_size_mb(hash_mb));
+    let mut pos = Position::new();
+    pos.variant = variant;
+    let (mut checked, mut failed) = (0, 0);
+    let start = Instant::now();
+    for (fen, expected) in text.lines().filter_map(parse_epd) {
+        pos.load_fen(&fen);
+        for &(depth, want) in expected.iter().filter(|e| e.0 <= max_depth) {
+            let got: u64 = divide(&mut pos, depth, threads, tt.as_ref()).iter().map(|c| c.1).sum();
+            checked += 1;
+            if got != want {
+                failed += 1;
+                println!("MISMATCH {} depth {}: expected {}, got {}", fen, depth, want, got);
+            }
+        }
+    }
+    println!("{} checks, {} mismatches, {}s", checked, failed, start.elapsed().as_secs());
+    Ok(failed)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -77,4 +206,50 @@ mod tests {
     fn position_six() {
         assert_eq!(perft(5, N), RESULTS[5][N-1]);
     }
+
+    #[test]
+    fn hashed_divide() {
+        let tt = PerftTable::with_size_mb(16);
+        let mut pos = Position::new();
+        for (fen, results) in POSITIONS.iter().zip(RESULTS.iter()) {
+            pos.load_fen(fen);
+            let counts = divide(&mut pos, 4, 4, Some(&tt));
+            assert_eq!(counts.len() as u64, results[0]);
+            assert_eq!(counts.iter().map(|c| c.1).sum::<u64>(), results[3]);
+        }
+        let (fen, expected) = parse_epd(&format!("{} ;D1 20 ;D2 400", POSITIONS[0])).unwrap();
+        assert_eq!((fen.as_str(), expected), (POSITIONS[0], vec![(1, 20), (2, 400)]));
+    }
+
+    #[test]
+    fn chess960() {
+        let mut pos = Position::new();
+        for (fen, nodes) in [
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 326672),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 667366),
+            ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", 382958),
+        ] {
+            pos.load_fen(fen);
+            assert_eq!(super::perft(&mut pos, 4), nodes, "{}", fen);
+        }
+        pos.load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let mut moves = MoveList::new();
+        pos.gen_moves::<false>(&mut moves);
+        let castles: Vec<_> = moves.iter().filter(|m| m.0.kind() == MoveKind::Castle)
+            .map(|m| (m.0.to_uci(false), m.0.to_uci(true))).collect();
+        assert_eq!(castles, [("e1g1".to_owned(), "e1h1".to_owned()), ("e1c1".to_owned(), "e1a1".to_owned())]);
+    }
+
+    #[test]
+    fn slider_backends() {
+        use super::super::magic_table::SliderBackend;
+        let default = ATTK_TBL.backend();
+        for backend in SliderBackend::ALL.iter().copied() {
+            if !ATTK_TBL.set_backend(backend) { continue }
+            for (p, results) in RESULTS.iter().enumerate().take(POSITIONS.len()) {
+                assert_eq!(perft(p, 4), results[3], "{} position {}", backend, p);
+            }
+        }
+        ATTK_TBL.set_backend(default);
+    }
 }
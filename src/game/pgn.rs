@@ -0,0 +1,170 @@
+use super::{movgen::Move, pos::Position, types::Color, variant::Variant};
+use std::io::{self, Write};
+
+const LINE_WIDTH: usize = 80;
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes `moves` played from `start` as one PGN game. The tags are written in the given
+/// order, followed by `Variant`, `SetUp` and `FEN` when the game needs them; the movetext
+/// ends with the value of the `Result` tag.
+pub fn write_game(out: &mut impl Write, tags: &[(&str, &str)], start: &Position, moves: &[Move]) -> io::Result<()> {
+    for (k, v) in tags {
+        writeln!(out, "[{} \"{}\"]", k, escape(v))?;
+    }
+    let fen = start.fen();
+    if start.variant != Variant::Standard {
+        writeln!(out, "[Variant \"{}\"]", start.variant)?;
+    }
+    if fen != start.variant.start_fen() {
+        writeln!(out, "[SetUp \"1\"]\n[FEN \"{}\"]", fen)?;
+    }
+    writeln!(out)?;
+
+    let mut pos = start.clone();
+    let mut tokens = Vec::with_capacity(moves.len() * 3 / 2 + 1);
+    for (i, &m) in moves.iter().enumerate() {
+        let number = 1 + (i + (start.turn == Color::Black) as usize) / 2;
+        if pos.turn == Color::White {
+            tokens.push(format!("{}.", number));
+        } else if i == 0 {
+            tokens.push(format!("{}...", number));
+        }
+        tokens.push(pos.to_san(m));
+        pos.make_move(m);
+    }
+    tokens.push(tags.iter().find(|t| t.0 == "Result").map_or("*", |t| t.1).to_owned());
+
+    let mut line = String::new();
+    for t in tokens {
+        if !line.is_empty() && line.len() + 1 + t.len() > LINE_WIDTH {
+            writeln!(out, "{}", line)?;
+            line.clear();
+        }
+        if !line.is_empty() { line.push(' '); }
+        line.push_str(&t);
+    }
+    writeln!(out, "{}\n", line)
+}
+
+/// A game read from PGN: the tags in file order, the position it starts from and its moves.
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub start: Position,
+    pub moves: Vec<Move>,
+}
+
+impl Game {
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.iter().find(|t| t.0 == key).map(|t| t.1.as_str())
+    }
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), String> {
+    let bad = || format!("bad tag [{}", line);
+    let inner = line.trim_end().strip_suffix(']').ok_or_else(bad)?;
+    let (key, value) = inner.split_once(char::is_whitespace).ok_or_else(bad)?;
+    let value = value.trim().strip_prefix('"').and_then(|v| v.strip_suffix('"')).ok_or_else(bad)?;
+    Ok((key.to_owned(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+/// Splits movetext into move tokens, dropping comments, variations, NAGs, move numbers,
+/// annotation marks and the result.
+fn move_tokens(text: &str) -> Vec<String> {
+    let (mut tokens, mut cur) = (Vec::new(), String::new());
+    let (mut comment, mut line_comment, mut variation) = (false, false, 0);
+    for ch in text.chars() {
+        match ch {
+            _ if line_comment => line_comment = ch != '\n',
+            '}' if comment => comment = false,
+            _ if comment => (),
+            '{' => comment = true,
+            ';' => line_comment = true,
+            '(' => variation += 1,
+            ')' => variation -= 1,
+            _ if variation > 0 => (),
+            _ if ch.is_whitespace() => tokens.push(std::mem::take(&mut cur)),
+            _ => cur.push(ch),
+        }
+    }
+    tokens.push(cur);
+    tokens.into_iter()
+        .filter(|t| !t.starts_with('$') && !["1-0", "0-1", "1/2-1/2", "*"].contains(&t.as_str()))
+        .map(|t| strip_move_number(&t).trim_end_matches(['!', '?']).to_owned())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// `e4` from `12.e4` or `12...e4`; digits without dots, as in `0-0`, stay.
+fn strip_move_number(t: &str) -> &str {
+    let rest = t.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() < t.len() && rest.starts_with('.') { rest.trim_start_matches('.') } else { t }
+}
+
+/// Reads the first game in `text`. A `Variant` tag picks the rules and a `FEN` tag the
+/// start position; moves may be in SAN or UCI notation.
+pub fn read_game(text: &str) -> Result<Game, String> {
+    let (mut tags, mut movetext) = (Vec::new(), String::new());
+    for line in text.lines().map(str::trim).filter(|l| !l.starts_with('%')) {
+        match line.strip_prefix('[') {
+            //a tag after the movetext starts the next game
+            Some(_) if !movetext.trim().is_empty() => break,
+            Some(tag) => tags.push(parse_tag(tag)?),
+            None => { movetext.push_str(line); movetext.push('\n'); }
+        }
+    }
+    let mut game = Game { tags, start: Position::new(), moves: Vec::new() };
+    if let Some(v) = game.tag("Variant") {
+        game.start.variant = v.parse()?;
+    }
+    let fen = game.tag("FEN").unwrap_or_else(|| game.start.variant.start_fen()).to_owned();
+    game.start.try_load_fen(&fen)?;
+
+    let mut pos = game.start.clone();
+    for (i, tok) in move_tokens(&movetext).iter().enumerate() {
+        let m = pos.parse_san(tok).or_else(|_| Move::from_uci(&pos, tok))
+            .map_err(|e| format!("move {}: {}", i + 1, e))?;
+        pos.make_move(m);
+        game.moves.push(m);
+    }
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_movetext() {
+        let mut start = Position::new();
+        start.load_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 b - - 0 1");
+        let mut pos = start.clone();
+        let moves: Vec<Move> = ["g8h8", "a1a8"].iter().map(|m| {
+            let m = Move::from_uci(&pos, m).unwrap();
+            pos.make_move(m);
+            m
+        }).collect();
+        let result = pos.status().result();
+        let mut out = Vec::new();
+        write_game(&mut out, &[("White", "a \"b\""), ("Result", result)], &start, &moves).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text, format!("[White \"a \\\"b\\\"\"]\n[Result \"1-0\"]\n\
+            [SetUp \"1\"]\n[FEN \"{}\"]\n\n1... Kh8 2. Ra8# 1-0\n\n", start.fen()));
+
+        let game = read_game(&text).unwrap();
+        assert_eq!((game.tag("White"), game.start.fen(), &game.moves), (Some("a \"b\""), start.fen(), &moves));
+    }
+
+    #[test]
+    fn reads_movetext() {
+        let text = "[Event \"?\"]\n[Result \"*\"]\n\n1. e4 {best by test} e5 (1... c5 2. Nf3) 2. Nf3!? $1 Nc6\n\
+            ; a comment\n3. Bb5 a6 4.Ba4 g8f6 5. 0-0 *\n\n[Event \"next\"]\n\n1. d4 *\n";
+        let game = read_game(text).unwrap();
+        let mut pos = game.start.clone();
+        let san: Vec<String> = game.moves.iter().map(|&m| { let s = pos.to_san(m); pos.make_move(m); s }).collect();
+        assert_eq!(san, ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O"]);
+        assert!(read_game("1. e4 e4").err().unwrap().starts_with("move 2"));
+    }
+}
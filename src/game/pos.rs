@@ -1,63 +1,57 @@
 use super::{bitbrd::*, defs::*, movgen::*, zobrist::ZOBRIST};
-use super::pvtable::*;
+use super::{params::EvalParams, weights::DEFAULT_PARAMS};
+use super::variant::{Variant, VariantState};
+use super::types::{Color, File, Piece, PieceType, Rank, Square};
+#[cfg(feature = "nnue")]
+use super::nnue::*;
+use std::sync::Arc;
 use std::{fmt, str::FromStr};
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Piece(u8);
-
-impl Piece {
-    pub fn none() -> Self { Self(0xFF) }
-
-    pub fn new(tp: u8, clr: u8) -> Self {
-        Self(tp << 1 | clr)
-    }
-
-    pub fn get_type(&self) -> u8 { self.0 >> 1 }
-    pub fn get_color(&self) -> u8 { self.0 & 1 }
-    pub fn id(&self) -> u8 { self.0 }
-
-    pub fn is_some(&self) -> bool { self.0 != 0xFF }
-    pub fn is_none(&self) -> bool { self.0 == 0xFF }
-}
-
-impl fmt::Display for Piece {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({:?} {:?})", self.get_color(), self.get_type())
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CastlingPerm(u8);
 
 impl CastlingPerm {
     pub fn new() -> Self { Self(0) }
-    pub fn king(&self, c: u8) -> bool {
-        self.0 & (1 << c) != 0
+    pub fn king(&self, c: Color) -> bool {
+        self.0 & (1 << c as u8) != 0
     }
 
-    pub fn queen(&self, c: u8) -> bool {
-        self.0 & (1 << (2 + c)) != 0
+    pub fn queen(&self, c: Color) -> bool {
+        self.0 & (1 << (2 + c as u8)) != 0
     }
 
-    pub fn dis_king(&mut self, c: u8) {
-        self.0 &= !(1 << c);
+    pub fn dis_king(&mut self, c: Color) {
+        self.0 &= !(1 << c as u8);
     }
 
-    pub fn dis_queen(&mut self, c: u8) {
-        self.0 &= !(1 << (2 + c));
+    pub fn dis_queen(&mut self, c: Color) {
+        self.0 &= !(1 << (2 + c as u8));
     }
 
-    pub fn dis_both(&mut self, c: u8) {
+    pub fn dis_both(&mut self, c: Color) {
         self.dis_king(c);
         self.dis_queen(c)
     }
 
-    pub fn any(&self, c: u8) -> bool {
+    pub fn any(&self, c: Color) -> bool {
         self.king(c) || self.queen(c)
     }
 
+    /// Keeps only the rights whose bits are set in `mask`.
+    pub fn retain(&mut self, mask: u8) {
+        self.0 &= mask;
+    }
+
+    fn allow(&mut self, c: Color, king_side: bool) {
+        self.0 |= if king_side { 1 << c as u8 } else { 1 << (2 + c as u8) };
+    }
+
     pub fn id(&self) -> u8 { self.0 }
 }
 
+impl Default for CastlingPerm {
+    fn default() -> Self { Self::new() }
+}
+
 impl FromStr for CastlingPerm {
     type Err = ();
 
@@ -79,262 +73,557 @@ impl FromStr for CastlingPerm {
 
 impl fmt::Display for CastlingPerm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.king(WHITE) { write!(f, "K")? }
-        if self.queen(WHITE) { write!(f, "Q")? }
-        if self.king(BLACK) { write!(f, "k")? }
-        if self.queen(BLACK) { write!(f, "q")? }
+        if self.king(Color::White) { write!(f, "K")? }
+        if self.queen(Color::White) { write!(f, "Q")? }
+        if self.king(Color::Black) { write!(f, "k")? }
+        if self.queen(Color::Black) { write!(f, "q")? }
         if self.0 == 0 { write!(f, "-")? }
 
         Ok(())
     }
 }
 
+/// Where the castling king and rooks start, indexed by color and then by side
+/// (0 for the queen side, 1 for the king side), which squares have to be empty and
+/// which must not be attacked. `mask` clears the rights a move from or to a square loses.
 #[derive(Clone, Copy)]
-pub struct Hist {
-    pub m: Move,
-    pub cas: CastlingPerm,
-    pub cap: Piece,
-    pub ep: u8,
-    pub fty: u8,
-    pub key: u64,
+pub struct CastleRules {
+    pub king: [Square; 2],
+    pub rooks: [[Square; 2]; 2],
+    pub path: [[BitBoard; 2]; 2],
+    pub safe: [[BitBoard; 2]; 2],
+    pub mask: [u8; 64],
 }
 
-impl Hist {
+/// The king and rook destinations of a castling move given as king takes rook.
+pub fn castle_targets(king: Square, rook: Square) -> (Square, Square) {
+    let rank = king.rank();
+    if rook > king { (Square::new(File::G, rank), Square::new(File::F, rank)) }
+    else { (Square::new(File::C, rank), Square::new(File::D, rank)) }
+}
+
+fn span(a: Square, b: Square) -> BitBoard {
+    let (lo, hi) = (a.min(b).u8(), a.max(b).u8());
+    (u64::MAX >> (63 - hi)) & (u64::MAX << lo)
+}
+
+impl CastleRules {
     pub fn new() -> Self {
+        let sq = |f, r| Square::new(f, r);
         Self {
-            m: Move::new(),
-            cas: CastlingPerm::new(),
-            cap: Piece::none(),
-            ep: 255,
-            fty: 0,
-            key: 0,
+            king: [sq(File::E, Rank::R1), sq(File::E, Rank::R8)],
+            rooks: [[sq(File::A, Rank::R1), sq(File::H, Rank::R1)], [sq(File::A, Rank::R8), sq(File::H, Rank::R8)]],
+            path: [[0; 2]; 2],
+            safe: [[0; 2]; 2],
+            mask: [0xF; 64],
         }
     }
+
+    fn add(&mut self, c: Color, king: Square, rook: Square) {
+        let side = (rook > king) as usize;
+        let (kt, rt) = castle_targets(king, rook);
+        self.king[c] = king;
+        self.rooks[c][side] = rook;
+        self.path[c][side] = (span(king, kt) | span(rook, rt)) & !king.bb() & !rook.bb();
+        self.safe[c][side] = span(king, kt);
+        self.mask[king] &= !(1 << c as u8 | 1 << (2 + c as u8));
+        self.mask[rook] &= !(1 << (2 * (side as u8 ^ 1) + c as u8));
+    }
+}
+
+impl Default for CastleRules {
+    fn default() -> Self { Self::new() }
+}
+
+/// The Chess960 start position with Scharnagl number `index`; 518 is the standard one.
+pub fn chess960_fen(index: u16) -> String {
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4),
+    ];
+    let mut n = index as usize % 960;
+    let mut rank = [' '; 8];
+    rank[n % 4 * 2 + 1] = 'b';
+    n /= 4;
+    rank[n % 4 * 2] = 'b';
+    n /= 4;
+    //places `ch` on the `i`th still empty square
+    fn put(rank: &mut [char; 8], i: usize, ch: char) {
+        *rank.iter_mut().filter(|c| **c == ' ').nth(i).unwrap() = ch;
+    }
+    put(&mut rank, n % 6, 'q');
+    n /= 6;
+    let (n1, n2) = KNIGHTS[n];
+    put(&mut rank, n2, 'n');
+    put(&mut rank, n1, 'n');
+    for ch in ['r', 'k', 'r'] {
+        put(&mut rank, 0, ch);
+    }
+    let black: String = rank.iter().collect();
+    format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", black, black.to_uppercase())
+}
+
+#[derive(Clone, Copy)]
+pub struct Hist {
+    pub m: Move,
+    pub cas: CastlingPerm,
+    pub cap: Option<Piece>,
+    pub ep: Option<Square>,
+    pub fty: u8,
+    pub key: u64,
+    pub vstate: VariantState,
+    /// Pieces the move blew up in Atomic, on top of `Position::blasts`.
+    pub blasts: u8,
 }
 
+/// The game state: pieces, side to move, rights, the move history and the incrementally
+/// updated keys, material and NNUE accumulator. Search state lives in `Searcher`.
+#[derive(Clone)]
 pub struct Position {
-    pub board: [Piece; 64],
+    pub board: [Option<Piece>; 64],
     pub pieces: [[BitBoard; 6]; 2],
     pub occupied: [BitBoard; 2],
 
-    pub hist: [Hist; MAX_HIST],
-    pub hist_ply: u16,
-    pub ep: u8,
-    pub turn: u8,
+    /// One entry per move made, the game's moves followed by the search's.
+    pub hist: Vec<Hist>,
+    /// Plies played before the FEN the position was loaded from, by its move number.
+    pub start_ply: u16,
+    pub ep: Option<Square>,
+    pub turn: Color,
     pub cas: CastlingPerm,
     pub key: u64,
+    pub pawn_key: u64,
 
     pub fty: u8,
-    pub ply: u16,
-    pub pv_line: PVLine,
-    pub pv_table: HashTable,
-
-    pub search_hist: [[u16; 64]; 12],
-    pub search_killers: [[Move; MAX_DEPTH]; 2],
-    pub counter_moves: [[Move; 64]; 64],
 
     pub material: [i16; 2],
+    pub params: Arc<EvalParams>,
+    pub castling: CastleRules,
+    pub variant: Variant,
+    pub vstate: VariantState,
+    pub blasts: Vec<(Square, Piece)>,
+
+    #[cfg(feature = "nnue")]
+    pub nnue: Option<Arc<Network>>,
+    #[cfg(feature = "nnue")]
+    pub nnue_acc: Accumulator,
 }
 
 impl Position {
     pub fn new() -> Self {
         Self {
-            board: [Piece::none(); 64],
+            board: [None; 64],
             pieces: [[0; 6]; 2],
             occupied: [0; 2],
             
-            hist: [Hist::new(); MAX_HIST],
-            hist_ply: 0,
-            ep: NS,
-            turn: WHITE,
+            hist: Vec::with_capacity(MAX_HIST),
+            start_ply: 0,
+            ep: None,
+            turn: Color::White,
             cas: CastlingPerm::new(),
             key: 0,
+            pawn_key: 0,
 
             fty: 0,
-            ply: 0,
-            pv_line: PVLine::new(),
-            pv_table: HashTable::new(),
-
-            search_hist: [[0; 64]; 12],
-            search_killers: [[Move::new(); MAX_DEPTH]; 2],
-            counter_moves: [[Move::new(); 64]; 64],
 
             material: [0; 2],
+            params: Arc::new(DEFAULT_PARAMS),
+            castling: CastleRules::new(),
+            variant: Variant::Standard,
+            vstate: VariantState::new(),
+            blasts: Vec::new(),
+
+            #[cfg(feature = "nnue")]
+            nnue: None,
+            #[cfg(feature = "nnue")]
+            nnue_acc: Accumulator::new(),
         }
     }
 
+    /// Plies since the start of the game, counting those before the loaded FEN.
+    pub fn game_ply(&self) -> usize { self.start_ply as usize + self.hist.len() }
+
     pub fn previous_move(&self) -> Move {
-        match self.hist_ply {
-            0 => Move::new(),
-            idx => self.hist[idx as usize].m
-        }
+        self.hist.last().map_or(Move::new(), |h| h.m)
     }
 
-    pub fn turnx(&self) -> usize {
-        self.turn as usize
+    pub fn all_ocupied(&self) -> BitBoard {
+        self.occupied[Color::White] | self.occupied[Color::Black]
     }
 
-    pub fn all_ocupied(&self) -> BitBoard {
-        self.occupied[WHITEX] | self.occupied[BLACKX]
+    /// The king square of `c`, if it has one.
+    pub fn king_square(&self, c: Color) -> Option<Square> {
+        let k = self.pieces[c][PieceType::King];
+        (k != 0).then(|| Square::from_index_unchecked(k.trailing_zeros() as u8))
     }
 
     pub fn verify(&self) {
-        for pt in PAWNX..=KINGX {
-            for c in WHITEX..=BLACKX {
+        for pt in PieceType::ALL {
+            for c in Color::ALL {
                 for sq in self.pieces[c][pt].bits() {
-                    assert_eq!(self.board[sq as usize], Piece::new(pt as u8, c as u8));
+                    assert_eq!(self.board[sq], Some(Piece::new(pt, c)));
                     assert!(self.occupied[c].chk(sq));
-                    assert!(!self.occupied[c^1].chk(sq), "{}", sq);
+                    assert!(!self.occupied[!c].chk(sq), "{}", sq);
                 }
             }
         }
-        for sq in 0..64 {
-            let p = self.board[sq as usize];
-            let (cx, px) = (p.get_color() as usize, p.get_type() as usize);
-            if p.is_some() {
-                assert!(self.pieces[cx][px].chk(sq));
-                assert!(self.occupied[cx].chk(sq));
-                assert!(!self.occupied[cx^1].chk(sq));
-            } else {
-                for px in PAWNX..=KINGX {
-                    assert!(!self.pieces[cx][px].chk(sq));
+        for sq in Square::all() {
+            match self.board[sq] {
+                Some(p) => {
+                    let c = p.color();
+                    assert!(self.pieces[c][p.kind()].chk(sq));
+                    assert!(self.occupied[c].chk(sq));
+                    assert!(!self.occupied[!c].chk(sq));
                 }
-                assert!(!self.all_ocupied().chk(sq));
+                None => assert!(!self.all_ocupied().chk(sq)),
             }
         }
         assert_eq!(self.key, ZOBRIST.gen_key(self));
+        assert_eq!(self.pawn_key, ZOBRIST.gen_pawn_key(self));
     }
 
-    pub fn do_castling(&mut self, f: u8, t: u8) {
-        const ROOK_FROM: [[u8; 2]; 2] = [ [0, 7], [56, 63] ];
-        const ROOK_TO: [[u8; 2]; 2] = [ [3, 5], [59, 61] ];
-        let x = (t > f) as usize;
-        let side = self.turn as usize;
-        self.move_piece(f, t);
-        self.move_piece(ROOK_FROM[side][x], ROOK_TO[side][x]);
+    /// Castles with the king on `f` and the rook on `t`. The rook is lifted first
+    /// since in Chess960 either piece may land on the other's start square.
+    pub fn do_castling(&mut self, f: Square, t: Square) {
+        let (kt, rt) = castle_targets(f, t);
+        self.remove_piece(t);
+        if f != kt { self.move_piece(f, kt); }
+        self.add_piece(self.turn, PieceType::Rook, rt);
         self.cas.dis_both(self.turn);
     }
 
-    pub fn add_piece(&mut self, side: usize, px: usize, sq: u8) {
-        let p = Piece::new(px as u8, side as u8);
-        self.board[sq as usize] = p;
+    pub fn undo_castling(&mut self, f: Square, t: Square) {
+        let (kt, rt) = castle_targets(f, t);
+        self.remove_piece(rt);
+        if f != kt { self.move_piece(kt, f); }
+        self.add_piece(self.turn, PieceType::Rook, t);
+    }
+
+    pub fn add_piece(&mut self, side: Color, pt: PieceType, sq: Square) {
+        let p = Piece::new(pt, side);
+        self.board[sq] = Some(p);
         self.occupied[side].set(sq);
-        self.pieces[side][px].set(sq);
-        self.material[side] += MATERIAL_TABLE[px];
+        self.pieces[side][pt].set(sq);
+        self.material[side] += MATERIAL_TABLE[pt];
         self.key ^= ZOBRIST.piece(sq, p);
+        if pt == PieceType::Pawn { self.pawn_key ^= ZOBRIST.piece(sq, p); }
+        #[cfg(feature = "nnue")]
+        self.nnue_add(p, sq);
     }
 
-    pub fn remove_piece(&mut self, sq: u8) {
-        let p = self.board[sq as usize];
-        let (side, px) = (p.get_color() as usize, p.get_type() as usize);
-        self.pieces[side][px].clear(sq);
+    /// Takes the piece off `sq`, which must not be empty.
+    pub fn remove_piece(&mut self, sq: Square) {
+        let p = self.board[sq].expect("no piece to remove");
+        let (side, pt) = (p.color(), p.kind());
+        self.pieces[side][pt].clear(sq);
         self.occupied[side].clear(sq);
-        self.board[sq as usize] = Piece::none();
-        self.material[side] -= MATERIAL_TABLE[px];
+        self.board[sq] = None;
+        self.material[side] -= MATERIAL_TABLE[pt];
         self.key ^= ZOBRIST.piece(sq, p);
+        if pt == PieceType::Pawn { self.pawn_key ^= ZOBRIST.piece(sq, p); }
+        #[cfg(feature = "nnue")]
+        self.nnue_sub(p, sq);
     }
 
-    pub fn move_piece(&mut self, from: u8, to: u8) {
-        let p = self.board[from as usize];
-        let (side, px) = (p.get_color() as usize, p.get_type() as usize);
-        self.pieces[side][px].clear(from);
+    pub fn move_piece(&mut self, from: Square, to: Square) {
+        let p = self.board[from].expect("no piece to move");
+        let (side, pt) = (p.color(), p.kind());
+        self.pieces[side][pt].clear(from);
         self.occupied[side].clear(from);
-        self.board[from as usize] = Piece::none();
-        self.board[to as usize] = p;
+        self.board[from] = None;
+        self.board[to] = Some(p);
         self.occupied[side].set(to);
-        self.pieces[side][px].set(to);
+        self.pieces[side][pt].set(to);
         self.key ^= ZOBRIST.piece(from, p);
         self.key ^= ZOBRIST.piece(to, p);
+        if pt == PieceType::Pawn { 
+            self.pawn_key ^= ZOBRIST.piece(from, p) ^ ZOBRIST.piece(to, p); 
+        }
+        #[cfg(feature = "nnue")]
+        self.nnue_move(p, from, to);
     }
 
     pub fn reset(&mut self) {
-        for p in self.board.iter_mut() {
-            *p = Piece::none();
-        }
-        for c in WHITEX..=BLACKX {
-            for p in PAWNX..=KINGX {
-                self.pieces[c][p] = 0;
-            }
-            self.occupied[c] = 0;
-        }
-        self.hist_ply = 0;
-        self.ep = NS;
-        self.turn = WHITE;
+        self.board = [None; 64];
+        self.pieces = [[0; 6]; 2];
+        self.occupied = [0; 2];
+        self.hist.clear();
+        self.ep = None;
+        self.turn = Color::White;
         self.cas = CastlingPerm::new();
+        self.castling = CastleRules::new();
+        self.vstate = VariantState::new();
+        self.blasts.clear();
         self.key = 0;
+        self.pawn_key = 0;
         self.fty = 0;
-        self.ply = 0;
         self.material = [0; 2];
-        //the rest is cleared automatically by search()
+        #[cfg(feature = "nnue")]
+        { self.nnue_acc.dirty = [true; 2]; }
     }
 
+    /// Parses a standard chess FEN.
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        let mut pos = Self::new();
+        pos.try_load_fen(fen)?;
+        Ok(pos)
+    }
+
+    /// `load_fen` that rejects malformed input and illegal positions instead of
+    /// panicking; the position is left as it was on errors.
+    pub fn try_load_fen(&mut self, fen: &str) -> Result<(), String> {
+        check_fen(fen)?;
+        let mut pos = self.clone();
+        pos.load_fen(fen);
+        pos.check_legal(fen.split_whitespace().nth(2).unwrap())?;
+        *self = pos;
+        Ok(())
+    }
+
+    pub fn side_to_move(&self) -> Color { self.turn }
+
+    pub fn piece_at(&self, sq: Square) -> Option<Piece> { self.board[sq] }
+
+    /// Reads a FEN under the current `variant`, including Crazyhouse pockets and
+    /// Three-check counters.
     pub fn load_fen(&mut self, fen: &str) {
         self.reset();
         let mut ss = fen.split_whitespace();
-        let (mut rank, mut file) = (7, 0);
-
-        for ch in ss.next().unwrap().chars() {
-            let c = ch.is_lowercase() as usize;
-            let sq = rank * 8 + file;
-            let px = match ch.to_ascii_lowercase() {
-                'p' => PAWNX,
-                'n' => KNIGHTX,
-                'b' => BISHOPX,
-                'r' => ROOKX,
-                'q' => QUEENX,
-                'k' => KINGX,
-                '/' => { rank -= 1; file = 0; continue; },
-                ' ' => break,
-                c if c.is_digit(9) && c != '0' 
-                    => { file += c.to_digit(9).unwrap() as u8; continue; }
-                ch => panic!("unexpected char in fen string {}", ch),
-
-            };
-            self.add_piece(c, px, sq);
-            file += 1;
-        }
-        self.turn = match ss.next().unwrap() {
-            "w" => WHITE,
-            "b" => BLACK,
-            _ => panic!("invalid side character"),
+        let (mut rank, mut file) = (7u8, 0u8);
+
+        let board = ss.next().unwrap();
+        let (board, pocket) = match board.find('[') {
+            Some(idx) => (&board[..idx], &board[idx..]),
+            None => match board.match_indices('/').nth(7) {
+                Some((idx, _)) => (&board[..idx], &board[idx..]),
+                None => (board, ""),
+            },
         };
-        self.cas = CastlingPerm::from_str(ss.next().unwrap()).unwrap();
-        self.ep = match ss.next().unwrap() {
-            "-" => NS,
-            ep => (ep.as_bytes()[0] - b'a' + (ep.as_bytes()[1] - b'1')*8) 
-        };
-        self.fty = ss.next()
+        self.parse_pocket(pocket);
+        //the square a '~' marks as promoted
+        let mut last = None;
+        for ch in board.chars() {
+            match ch {
+                '/' => { rank -= 1; file = 0; last = None; }
+                '~' => if let Some(sq) = last.take() { self.vstate.promoted.set(sq) },
+                c if c.is_digit(9) && c != '0' => { file += c.to_digit(9).unwrap() as u8; last = None; }
+                ch => {
+                    let p = Piece::from_char(ch)
+                        .unwrap_or_else(|| panic!("unexpected char in fen string {}", ch));
+                    let sq = Square::from_index_unchecked(rank * 8 + file);
+                    self.add_piece(p.color(), p.kind(), sq);
+                    last = Some(sq);
+                    file += 1;
+                }
+            }
+        }
+        self.turn = ss.next().unwrap().parse().expect("invalid side character");
+        let cas = ss.next().unwrap();
+        if self.variant != Variant::Antichess { self.parse_castling(cas); }
+        self.ep = ss.next().unwrap().parse().ok();
+        let (checks, rest): (Vec<&str>, Vec<&str>) = ss.partition(|s| s.contains('+'));
+        if let Some(field) = checks.first() { self.parse_checks(field); }
+        self.fty = rest.first()
             .and_then(|s|s.parse::<u8>().ok())
             .unwrap_or(0);
+        let move_number = rest.get(1).and_then(|s| s.parse::<u16>().ok()).unwrap_or(1).max(1);
+        self.start_ply = 2 * (move_number - 1).min(5000) + (self.turn == Color::Black) as u16;
 
         self.key ^= ZOBRIST.en_passant(self.ep);
         self.key ^= ZOBRIST.castling(self.cas);
-        if self.turn == WHITE { self.key ^= ZOBRIST.side(); }
+        if self.turn == Color::White { self.key ^= ZOBRIST.side(); }
+        #[cfg(feature = "nnue")]
+        self.nnue_refresh();
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self { Self::new() }
+}
+
+/// Checks the fields `load_fen` relies on.
+fn check_fen(fen: &str) -> Result<(), String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 4 { return Err(format!("incomplete fen {}", fen)) }
+    let board = fields[0];
+    let (board, pocket) = match board.find('[') {
+        Some(idx) => (&board[..idx], board[idx..].trim_end_matches(']')),
+        None => match board.match_indices('/').nth(7) {
+            Some((idx, _)) => (&board[..idx], &board[idx..]),
+            None => (board, ""),
+        },
+    };
+    let ranks: Vec<&str> = board.split('/').collect();
+    if ranks.len() != 8 { return Err(format!("expected 8 ranks in {}", board)) }
+    for rank in ranks {
+        let mut files = 0;
+        let mut prev = None;
+        for ch in rank.chars() {
+            match ch {
+                '1'..='8' => files += ch as u32 - '0' as u32,
+                '~' if prev.is_some_and(|p: char| p.is_ascii_alphabetic()) => (),
+                '~' => return Err(format!("'~' without a piece before it in {}", board)),
+                _ if "pnbrqkPNBRQK".contains(ch) => files += 1,
+                _ => return Err(format!("unexpected {} in {}", ch, board)),
+            }
+            prev = Some(ch);
+        }
+        if files != 8 { return Err(format!("rank {} is not 8 squares wide", rank)) }
+    }
+    for ch in pocket.chars().filter(|&ch| ch != '[' && ch != '/') {
+        if !"pnbrqPNBRQ".contains(ch) || pocket.matches(ch).count() > 16 {
+            return Err(format!("bad pocket {}", pocket))
+        }
+    }
+    if fields[1] != "w" && fields[1] != "b" { return Err(format!("bad side to move {}", fields[1])) }
+    if fields[2] != "-" && !fields[2].chars().all(|ch| "KQkqABCDEFGHabcdefgh".contains(ch)) {
+        return Err(format!("bad castling rights {}", fields[2]))
+    }
+    match fields[3].as_bytes() {
+        b"-" | [b'a'..=b'h', b'3' | b'6'] => (),
+        _ => return Err(format!("bad en passant square {}", fields[3])),
+    }
+    //Three-check counters may come before the move counters
+    let counters: Vec<&str> = fields[4..].iter().filter(|f| !f.contains('+')).copied().collect();
+    let fty_ok = counters.first().is_none_or(|f| f.parse::<u8>().is_ok());
+    let number_ok = counters.get(1).is_none_or(|f| f.parse::<u16>().is_ok());
+    if counters.len() > 2 || !fty_ok || !number_ok {
+        return Err(format!("bad move counters {}", counters.join(" ")))
+    }
+    Ok(())
+}
+
+impl Position {
+    /// Checks what the text of a FEN doesn't show: one king a side, no pawns on the
+    /// back ranks, castling rights with their king and rook, and no capturable king.
+    fn check_legal(&self, castling: &str) -> Result<(), String> {
+        for c in Color::ALL {
+            let kings = self.pieces[c][PieceType::King].count_ones();
+            let expected = match self.variant {
+                Variant::Antichess => kings,
+                Variant::Horde if c == Color::White => 0,
+                _ => 1,
+            };
+            if kings != expected { return Err(format!("{} has {} kings", c, kings)) }
+        }
+        let mut pawns = (self.pieces[Color::White][PieceType::Pawn] | self.pieces[Color::Black][PieceType::Pawn])
+            & (Rank::R1.bb() | Rank::R8.bb());
+        //the horde starts with pawns on the first rank
+        if self.variant == Variant::Horde { pawns &= !(self.pieces[Color::White][PieceType::Pawn] & Rank::R1.bb()) }
+        if pawns != 0 { return Err("pawns on the first or last rank".to_owned()) }
+        if self.variant != Variant::Antichess {
+            let rights: usize = Color::ALL.iter().map(|&c| self.cas.king(c) as usize + self.cas.queen(c) as usize).sum();
+            if rights != castling.chars().filter(|&ch| ch != '-').count() {
+                return Err(format!("castling rights {} without their king and rook", castling))
+            }
+        }
+        if self.in_check(!self.turn) { return Err(format!("{} can capture the king", self.turn)) }
+        Ok(())
+    }
+
+    /// Reads `KQkq`, X-FEN (the letter stands for the outermost rook on that side of
+    /// the king) and Shredder-FEN (rook files) castling fields.
+    fn parse_castling(&mut self, field: &str) {
+        for ch in field.chars().filter(|&ch| ch != '-') {
+            let c = if ch.is_ascii_lowercase() { Color::Black } else { Color::White };
+            let rank = Rank::R1.relative(c);
+            let king = match self.king_square(c) {
+                Some(k) if k.rank() == rank => k,
+                _ => continue,
+            };
+            let rooks = self.pieces[c][PieceType::Rook] & rank.bb();
+            let on_rank = |f: File| Square::new(f, rank);
+            let rook = match ch.to_ascii_lowercase() {
+                'k' => File::ALL[king.file().index() + 1..].iter().rev().map(|&f| on_rank(f))
+                    .find(|&sq| rooks.chk(sq)),
+                'q' => File::ALL[..king.file().index()].iter().map(|&f| on_rank(f))
+                    .find(|&sq| rooks.chk(sq)),
+                f @ 'a'..='h' => File::from_char(f).map(on_rank).filter(|&sq| rooks.chk(sq)),
+                _ => panic!("invalid castling character {}", ch),
+            };
+            if let Some(rook) = rook {
+                self.castling.add(c, king, rook);
+                self.cas.allow(c, rook > king);
+            }
+        }
+    }
+
+    /// X-FEN castling field: `KQkq` unless an inner rook is the castling one.
+    fn castling_fen(&self) -> String {
+        let mut s = String::new();
+        for c in Color::ALL {
+            for (side, allowed) in [(1, self.cas.king(c)), (0, self.cas.queen(c))] {
+                if !allowed { continue }
+                let rook = self.castling.rooks[c][side];
+                let rank = rook.rank();
+                let beyond = if side == 1 { span(rook, Square::new(File::H, rank)) }
+                    else { span(Square::new(File::A, rank), rook) };
+                let outermost = self.pieces[c][PieceType::Rook] & beyond & !rook.bb() == 0;
+                let ch = if outermost { if side == 1 { 'k' } else { 'q' } }
+                    else { rook.file().char() };
+                s.push(if c == Color::White { ch.to_ascii_uppercase() } else { ch });
+            }
+        }
+        if s.is_empty() { s.push('-'); }
+        s
+    }
+
+    pub fn fen(&self) -> String {
+        use std::fmt::Write;
+        let mut s = String::new();
+        for rank in Rank::ALL.iter().rev() {
+            let mut empty = 0;
+            for file in File::ALL {
+                let sq = Square::new(file, *rank);
+                let p = match self.board[sq] {
+                    Some(p) => p,
+                    None => {
+                        empty += 1;
+                        continue;
+                    }
+                };
+                if empty > 0 { write!(s, "{}", empty).unwrap(); }
+                empty = 0;
+                s.push(p.char());
+                if self.vstate.promoted.chk(sq) { s.push('~'); }
+            }
+            if empty > 0 { write!(s, "{}", empty).unwrap(); }
+            if *rank != Rank::R1 { s.push('/'); }
+        }
+        if self.variant == Variant::Crazyhouse { s.push_str(&self.pocket_fen()); }
+        write!(s, " {} {} ", self.turn.char(), self.castling_fen()).unwrap();
+        match self.ep {
+            None => s.push('-'),
+            Some(ep) => write!(s, "{}", ep).unwrap(),
+        }
+        if self.variant == Variant::ThreeCheck { write!(s, " {}", self.checks_fen()).unwrap(); }
+        write!(s, " {} {}", self.fty, 1 + self.game_ply() / 2).unwrap();
+        s
     }
 }
 
 impl fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        const S: [char; 7] = ['.', 'p', 'n', 'b', 'r', 'q', 'k'];
         const TURN: [&str; 2] = ["White", "Black"];
-        for i in (0..8).rev() {
-            write!(f, "{}    ", i+1)?;
-            for p in self.board[i*8..(i+1)*8].iter() {
-                let pdx = if p.is_none() { 0 } else { p.get_type() as usize + 1 };
-                write!(f, "{}  ", match p.get_color() {
-                    WHITE => S[pdx].to_ascii_uppercase(),
-                    BLACK => S[pdx],
-                    _ => unreachable!()
-                })?;
+        for rank in Rank::ALL.iter().rev() {
+            write!(f, "{}    ", rank)?;
+            for file in File::ALL {
+                write!(f, "{}  ", self.board[Square::new(file, *rank)].map_or('.', Piece::char))?;
             }
             writeln!(f)?;
         }
         write!(f, "\n     ")?;
-        for i in ['a','b','c','d','e','f', 'g', 'h'].iter() {
-            write!(f, "{}  ", i)?;
+        for file in File::ALL {
+            write!(f, "{}  ", file)?;
         }
+        let ep = self.ep.map_or("-".to_owned(), |sq| sq.to_string());
         writeln!(f, "\nturn: {} // cas: {} // ep: {} // fty: {} //eval: {}", 
-            TURN[self.turn as usize], self.cas, self.ep, self.fty, self.eval())
+            TURN[self.turn], self.cas, ep, self.fty, self.eval())?;
+        match self.variant {
+            Variant::Standard => Ok(()),
+            Variant::Crazyhouse => writeln!(f, "variant: {} // pocket: {}", self.variant, self.pocket_fen()),
+            Variant::ThreeCheck => writeln!(f, "variant: {} // checks left: {}", self.variant, self.checks_fen()),
+            _ => writeln!(f, "variant: {}", self.variant),
+        }
     }
 }
 
@@ -343,7 +632,47 @@ mod tests {
     use super::*;
 
     fn cs_to_flags(cs: CastlingPerm) -> [bool; 4] {
-        [cs.king(WHITE), cs.queen(WHITE), cs.king(BLACK), cs.queen(BLACK)]
+        [cs.king(Color::White), cs.queen(Color::White), cs.king(Color::Black), cs.queen(Color::Black)]
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        use crate::game::perft::POSITIONS;
+        let mut pos = Position::new();
+        for fen in POSITIONS.iter() {
+            pos.load_fen(fen);
+            let mut parts = pos.fen();
+            parts.truncate(parts.rfind(' ').unwrap());
+            assert!(fen.starts_with(&parts), "{} vs {}", fen, parts);
+        }
+    }
+
+    #[test]
+    fn rejects_illegal_fens() {
+        for fen in [
+            "4k3/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R w KQ - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1 2",
+            "4k3/4R3/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/~4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1~3K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K~~3 w - - 0 1",
+        ] {
+            let mut pos = Position::new();
+            assert!(pos.try_load_fen(fen).is_err(), "{}", fen);
+            assert_eq!(pos.fen(), Position::new().fen());
+        }
+        let mut pos = Position::new();
+        for v in Variant::ALL {
+            pos.variant = v;
+            assert!(pos.try_load_fen(v.start_fen()).is_ok(), "{:?}", v);
+        }
+        pos.variant = Variant::Standard;
+        assert!(pos.try_load_fen("4k3/8/8/8/8/8/8/4K2R w K - 3 40").is_ok());
     }
 
     #[test]
@@ -351,13 +680,39 @@ mod tests {
         assert_eq!(CastlingPerm::new(), CastlingPerm::from_str("-").unwrap());
         let mut cs = CastlingPerm::from_str("KQkq").unwrap();
         assert_eq!(cs_to_flags(cs), [true, true, true, true]);
-        cs.dis_king(WHITE);
+        cs.dis_king(Color::White);
         assert_eq!(cs_to_flags(cs), [false, true, true, true]);
-        cs.dis_king(BLACK);
+        cs.dis_king(Color::Black);
         assert_eq!(cs_to_flags(cs), [false, true, false, true]);
-        cs.dis_queen(WHITE);
+        cs.dis_queen(Color::White);
         assert_eq!(cs_to_flags(cs), [false, false, false, true]);
-        cs.dis_queen(BLACK);
+        cs.dis_queen(Color::Black);
         assert_eq!(cs_to_flags(cs), [false, false, false, false]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn chess960_castling_fields() {
+        assert_eq!(chess960_fen(518), crate::game::uci::START_FEN);
+        let mut pos = Position::new();
+        pos.load_fen("1r2k1r1/8/8/8/8/8/8/R3K2R w KAgb - 0 1");
+        let sq = |s: &str| s.parse::<Square>().unwrap();
+        assert_eq!(pos.castling.rooks, [[sq("a1"), sq("h1")], [sq("b8"), sq("g8")]]);
+        assert!(pos.fen().starts_with("1r2k1r1/8/8/8/8/8/8/R3K2R w KQkq - "));
+        pos.load_fen("rr2k2r/8/8/8/8/8/8/R3K1RR w Gb - 0 1");
+        assert!(pos.fen().contains(" w Gb - "));
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let mut pos = Position::new();
+        pos.load_fen(crate::game::uci::START_FEN);
+        let mut copy = pos.clone();
+        let mut moves = MoveList::new();
+        copy.gen_moves::<false>(&mut moves);
+        assert!(copy.make_move(moves.iter().next().unwrap().0));
+        assert_ne!(copy.key, pos.key);
+        copy.unmake_move();
+        assert_eq!(copy.fen(), pos.fen());
+        assert_eq!(copy.key, pos.key);
+    }
+}
@@ -32,19 +32,32 @@ impl HashEntry {
     }
 }
 
+/// Entries keep the upper half of the key for verification, the lower half mostly
+/// picks the slot.
 pub struct HashTable {
-    entries: Vec<(u64, HashEntry)>,
+    entries: Vec<(u32, HashEntry)>,
 }
 
 impl HashTable {
     pub fn new() -> Self {
+        Self::with_entries(NUM_ENTRIES as usize)
+    }
+
+    pub fn with_size_mb(mb: usize) -> Self {
+        let entry_size = std::mem::size_of::<(u32, HashEntry)>();
+        Self::with_entries((mb * 1024 * 1024 / entry_size).max(1))
+    }
+
+    fn with_entries(n: usize) -> Self {
         Self {
-            entries: vec![(0, HashEntry::new()); NUM_ENTRIES as usize],
+            entries: vec![(0, HashEntry::new()); n],
         }
     }
 
     pub fn clear(&mut self) {
-        self.entries.clear();
+        for e in self.entries.iter_mut() {
+            *e = (0, HashEntry::new());
+        }
     }
 
     pub fn store(&mut self, pk: u64, e: HashEntry) {
@@ -53,18 +66,32 @@ impl HashTable {
                 return;
             }
         }
-        self.entries[(pk % NUM_ENTRIES) as usize] = (pk, e);
+        let idx = (pk % self.entries.len() as u64) as usize;
+        self.entries[idx] = (check(pk), e);
         // self.entries.insert(pk, e);
     }
 
+    /// Per mille of the first thousand slots in use.
+    pub fn hashfull(&self) -> u16 {
+        let sample = &self.entries[..self.entries.len().min(1000)];
+        let used = sample.iter().filter(|e| !matches!(e.1.flags, EntryFlags::None)).count();
+        (used * 1000 / sample.len()) as u16
+    }
+
     pub fn probe(&self, pk: u64) -> Option<HashEntry> {
-        let e = &self.entries[(pk % NUM_ENTRIES) as usize];
-        if e.0 == pk { Some(e.1) }
+        let e = &self.entries[(pk % self.entries.len() as u64) as usize];
+        if e.0 == check(pk) { Some(e.1) }
         else { None }
         // self.entries.get(&pk).and_then(|m|Some(*m))
     }
 }
 
+impl Default for HashTable {
+    fn default() -> Self { Self::new() }
+}
+
+fn check(pk: u64) -> u32 { (pk >> 32) as u32 }
+
 pub struct PVLine {
     data: [Move; MAX_DEPTH],
     n: usize,
@@ -73,7 +100,7 @@ pub struct PVLine {
 impl PVLine {
     pub fn new() -> Self {
         Self {
-            data: [Move::new(); MAX_DEPTH as usize],
+            data: [Move::new(); MAX_DEPTH],
             n: 0,
         }
     }
@@ -91,8 +118,10 @@ impl PVLine {
     pub fn clear(&mut self) { 
         self.n = 0;
     }
+}
 
-    pub fn len(&self) -> usize { self.n }
+impl Default for PVLine {
+    fn default() -> Self { Self::new() }
 }
 
 impl Position {
@@ -107,20 +136,23 @@ impl Position {
         }
         false
     }
+}
 
-    pub fn extract_pv_line(&mut self, mut depth: u8) {
+impl HashTable {
+    /// Follows the stored best moves from `p` into `line`, at most `depth` of them.
+    pub fn pv_line(&self, p: &mut Position, mut depth: u8, line: &mut PVLine) {
         debug_assert!((depth as usize) < MAX_DEPTH);
-        self.pv_line.clear();
-        while let Some(e) = self.pv_table.probe(self.key) {
-            if !self.move_exists(e.m) { break }
-            self.make_move(e.m);
-            self.pv_line.push(e.m);
+        line.clear();
+        while let Some(e) = self.probe(p.key) {
+            if !p.move_exists(e.m) { break }
+            p.make_move(e.m);
+            line.push(e.m);
             if depth <= 1 { break }
             depth -= 1;
         }
 
-        for _ in 0..self.pv_line.n {
-            self.unmake_move();
+        for _ in 0..line.n {
+            p.unmake_move();
         }
     }
 }
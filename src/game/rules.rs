@@ -0,0 +1,193 @@
+use super::{defs::LIGHT_SQUARES, pos::*, variant::Variant};
+use super::types::{Color, PieceType};
+use std::fmt;
+
+/// Where a game stands. `FiftyMove` and `Threefold` are draws a player may claim,
+/// `SeventyFiveMove` and `FivefoldRepetition` end the game without a claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The winner.
+    Checkmate(Color),
+    Stalemate,
+    /// The winner under a variant rule: three checks, a king on the hill, no pieces left...
+    VariantWin(Color),
+    FiftyMove,
+    Threefold,
+    InsufficientMaterial,
+    SeventyFiveMove,
+    FivefoldRepetition,
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool { *self != GameStatus::Ongoing }
+
+    pub fn winner(&self) -> Option<Color> {
+        match *self {
+            GameStatus::Checkmate(c) | GameStatus::VariantWin(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// The PGN result: `1-0`, `0-1`, `1/2-1/2` or `*` while the game goes on.
+    pub fn result(&self) -> &'static str {
+        match (self, self.winner()) {
+            (GameStatus::Ongoing, _) => "*",
+            (_, Some(Color::White)) => "1-0",
+            (_, Some(Color::Black)) => "0-1",
+            (_, None) => "1/2-1/2",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "ongoing",
+            GameStatus::Checkmate(_) => "checkmate",
+            GameStatus::Stalemate => "stalemate",
+            GameStatus::VariantWin(_) => "variant win",
+            GameStatus::FiftyMove => "fifty move rule",
+            GameStatus::Threefold => "threefold repetition",
+            GameStatus::InsufficientMaterial => "insufficient material",
+            GameStatus::SeventyFiveMove => "seventy-five move rule",
+            GameStatus::FivefoldRepetition => "fivefold repetition",
+        }
+    }
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.winner() {
+            Some(c) => write!(f, "{}, {} wins", self.as_str(), c),
+            None => f.write_str(self.as_str()),
+        }
+    }
+}
+
+impl Position {
+    /// History indexes of earlier occurrences of the current position, newest first.
+    /// Only the same side to move is checked and the scan stops at the last capture,
+    /// pawn move or null move, none of which can be repeated across.
+    fn repeats(&self) -> impl Iterator<Item = usize> + '_ {
+        let n = self.hist.len();
+        let end = n - (self.fty as usize).min(n);
+        (end..n.saturating_sub(3)).rev().step_by(2).filter(move |&i| self.hist[i].key == self.key)
+    }
+
+    /// Draw by repetition inside a search whose root was `tree_plies` plies ago: a single
+    /// repeat of a position from the search tree counts, positions from the game before
+    /// the root need a threefold repetition.
+    pub fn is_repetition(&self, tree_plies: usize) -> bool {
+        let root = self.hist.len().saturating_sub(tree_plies);
+        let mut seen = false;
+        for i in self.repeats() {
+            if i > root || seen { return true }
+            seen = true;
+        }
+        false
+    }
+
+    pub fn is_threefold(&self) -> bool {
+        self.repeats().nth(1).is_some()
+    }
+
+    /// No sequence of moves can mate: bare kings with at most one minor piece, or
+    /// only bishops that all stand on squares of one color.
+    pub fn insufficient_material(&self) -> bool {
+        let [w, b] = &self.pieces;
+        let heavy = |p: &[u64; 6]| p[PieceType::Pawn] | p[PieceType::Rook] | p[PieceType::Queen];
+        if heavy(w) | heavy(b) != 0 { return false }
+        let knights = w[PieceType::Knight] | b[PieceType::Knight];
+        let bishops = w[PieceType::Bishop] | b[PieceType::Bishop];
+        (knights | bishops).count_ones() <= 1
+            || knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0)
+    }
+
+    /// Checkmate, stalemate, a variant win or a draw by rule. Automatic endings are
+    /// reported before claimable ones, and a mate on the hundredth half move stands.
+    pub fn status(&mut self) -> GameStatus {
+        if let Some(c) = self.variant_winner() { return GameStatus::VariantWin(c) }
+        if self.legal_moves().is_empty() {
+            let us = self.turn;
+            return match self.variant {
+                //the side that can't move wins
+                Variant::Antichess => GameStatus::VariantWin(us),
+                _ if self.in_check(us) => GameStatus::Checkmate(!us),
+                _ => GameStatus::Stalemate,
+            }
+        }
+        if self.variant == Variant::Standard && self.insufficient_material() {
+            return GameStatus::InsufficientMaterial
+        }
+        if self.fty >= 150 { return GameStatus::SeventyFiveMove }
+        if self.repeats().nth(3).is_some() { return GameStatus::FivefoldRepetition }
+        if self.fty >= 100 { return GameStatus::FiftyMove }
+        if self.is_threefold() { return GameStatus::Threefold }
+        GameStatus::Ongoing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::movgen::Move;
+
+    fn play(pos: &mut Position, moves: &str) {
+        for m in moves.split_whitespace() {
+            let m = Move::from_uci(pos, m).unwrap();
+            assert!(pos.make_move(m));
+        }
+    }
+
+    #[test]
+    fn repetitions() {
+        let mut pos = Position::new();
+        pos.load_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        play(&mut pos, "e1d1 e8d8 d1e1 d8e8 e1d1");
+        //a single repeat is only a draw if it happened after the root
+        assert!(pos.is_repetition(5));
+        assert!(!pos.is_repetition(4));
+        assert_eq!(pos.status(), GameStatus::Ongoing);
+        play(&mut pos, "e8d8 d1e1 d8e8 e1d1");
+        assert!(pos.is_repetition(0));
+        assert_eq!(pos.status(), GameStatus::Threefold);
+        play(&mut pos, "e8d8 d1e1 d8e8 e1d1 e8d8 d1e1 d8e8 e1d1");
+        assert_eq!(pos.status(), GameStatus::FivefoldRepetition);
+
+        //a null move hides the positions before it
+        pos.load_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        play(&mut pos, "e1d1 e8d8");
+        pos.make_null_move();
+        play(&mut pos, "d8e8 d1e1");
+        pos.make_null_move();
+        assert!(!pos.is_repetition(6));
+
+        //the history grows past its initial capacity
+        pos.load_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        for _ in 0..400 { play(&mut pos, "e1d1 e8d8 d1e1 d8e8"); }
+        assert_eq!(pos.hist.len(), 1600);
+        assert_eq!(pos.status(), GameStatus::SeventyFiveMove);
+        pos.load_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 120 80");
+        assert_eq!(pos.status(), GameStatus::FiftyMove);
+    }
+
+    #[test]
+    fn game_endings() {
+        let mut pos = Position::new();
+        let mut status = |fen: &str| { pos.load_fen(fen); pos.status() };
+        assert_eq!(status("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"), GameStatus::InsufficientMaterial);
+        assert_eq!(status("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1"), GameStatus::InsufficientMaterial);
+        assert_eq!(status("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"), GameStatus::Ongoing);
+        assert_eq!(status("4k3/8/8/8/8/8/8/1N2K1n1 w - - 0 1"), GameStatus::Ongoing);
+        assert_eq!(status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"), GameStatus::Checkmate(Color::White));
+        assert_eq!(status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"), GameStatus::Stalemate);
+        //mate on the hundredth half move stands
+        assert_eq!(status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80"), GameStatus::Checkmate(Color::White));
+        let st = status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!((st.result(), st.is_over()), ("1/2-1/2", true));
+        assert_eq!(GameStatus::Checkmate(Color::Black).to_string(), "checkmate, black wins");
+
+        pos.variant = Variant::KingOfTheHill;
+        pos.load_fen("8/8/8/3K4/8/8/8/k7 b - - 0 1");
+        assert_eq!(pos.status(), GameStatus::VariantWin(Color::White));
+    }
+}
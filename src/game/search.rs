@@ -1,23 +1,130 @@
-use super::{pos::*, movgen::*, pvtable::*};
+use super::{pos::*, movgen::*, pvtable::*, pawns::PawnTable, params::EvalParams, skill::{self, Skill}, tunables, variant::Variant};
+use super::wdl::{Wdl, DEFAULT_MODEL};
+use super::types::PieceType;
+use std::sync::Arc;
 use std::{fmt, time::{SystemTime, Duration}};
 
 const INFINITY: i16 = i16::MAX;
 const CHECKUP_INTERVAL_MASK: u32 = 2047;
+/// Searches running longer than this report the root move and their progress.
+const LONG_SEARCH: Duration = Duration::from_secs(3);
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hash size of the table `search` allocates.
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// A search score from the side to move: centipawns or moves to mate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Cp(i16),
+    Mate(i16),
+}
 
-struct Score(i16);
+impl Score {
+    pub fn from_raw(score: i16) -> Self {
+        if score > INFINITY - 100 {
+            Score::Mate((INFINITY - score + 1) / 2)
+        } else if score < -INFINITY + 100 {
+            //negative when being mated
+            Score::Mate(-(score + INFINITY) / 2)
+        } else {
+            Score::Cp(score)
+        }
+    }
+}
 
 impl fmt::Display for Score {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.0 > INFINITY - 100 {
-            write!(f, "mate {}", (INFINITY - self.0 + 1) / 2)
-        } else if self.0 < -INFINITY + 100 {
-            write!(f, "mate {}", (self.0 + INFINITY - 1) / 2)
-        } else {
-            write!(f, "cp {}", self.0)
+        match self {
+            Score::Cp(cp) => write!(f, "cp {}", cp),
+            Score::Mate(n) => write!(f, "mate {}", n),
         }
     }
 }
 
+/// When to stop searching; without any limit the search runs to `MAX_DEPTH`.
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub move_time: Option<Duration>,
+    /// Root lines to search; 0 and 1 both search only the best.
+    pub multi_pv: usize,
+    pub skill: Option<Skill>,
+}
+
+/// Which side of the aspiration window the search failed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Lower,
+    Upper,
+}
+
+/// Reported for every line of a completed iteration, and with a `bound` when the
+/// aspiration window fails before the re-search.
+pub struct SearchUpdate {
+    pub depth: u8,
+    /// The deepest ply reached.
+    pub seldepth: u16,
+    /// Which root line this is, from 1.
+    pub multipv: usize,
+    pub score: Score,
+    pub bound: Option<Bound>,
+    /// The expected result at `score`, if asked for.
+    pub wdl: Option<Wdl>,
+    /// Nodes searched since the start.
+    pub nodes: u64,
+    pub time: Duration,
+    /// Transposition table use in per mille.
+    pub hashfull: u16,
+    pub pv: Vec<Move>,
+    /// Share of the beta cutoffs the first move searched produced.
+    pub first_move_cutoffs: f32,
+}
+
+fn nps(nodes: u64, time: Duration) -> u64 {
+    (nodes as f64 / time.as_secs_f64().max(1e-3)) as u64
+}
+
+impl fmt::Display for SearchUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "info depth {} seldepth {} multipv {} score {}", self.depth, self.seldepth, self.multipv, self.score)?;
+        match self.bound {
+            Some(Bound::Lower) => f.write_str(" lowerbound")?,
+            Some(Bound::Upper) => f.write_str(" upperbound")?,
+            None => (),
+        }
+        if let Some(wdl) = self.wdl {
+            write!(f, " {}", wdl)?;
+        }
+        //there are no tablebases to hit
+        write!(f, " nodes {} nps {} hashfull {} tbhits 0 time {} pv",
+            self.nodes, nps(self.nodes, self.time), self.hashfull, self.time.as_millis())?;
+        for m in self.pv.iter() {
+            write!(f, " {}", m)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct SearchResult {
+    /// None if there is no legal move.
+    pub best_move: Option<Move>,
+    pub score: Score,
+    /// The deepest completed iteration.
+    pub depth: u8,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    /// The root moves of the last iteration that finished all its lines, best first.
+    pub lines: Vec<(Move, Score)>,
+}
+
+/// Searches a copy of `pos` with a fresh `DEFAULT_HASH_MB` table, calling `on_update`
+/// after every iteration. Keep a `Searcher` to reuse the table between moves.
+pub fn search(pos: &Position, limits: &SearchLimits, on_update: impl FnMut(&SearchUpdate)) -> SearchResult {
+    Searcher::with_hash_mb(pos.clone(), DEFAULT_HASH_MB).go(limits, on_update)
+}
+
 pub struct SearchInfo {
     start_time: SystemTime,
     move_time: Option<Duration>,
@@ -28,12 +135,32 @@ pub struct SearchInfo {
     // move_to_go: u32,
 
     nodes: u32,
+    total_nodes: u64,
+    node_limit: Option<u64>,
 
     fh: f32,
     fhf: f32,
 
     // quit: bool,
     stopped: bool,
+    quiet: bool,
+    debug: bool,
+    /// The iteration's depth and the deepest ply it reached.
+    iteration: u8,
+    seldepth: u16,
+    next_progress: Duration,
+    progress_due: bool,
+
+    multi_pv: usize,
+    skill: Option<Skill>,
+    show_wdl: bool,
+    /// Root moves already reported in this iteration.
+    excluded: Vec<Move>,
+    root_move: Move,
+    lines: Vec<(Move, i16)>,
+
+    pub best_move: Move,
+    pub best_score: i16,
 }
 
 impl SearchInfo {
@@ -42,18 +169,95 @@ impl SearchInfo {
         Self {
             start_time: now,
             depth, nodes: 0, fh: 0., fhf: 0.,
+            total_nodes: 0, node_limit: None,
             move_time,
             stopped: false,
+            quiet: false,
+            debug: false,
+            iteration: 0,
+            seldepth: 0,
+            next_progress: LONG_SEARCH,
+            progress_due: false,
+            multi_pv: 1,
+            skill: None,
+            show_wdl: false,
+            excluded: Vec::new(),
+            root_move: Move::new(),
+            lines: Vec::new(),
+            best_move: Move::new(),
+            best_score: 0,
         }
     }
 
+    /// Stops the search once roughly `nodes` nodes have been searched.
+    pub fn with_nodes(mut self, nodes: u64) -> Self {
+        self.node_limit = Some(nodes);
+        self
+    }
+
+    /// Searches the best `n` root moves, each with its own principal variation.
+    pub fn with_multi_pv(mut self, n: usize) -> Self {
+        self.multi_pv = n.max(1);
+        self
+    }
+
+    /// Plays at `skill`, lowering the depth and node limits to its own.
+    pub fn with_skill(mut self, skill: Skill) -> Self {
+        self.depth = self.depth.min(skill.depth(&mut rand::thread_rng()));
+        self.node_limit = Some(self.node_limit.map_or(skill.nodes(), |n| n.min(skill.nodes())));
+        self.skill = Some(skill);
+        self
+    }
+
+    /// Adds win/draw/loss estimates to the updates.
+    pub fn with_wdl(mut self) -> Self {
+        self.show_wdl = true;
+        self
+    }
+
+    /// Nodes searched over all completed and aborted iterations.
+    pub fn nodes(&self) -> u64 { self.total_nodes }
+
+    /// Suppresses `info` and `bestmove` output.
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    /// Adds `info string` statistics on the move ordering to the output.
+    pub fn with_debug(mut self) -> Self {
+        self.debug = true;
+        self
+    }
+
+    fn elapsed(&self) -> Duration { self.start_time.elapsed().unwrap_or_default() }
+
+    /// Whether a long search should tell what it is doing.
+    fn reporting(&self) -> bool {
+        !self.quiet && self.elapsed() >= LONG_SEARCH
+    }
+
     fn checkup(&mut self) -> bool {
+        //the limits wait for the first iteration, so that there is a move to play and
+        //a weakened search has all its lines to choose from
+        if self.iteration <= 1 { return false }
+        if let Some(limit) = self.node_limit {
+            //small budgets of weak skill levels need the exact count
+            if self.total_nodes + self.nodes as u64 >= limit {
+                self.stopped = true;
+            }
+        }
         if self.nodes & CHECKUP_INTERVAL_MASK == 0 {
+            let elapsed = self.elapsed();
             if let Some(move_time) = self.move_time {
-                if self.start_time.elapsed().unwrap() >= move_time {
+                if elapsed >= move_time {
                     self.stopped = true;
                 }
             }
+            if !self.quiet && elapsed >= self.next_progress {
+                self.next_progress = elapsed + PROGRESS_INTERVAL;
+                self.progress_due = true;
+            }
         }
 
         self.stopped
@@ -61,92 +265,301 @@ impl SearchInfo {
 }
 
 impl Position {
-    pub fn is_repetition(&self) -> bool {
-        for i in self.hist_ply as usize - self.fty as usize..self.hist_ply as usize{
-            if self.key == self.hist[i].key {
-                return true
-            }
-        }
-        false
+    pub fn should_null_move(&self) -> bool {
+        if self.variant == Variant::Antichess { return false }
+        let side = self.turn;
+        self.occupied[side] & !self.pieces[side][PieceType::Pawn] & !self.pieces[side][PieceType::King] != 0
     }
 
-    pub fn should_null_move(&self) -> bool {
-        use super::defs::*;
-        let side = self.turnx();
-        self.occupied[side] & !self.pieces[side][PAWNX] & !self.pieces[side][KINGX] != 0
+    /// Mate-like score for games a variant rule has already decided.
+    fn variant_score(&self, ply: u16) -> Option<i16> {
+        if self.variant == Variant::Standard { return None }
+        let winner = self.variant_winner()?;
+        Some(if winner == self.turn { INFINITY - ply as i16 } else { -INFINITY + ply as i16 })
     }
+}
+
+
+/// Search state besides the position and the transposition table: move ordering
+/// heuristics, the pawn cache, the principal variation and the distance from the root.
+pub struct ThreadData {
+    pub pv_line: PVLine,
+    pub pawn_table: PawnTable,
+    pub history: [[u16; 64]; 12],
+    pub killers: [[Move; MAX_DEPTH]; 2],
+    pub counter_moves: [[Move; 64]; 64],
+    pub ply: u16,
+}
 
-    pub fn search_reset(&mut self) {
-        for i in self.search_hist.iter_mut() {
+impl ThreadData {
+    pub fn new() -> Self {
+        Self {
+            pv_line: PVLine::new(),
+            pawn_table: PawnTable::new(),
+            history: [[0; 64]; 12],
+            killers: [[Move::new(); MAX_DEPTH]; 2],
+            counter_moves: [[Move::new(); 64]; 64],
+            ply: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        for i in self.history.iter_mut() {
             for j in i.iter_mut() {
                 *j = 0;
             }
         }
-        for i in self.search_killers.iter_mut() {
+        for i in self.killers.iter_mut() {
             for j in i.iter_mut() {
                 *j = Move::new();
             }
         }
-
-        // self.pv_table.clear();
         self.ply = 0;
     }
 
-    pub fn search(&mut self, info: &mut SearchInfo) {
-        self.search_reset();
-        const WINDOW: i16 = 50;
+    /// Scores the quiet moves `gen_moves` leaves at zero with the killers and history.
+    fn order_quiets(&self, p: &Position, moves: &mut MoveList) {
+        let ply = self.ply as usize;
+        for om in moves.iter_mut() {
+            let m = om.0;
+            if m.prom().is_some() || m.cap() && m.kind() != MoveKind::EnPassant { continue }
+            om.1 = if self.killers[0][ply] == m {
+                tunables::killer_base_cur()
+            } else if self.killers[1][ply] == m {
+                tunables::killer_base_prev()
+            } else {
+                self.history[p.moved_piece(m)][m.to()]
+            };
+        }
+    }
+}
+
+impl Default for ThreadData {
+    fn default() -> Self { Self::new() }
+}
+
+/// Searches `pos` with its own transposition table and thread data.
+pub struct Searcher {
+    pub(crate) pos: Position,
+    pub(crate) tt: HashTable,
+    pub(crate) td: ThreadData,
+}
+
+impl Searcher {
+    pub fn new(pos: Position) -> Self {
+        Self { pos, tt: HashTable::new(), td: ThreadData::new() }
+    }
+
+    pub fn with_hash_mb(pos: Position, mb: usize) -> Self {
+        Self { pos, tt: HashTable::with_size_mb(mb), td: ThreadData::new() }
+    }
+
+    pub fn position(&self) -> &Position { &self.pos }
+
+    /// Moves on to `pos`; what was learnt about earlier positions is kept.
+    pub fn set_position(&mut self, pos: Position) { self.pos = pos; }
+
+    /// Forgets everything learnt in earlier games.
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.td.pawn_table.clear();
+        self.td.counter_moves = [[Move::new(); 64]; 64];
+    }
+
+    /// Swaps the evaluation weights; cached pawn scores were computed with the old ones.
+    pub(crate) fn set_params(&mut self, params: Arc<EvalParams>) {
+        self.pos.set_params(params);
+        self.td.pawn_table.clear();
+    }
+
+    fn eval(&self) -> i16 {
+        self.pos.eval_cached(Some(&self.td.pawn_table))
+    }
+
+    /// Searches within `limits` without printing anything.
+    pub fn go(&mut self, limits: &SearchLimits, mut on_update: impl FnMut(&SearchUpdate)) -> SearchResult {
+        let mut info = SearchInfo::new(limits.depth.unwrap_or(MAX_DEPTH as u8), limits.move_time).quiet().with_wdl();
+        if let Some(nodes) = limits.nodes {
+            info = info.with_nodes(nodes);
+        }
+        info = info.with_multi_pv(limits.multi_pv);
+        if let Some(skill) = limits.skill {
+            info = info.with_skill(skill);
+        }
+        let (mut depth, mut pv) = (0, Vec::new());
+        self.iterate(&mut info, &mut |u| if u.multipv == 1 && u.bound.is_none() {
+            depth = u.depth;
+            pv = u.pv.clone();
+            on_update(u);
+        } else {
+            on_update(u);
+        });
+        SearchResult {
+            best_move: Some(info.best_move).filter(|m| !m.is_null()),
+            score: Score::from_raw(info.best_score),
+            depth, nodes: info.nodes(), pv,
+            lines: info.lines.iter().map(|&(m, s)| (m, Score::from_raw(s))).collect(),
+        }
+    }
+
+    /// Searches and prints `info` lines and the `bestmove` unless `info` is quiet.
+    pub(crate) fn search(&mut self, info: &mut SearchInfo) {
+        let (quiet, debug) = (info.quiet, info.debug);
+        self.iterate(info, &mut |u| if !quiet {
+            println!("{}", u);
+            if debug && u.multipv == 1 && u.bound.is_none() {
+                println!("info string depth {} first move cutoffs {:.1}%", u.depth, 100. * u.first_move_cutoffs);
+            }
+        });
+        if info.quiet { return }
+        //a search stopped before its first move still plays one
+        let best = Some(info.best_move).filter(|m| !m.is_null())
+            .or_else(|| self.pos.legal_moves().first().copied());
+        match best {
+            Some(m) => println!("bestmove {}", m),
+            None => {
+                println!("info string {}", self.pos.status());
+                println!("bestmove 0000");
+            }
+        }
+    }
+
+    fn iterate(&mut self, info: &mut SearchInfo, on_update: &mut dyn FnMut(&SearchUpdate)) {
+        self.td.reset();
+        info.lines.clear();
+        if info.skill.is_some() {
+            info.multi_pv = info.multi_pv.max(skill::MULTI_PV);
+        }
+        let multi_pv = info.multi_pv;
         let (mut alpha, mut beta) = (-INFINITY, INFINITY);
         for depth in 1..=info.depth {
     	    info.nodes = 0;
-            let mut score = self.alpha_beta(alpha, beta, depth, info, true);
-            if score <= alpha || score >= beta {
-                score = self.alpha_beta(-INFINITY, INFINITY, depth, info, true);
+            info.iteration = depth;
+            info.seldepth = 0;
+            info.excluded.clear();
+            let mut lines = Vec::with_capacity(multi_pv);
+            for multipv in 1..=multi_pv {
+                let first = multipv == 1;
+                let mut score = if first { self.alpha_beta(alpha, beta, depth, info, true) }
+                    else { self.alpha_beta(-INFINITY, INFINITY, depth, info, true) };
+                if first && (score <= alpha || score >= beta) && !info.stopped {
+                    let bound = if score <= alpha { Bound::Upper } else { Bound::Lower };
+                    self.tt.pv_line(&mut self.pos, depth, &mut self.td.pv_line);
+                    let pv = self.td.pv_line.iter().copied().collect();
+                    on_update(&self.update(info, multipv, score, Some(bound), pv));
+                    score = self.alpha_beta(-INFINITY, INFINITY, depth, info, true);
+                }
+                if info.stopped { break }
+
+                let pv: Vec<Move> = if first {
+                    //below -INFINITY the window would not negate
+                    alpha = score.saturating_sub(tunables::aspiration_window()).max(-INFINITY);
+                    beta = score.saturating_add(tunables::aspiration_window());
+                    self.tt.pv_line(&mut self.pos, depth, &mut self.td.pv_line);
+                    if let Some(m) = self.td.pv_line.iter().next() {
+                        info.best_move = *m;
+                        info.best_score = score;
+                    }
+                    self.td.pv_line.iter().copied().collect()
+                } else if info.root_move.is_null() {
+                    //fewer legal moves than lines
+                    break
+                } else {
+                    self.line_after(info.root_move, depth)
+                };
+                if let Some(&m) = pv.first() {
+                    info.excluded.push(m);
+                    lines.push((m, score));
+                }
+
+                on_update(&self.update(info, multipv, score, None, pv));
             }
+            info.total_nodes += info.nodes as u64;
             if info.stopped { break; }
-            alpha = score - WINDOW;
-            beta = score + WINDOW;
+            lines.sort_by_key(|l| std::cmp::Reverse(l.1));
+            info.lines = lines;
+        }
 
-            let ellapsed = info.start_time.elapsed().unwrap().as_millis();
+        if let Some(skill) = info.skill {
+            if let Some((m, score)) = skill.pick(&info.lines, &mut rand::thread_rng()) {
+                info.best_move = m;
+                info.best_score = score;
+            }
+        }
+    }
 
-            print!("info score {} depth {} nodes {} time {}",
-                     Score(score), depth, info.nodes, ellapsed);
+    fn update(&self, info: &SearchInfo, multipv: usize, score: i16, bound: Option<Bound>, pv: Vec<Move>) -> SearchUpdate {
+        let score = Score::from_raw(score);
+        SearchUpdate {
+            depth: info.iteration,
+            seldepth: info.seldepth,
+            multipv,
+            score,
+            bound,
+            wdl: info.show_wdl.then(|| DEFAULT_MODEL.wdl(score, self.pos.game_ply())),
+            nodes: info.total_nodes + info.nodes as u64,
+            time: info.elapsed(),
+            hashfull: self.tt.hashfull(),
+            pv,
+            first_move_cutoffs: if info.fh > 0. { info.fhf / info.fh } else { 0. },
+        }
+    }
 
-            print!(" pv");
-            self.extract_pv_line(depth);
-            for m in self.pv_line.iter() {
-                print!(" {}", m);
-            }
-            println!(" // Ordering: {:.2}", info.fhf / info.fh);
+    /// Prints how far a long search has got, with the move ordering statistics in debug mode.
+    fn report_progress(&self, info: &mut SearchInfo) {
+        info.progress_due = false;
+        let (nodes, time) = (info.total_nodes + info.nodes as u64, info.elapsed());
+        println!("info depth {} seldepth {} nodes {} nps {} hashfull {} tbhits 0 time {}",
+            info.iteration, info.seldepth, nodes, nps(nodes, time), self.tt.hashfull(), time.as_millis());
+        if info.debug && info.fh > 0. {
+            println!("info string first move cutoffs {:.1}%", 100. * info.fhf / info.fh);
         }
-        if let Some(m) = self.pv_line.iter().next() {
-            println!("bestmove {}", m);
-        } else {
-            println!("no legal moves");
+    }
+
+    /// The principal variation starting with the root move `m`.
+    fn line_after(&mut self, m: Move, depth: u8) -> Vec<Move> {
+        let mut pv = vec![m];
+        if depth > 1 && self.pos.make_move(m) {
+            self.tt.pv_line(&mut self.pos, depth - 1, &mut self.td.pv_line);
+            pv.extend(self.td.pv_line.iter().copied());
+            self.pos.unmake_move();
         }
+        pv
     }
 
     fn alpha_beta(&mut self, mut alpha: i16, beta: i16, mut depth: u8, info: &mut SearchInfo, mut do_null: bool) -> i16 {
-        const FULL_DEPTH_MOVES: u8 = 4;
-        const REDUCTION_LIMIT: u8 = 3;
         const DO_RAZORING: bool = true;
 
-        if self.ply > 0 && self.is_repetition() || self.fty >= 100 { return 0; }
+        if self.td.ply > 0 && self.pos.is_repetition(self.td.ply as usize) { return 0; }
+        if let Some(score) = self.pos.variant_score(self.td.ply) { return score }
+        //the game goes on at the root, and a mate on the hundredth half-move still stands
+        if self.td.ply > 0 && self.pos.fty >= 100 {
+            let mated = self.pos.variant != Variant::Antichess && self.pos.in_check(self.pos.turn)
+                && self.pos.legal_moves().is_empty();
+            return if mated { -INFINITY + self.td.ply as i16 } else { 0 };
+        }
         if depth == 0 {
             return self.quiescence(alpha, beta, info);
         }
 
         if info.checkup() { return 0; }
-        if self.ply as usize >= MAX_DEPTH { return self.eval() }
+        if info.progress_due { self.report_progress(info); }
+        if self.td.ply as usize >= MAX_DEPTH { return self.eval() }
         info.nodes += 1;
+        info.seldepth = info.seldepth.max(self.td.ply);
 
-        let in_check = self.in_check(self.turnx());
+        let in_check = self.pos.in_check(self.pos.turn);
         if in_check {
             depth += 1;
         }
 
+        //the root searches again for the next line without the moves already reported,
+        //and all lines search the root so that their scores compare
+        let root = self.td.ply == 0;
+        let exclusive = root && !info.excluded.is_empty();
         let mut pv_move = Move::new();
-        if let Some(e) = self.pv_table.probe(self.key) {
-            if e.depth >= depth {
+        if let Some(e) = self.tt.probe(self.pos.key) {
+            if e.depth >= depth && !(root && info.multi_pv > 1) {
                 use EntryFlags::*;
                 match e.flags {
                     Exact => return e.score,
@@ -161,11 +574,14 @@ impl Position {
             pv_move = e.m;
         }
 
-        const R: u8 = 3;
-        if do_null && !in_check && depth > R && self.should_null_move() {
-            self.make_null_move();
-            let score = -self.alpha_beta(-beta, -beta+1, depth - 1 - R, info, false);
-            self.unmake_null_move();
+        let r = tunables::null_move_reduction();
+        //the root always searches its moves so that there is a best move to play
+        if do_null && !root && !in_check && depth > r && self.pos.should_null_move() {
+            self.pos.make_null_move();
+            self.td.ply += 1;
+            let score = -self.alpha_beta(-beta, -beta+1, depth - 1 - r, info, false);
+            self.td.ply -= 1;
+            self.pos.unmake_null_move();
             if score >= beta {
                 return beta;
             }
@@ -176,20 +592,21 @@ impl Position {
         let old_alpha = alpha;
         let mut legal = 0;
         let mut moves = MoveList::new();
-        self.gen_moves::<false>(&mut moves);
+        self.pos.gen_moves::<false>(&mut moves);
+        self.td.order_quiets(&self.pos, &mut moves);
         if !pv_move.is_null() {
             if let Some(fnd) = moves.iter_mut().find(|om|om.0 == pv_move) {
                 fnd.1 = u16::MAX;
             }
         }
 
-        if DO_RAZORING && pv_move.is_null() && !in_check && depth <= 3 {
-            let mut score = self.eval() + 125;
+        if DO_RAZORING && !root && pv_move.is_null() && !in_check && depth <= 3 {
+            let mut score = self.eval() + tunables::razor_margin();
             if score < beta {
                 if depth == 1 {
                     return score.max(self.quiescence(alpha, beta, info));
                 }
-                score += 175;
+                score += tunables::razor_margin_deep();
                 if score < beta && depth <= 2 {
                     let new_score = self.quiescence(alpha, beta, info);
                     if new_score < beta {
@@ -201,19 +618,22 @@ impl Position {
 
         let mut moves_searched = 0;
 
-        let mut it = moves.iter_picky();
-        while let Some(m) = it.next() {
+        for m in moves.iter_picky() {
             let m = m.0;
-            if !self.make_move(m) { continue; }
-            self.ply += 1;
+            if exclusive && info.excluded.contains(&m) { continue }
+            if !self.pos.make_move(m) { continue; }
+            self.td.ply += 1;
             legal += 1;
+            if root && info.reporting() {
+                println!("info depth {} currmove {} currmovenumber {}", info.iteration, m, info.excluded.len() + legal);
+            }
 
             let score = if moves_searched == 0 {
                 -self.alpha_beta(-beta, -alpha, depth-1, info, true)
             } else {
                 let mut score;
-                if moves_searched >= FULL_DEPTH_MOVES && depth >= REDUCTION_LIMIT
-                    && !in_check && !m.cap() && m.prom() == 0 {
+                if moves_searched >= tunables::full_depth_moves() && depth >= tunables::reduction_limit()
+                    && !in_check && !m.cap() && m.prom().is_none() {
                     score = -self.alpha_beta(-alpha-1, -alpha, depth-2, info, true);
                 } else {
                     score = alpha + 1;
@@ -227,8 +647,8 @@ impl Position {
                 score
             };
             
-            self.unmake_move();
-            self.ply -= 1;
+            self.pos.unmake_move();
+            self.td.ply -= 1;
 
             if info.stopped { return 0 }
 
@@ -243,38 +663,47 @@ impl Position {
                         info.fh += 1.;
 
                         if !m.cap() {
-                            self.search_killers[1][self.ply as usize] = self.search_killers[0][self.ply as usize];
-                            self.search_killers[0][self.ply as usize] = m;
+                            let ply = self.td.ply as usize;
+                            self.td.killers[1][ply] = self.td.killers[0][ply];
+                            self.td.killers[0][ply] = m;
                         }
 
-                        self.pv_table.store(self.key, HashEntry {
-                            depth, flags: EntryFlags::Beta, m, score: beta
-                        });
+                        if root { info.root_move = m; }
+                        if !exclusive {
+                            self.tt.store(self.pos.key, HashEntry {
+                                depth, flags: EntryFlags::Beta, m, score: beta
+                            });
+                        }
                         return beta;
                     }
                     alpha = score;
 
                     if !m.cap() {
-                        self.search_hist[self.board[m.from() as usize].id() as usize][m.to() as usize] += depth as u16;
+                        self.td.history[self.pos.moved_piece(m)][m.to()] += depth as u16;
                     }
                 }
             }
             moves_searched += 1;
         }
+        if root { info.root_move = best_move; }
 
         if legal == 0 {
-            return match self.in_check(self.turnx()) {
-                true => -INFINITY + self.ply as i16,
+            return match self.pos.in_check(self.pos.turn) {
+                //running out of moves wins at Antichess
+                _ if self.pos.variant == Variant::Antichess => INFINITY - self.td.ply as i16,
+                true => -INFINITY + self.td.ply as i16,
                 false => 0,
             }
         }
 
+        //the root entry keeps the first line
+        if exclusive { return alpha }
         if alpha != old_alpha {
-            self.pv_table.store(self.key, HashEntry {
+            self.tt.store(self.pos.key, HashEntry {
                 depth, flags: EntryFlags::Exact, m: best_move, score: best_score
             })
         } else {
-            self.pv_table.store(self.key, HashEntry {
+            self.tt.store(self.pos.key, HashEntry {
                 depth, flags: EntryFlags::Alpha, m: best_move, score: alpha
             })
         }
@@ -285,9 +714,11 @@ impl Position {
     fn quiescence(&mut self, mut alpha: i16, beta: i16, info: &mut SearchInfo) -> i16 {
         if info.checkup() { return 0; }
         info.nodes += 1;
+        info.seldepth = info.seldepth.max(self.td.ply);
 
-        // self.is_repetition() || self.fty >= 100 { return 0; }
-        if self.ply as usize >= MAX_DEPTH  { return self.eval() }
+        // self.pos.is_repetition() || self.pos.fty >= 100 { return 0; }
+        if let Some(score) = self.pos.variant_score(self.td.ply) { return score }
+        if self.td.ply as usize >= MAX_DEPTH  { return self.eval() }
 
 
         let score = self.eval();
@@ -295,19 +726,19 @@ impl Position {
         if score > alpha { alpha = score; }
 
         let mut moves = MoveList::new();
-        self.gen_moves::<true>(&mut moves);
+        self.pos.gen_moves::<true>(&mut moves);
+        self.td.order_quiets(&self.pos, &mut moves);
         let mut legal = 0;
 
-        let mut it = moves.iter_picky();
-        while let Some(om) = it.next() {
+        for om in moves.iter_picky() {
             let m = om.0;
-            if !self.make_move(m) { continue; }
-            self.ply += 1;
+            if !self.pos.make_move(m) { continue; }
+            self.td.ply += 1;
             legal += 1;
             
             let score = -self.quiescence(-beta, -alpha, info);
-            self.unmake_move();
-            self.ply -= 1;
+            self.pos.unmake_move();
+            self.td.ply -= 1;
 
             if info.stopped { return 0 }
 
@@ -332,3 +763,54 @@ impl Position {
         alpha
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(depth: u8) -> SearchLimits {
+        SearchLimits { depth: Some(depth), ..SearchLimits::default() }
+    }
+
+    #[test]
+    fn root_scores() {
+        //the fifty-move rule neither ends the search at the root nor outranks the mate
+        let pos = Position::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 100 80").unwrap();
+        assert_eq!(search(&pos, &depth(3), |_| ()).score, Score::Mate(1));
+
+        //the aspiration window around a mated score stays within the score range
+        let pos = Position::from_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+        let res = search(&pos, &depth(4), |_| ());
+        assert_eq!((res.score, res.pv.len()), (Score::Mate(-1), 2));
+    }
+
+    #[test]
+    fn shallow_searches_find_a_move() {
+        //behind after 1. e4, razoring would drop straight into quiescence at the root
+        let mut pos = Position::from_fen(super::super::uci::START_FEN).unwrap();
+        let e4 = pos.parse_san("e4").unwrap();
+        assert!(pos.make_move(e4));
+        for d in 1..=3 {
+            assert!(search(&pos, &depth(d), |_| ()).best_move.is_some(), "depth {}", d);
+        }
+    }
+
+    #[test]
+    fn tiny_limits_still_move() {
+        let limits = SearchLimits { nodes: Some(1), ..SearchLimits::default() };
+        let pos = Position::from_fen(super::super::uci::START_FEN).unwrap();
+        assert!(search(&pos, &limits, |_| ()).best_move.is_some());
+    }
+
+    #[test]
+    fn weakened_searches_choose_among_lines() {
+        let mut pos = Position::new();
+        pos.load_fen(super::super::uci::START_FEN);
+        let mut s = Searcher::new(pos);
+        let mut info = SearchInfo::new(MAX_DEPTH as u8, None).quiet()
+            .with_nodes(1).with_skill(Skill::from_level(0).unwrap());
+        s.search(&mut info);
+        assert_eq!(info.lines.len(), skill::MULTI_PV);
+        assert!(info.lines.iter().any(|&(m, _)| m == info.best_move));
+    }
+}
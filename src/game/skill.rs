@@ -0,0 +1,137 @@
+//! Playing below full strength, for `Skill Level` and `UCI_LimitStrength`.
+//!
+//! A weakened search is capped in depth and nodes and searches several lines at the
+//! root. The move is then drawn among the lines close to the best one, with more
+//! randomness and a larger tolerated loss the lower the level. Now and then the search
+//! is cut to a couple of plies, which overlooks the opponent's tactics the way a
+//! hurried player does, instead of playing an arbitrary bad move.
+
+use super::movgen::Move;
+use rand::Rng;
+
+pub const MAX_LEVEL: u8 = 20;
+pub const MIN_ELO: u32 = ELO_LEVELS[0].0;
+pub const MAX_ELO: u32 = ELO_LEVELS[ELO_LEVELS.len() - 1].0;
+
+/// Elo of every other level, from self-play matches of 160 games at 2+0.05 between
+/// neighbouring even levels, counting down from full strength at 2800. Two levels
+/// apart score 50 to 190 Elo, and full strength is about 350 above level 18.
+const ELO_LEVELS: [(u32, u8); 11] = [
+    (1380, 0), (1510, 2), (1610, 4), (1810, 6), (1950, 8), (2110, 10),
+    (2270, 12), (2350, 14), (2400, 16), (2450, 18), (2800, MAX_LEVEL),
+];
+
+/// Root lines searched to choose from.
+pub const MULTI_PV: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Skill {
+    /// 0 to `MAX_LEVEL`, fractional for Elo settings between two levels.
+    level: f64,
+}
+
+impl Skill {
+    /// None for `MAX_LEVEL`, which plays at full strength.
+    pub fn from_level(level: u8) -> Option<Self> {
+        (level < MAX_LEVEL).then_some(Self { level: level as f64 })
+    }
+
+    /// Interpolates the level between the measured points of `ELO_LEVELS`; None for
+    /// `MAX_ELO`, which like `MAX_LEVEL` plays at full strength.
+    pub fn from_elo(elo: u32) -> Option<Self> {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let i = ELO_LEVELS.iter().rposition(|&(e, _)| e <= elo).unwrap();
+        let (e0, l0) = ELO_LEVELS[i];
+        let (e1, l1) = ELO_LEVELS.get(i + 1).copied().unwrap_or((e0 + 1, l0));
+        let level = l0 as f64 + (elo - e0) as f64 * (l1 - l0) as f64 / (e1 - e0) as f64;
+        (level < MAX_LEVEL as f64).then_some(Self { level })
+    }
+
+    pub fn level(&self) -> f64 { self.level }
+
+    /// Search depth for the next move: one ply per level, or fewer on the occasional
+    /// oversight.
+    pub fn depth(&self, rng: &mut impl Rng) -> u8 {
+        let chance = 0.15 * (1. - self.level / MAX_LEVEL as f64);
+        match self.oversight_depth() {
+            Some(d) if rng.gen_bool(chance) => d,
+            _ => self.normal_depth(),
+        }
+    }
+
+    fn normal_depth(&self) -> u8 { 1 + self.level as u8 }
+
+    /// At most two plies and always short of the normal depth, so level 0, which
+    /// searches a single ply, has no oversights.
+    fn oversight_depth(&self) -> Option<u8> {
+        Some((self.level as u8).min(2)).filter(|&d| d > 0)
+    }
+
+    /// Node budget, growing by half with every level: 400 at level 0, about 1.3M at 20.
+    pub fn nodes(&self) -> u64 {
+        (400. * 1.5f64.powf(self.level)) as u64
+    }
+
+    /// Picks among `lines`, root moves with their scores, best first. Every line gets
+    /// a random bonus and most of its loss back, so weak levels choose almost evenly
+    /// among the lines; lines losing more than the level tolerates are never picked.
+    pub fn pick(&self, lines: &[(Move, i16)], rng: &mut impl Rng) -> Option<(Move, i16)> {
+        let top = lines.first()?.1 as i32;
+        let weakness = 120 - (2. * self.level) as i32;
+        let max_loss = 50 + (15. * (MAX_LEVEL as f64 - self.level)) as i32;
+        let delta = (top - lines.last()?.1 as i32).min(100);
+        lines.iter()
+            .filter(|l| top - l.1 as i32 <= max_loss)
+            .max_by_key(|l| {
+                let loss = top - l.1 as i32;
+                l.1 as i32 + (weakness * loss + delta * rng.gen_range(0..weakness)) / 128
+            })
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn oversights_search_less() {
+        let mut rng = SmallRng::seed_from_u64(5);
+        for level in 0..MAX_LEVEL {
+            let skill = Skill::from_level(level).unwrap();
+            let normal = skill.normal_depth();
+            assert!(skill.oversight_depth().is_none_or(|d| d < normal), "level {}", level);
+            assert!((0..100).all(|_| skill.depth(&mut rng) <= normal));
+        }
+        assert_eq!(Skill::from_level(0).unwrap().oversight_depth(), None);
+    }
+
+    #[test]
+    fn weak_levels_vary_within_bounds() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        let lines: Vec<(Move, i16)> = [("a2", 30), ("b2", 20), ("c2", 0), ("d2", -900)].iter()
+            .map(|&(sq, s)| (Move::new_long(sq.parse().unwrap(), sq.replace('2', "4").parse().unwrap()), s))
+            .collect();
+        let picks = |skill: Skill, rng: &mut SmallRng| -> Vec<i16> {
+            (0..200).map(|_| skill.pick(&lines, rng).unwrap().1).collect()
+        };
+        let weak = picks(Skill::from_level(0).unwrap(), &mut rng);
+        assert!(weak.contains(&20) && weak.contains(&0) && !weak.contains(&-900));
+        let strong = picks(Skill::from_elo(MAX_ELO - 1).unwrap(), &mut rng);
+        let best = |picks: &[i16]| picks.iter().filter(|&&s| s == 30).count();
+        assert!(best(&strong) > best(&weak));
+        assert!(Skill::from_level(MAX_LEVEL).is_none() && Skill::from_elo(MAX_ELO).is_none());
+    }
+
+    #[test]
+    fn elo_follows_the_measured_levels() {
+        for &(elo, level) in ELO_LEVELS[..ELO_LEVELS.len() - 1].iter() {
+            assert_eq!(Skill::from_elo(elo).map(|s| s.level()), Some(level as f64));
+        }
+        assert_eq!(Skill::from_elo(2030).map(|s| s.level()), Some(9.));
+        assert_eq!(Skill::from_elo(0), Skill::from_elo(MIN_ELO));
+        let levels: Vec<f64> = (MIN_ELO..MAX_ELO).step_by(10).map(|e| Skill::from_elo(e).unwrap().level()).collect();
+        assert!(levels.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,120 @@
+//! Search constants that an SPSA harness can adjust at runtime.
+//!
+//! Every entry becomes a function returning its value. Without the `tune` feature
+//! that is the compiled in default; with it the value lives in an atomic that
+//! `setoption` can change between games.
+
+#[cfg(feature = "tune")]
+use std::sync::atomic::{AtomicI32, Ordering};
+
+pub struct Tunable {
+    pub name: &'static str,
+    pub default: i32,
+    pub min: i32,
+    pub max: i32,
+    /// Final SPSA perturbation size.
+    pub step: f64,
+    #[cfg(feature = "tune")]
+    value: &'static AtomicI32,
+}
+
+impl Tunable {
+    pub fn get(&self) -> i32 {
+        #[cfg(feature = "tune")]
+        { self.value.load(Ordering::Relaxed) }
+        #[cfg(not(feature = "tune"))]
+        { self.default }
+    }
+}
+
+macro_rules! tunables {
+    ($($name:ident: $ty:ty = $default:expr, $min:expr, $max:expr, $step:expr;)*) => {
+        #[cfg(feature = "tune")]
+        #[allow(non_upper_case_globals)]
+        mod store {
+            use std::sync::atomic::AtomicI32;
+            $(pub static $name: AtomicI32 = AtomicI32::new($default);)*
+        }
+
+        $(
+            #[inline(always)]
+            pub fn $name() -> $ty {
+                #[cfg(feature = "tune")]
+                { store::$name.load(std::sync::atomic::Ordering::Relaxed) as $ty }
+                #[cfg(not(feature = "tune"))]
+                { $default }
+            }
+        )*
+
+        pub static TUNABLES: &[Tunable] = &[$(Tunable {
+            name: stringify!($name),
+            default: $default,
+            min: $min,
+            max: $max,
+            step: $step,
+            #[cfg(feature = "tune")]
+            value: &store::$name,
+        },)*];
+    };
+}
+
+tunables! {
+    razor_margin: i16 = 125, 0, 400, 10.0;
+    razor_margin_deep: i16 = 175, 0, 400, 10.0;
+    null_move_reduction: u8 = 3, 1, 6, 0.5;
+    full_depth_moves: u8 = 4, 1, 12, 0.5;
+    reduction_limit: u8 = 3, 1, 8, 0.5;
+    aspiration_window: i16 = 50, 5, 200, 5.0;
+    capture_base: u16 = 10000, 9000, 12000, 50.0;
+    prom_base: u16 = 8000, 6000, 9000, 50.0;
+    killer_base_cur: u16 = 5000, 3000, 7000, 50.0;
+    killer_base_prev: u16 = 4500, 3000, 7000, 50.0;
+}
+
+#[cfg(any(test, feature = "tune"))]
+pub fn find(name: &str) -> Option<&'static Tunable> {
+    TUNABLES.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(feature = "tune")]
+pub fn set(name: &str, value: &str) -> Result<(), String> {
+    let t = find(name).ok_or(format!("unknown parameter {}", name))?;
+    let v = value.parse::<i32>().map_err(|_| format!("bad value for {}: {}", name, value))?;
+    if v < t.min || v > t.max {
+        return Err(format!("{} must be within [{}, {}]", name, t.min, t.max))
+    }
+    t.value.store(v, Ordering::Relaxed);
+    Ok(())
+}
+
+/// `option name ... type spin` lines for the `uci` handshake.
+#[cfg(feature = "tune")]
+pub fn uci_options() -> String {
+    TUNABLES.iter()
+        .map(|t| format!("option name {} type spin default {} min {} max {}\n",
+            t.name, t.default, t.min, t.max))
+        .collect()
+}
+
+/// One `name, int, value, min, max, c_end, r_end` line per parameter, the input
+/// format of the OpenBench SPSA tuner.
+pub fn spsa_inputs() -> String {
+    TUNABLES.iter()
+        .map(|t| format!("{}, int, {}, {}, {}, {}, 0.002\n", t.name, t.get(), t.min, t.max, t.step))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_within_bounds() {
+        for t in TUNABLES {
+            assert!(t.min <= t.default && t.default <= t.max, "{}", t.name);
+            assert_eq!(t.get(), t.default);
+        }
+        assert_eq!(spsa_inputs().lines().count(), TUNABLES.len());
+        assert_eq!(find("Aspiration_Window").map(|t| t.default), Some(aspiration_window() as i32));
+    }
+}
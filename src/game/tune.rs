@@ -0,0 +1,232 @@
+use super::{eval::NoTrace, params::EvalParams, pos::*, types::Color, weights::DEFAULT_PARAMS};
+use std::{collections::HashMap, fs, io, sync::Arc, time::Instant};
+
+pub struct Config {
+    pub data: String,
+    pub threads: usize,
+    pub passes: usize,
+    pub k: Option<f64>,
+    pub init: Option<String>,
+    pub out: String,
+    pub rust: String,
+}
+
+impl Config {
+    /// Parses `key value` pairs, e.g. `data data.txt threads 4 passes 10 out params.txt`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut cfg = Self {
+            data: "data.txt".to_owned(), threads: 1, passes: 100, k: None, init: None,
+            out: "params.txt".to_owned(), rust: "weights.rs".to_owned(),
+        };
+        let mut opts = HashMap::new();
+        let mut it = args.iter();
+        while let Some(key) = it.next() {
+            let val = it.next().ok_or(format!("missing value for {}", key))?;
+            opts.insert(key.as_str(), val.as_str());
+        }
+        for (key, val) in opts {
+            let bad = || format!("bad value for {}: {}", key, val);
+            match key {
+                "data" => cfg.data = val.to_owned(),
+                "threads" => cfg.threads = val.parse::<usize>().map_err(|_| bad())?.max(1),
+                "passes" => cfg.passes = val.parse().map_err(|_| bad())?,
+                "k" => cfg.k = Some(val.parse().map_err(|_| bad())?),
+                "init" => cfg.init = Some(val.to_owned()),
+                "out" => cfg.out = val.to_owned(),
+                "rust" => cfg.rust = val.to_owned(),
+                _ => return Err(format!("unknown option {}", key)),
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+/// A training position and the game result from white's side (0, 0.5 or 1).
+pub struct Sample {
+    pub fen: String,
+    pub result: f64,
+}
+
+/// Accepts `fen | score | result` lines written by `gen-data`, `fen | result`,
+/// and EPD style lines carrying the result as `[1.0]`, `"1-0"` and the like.
+pub fn parse_sample(line: &str) -> Option<Sample> {
+    let line = line.trim();
+    if line.is_empty() { return None }
+    if line.contains('|') {
+        let (fen, res) = line.rsplit_once('|')?;
+        let fen = fen.split('|').next()?.trim();
+        return Some(Sample { fen: fen.to_owned(), result: parse_result(res)? })
+    }
+    let idx = line.find(['[', '"'])?;
+    let result = parse_result(&line[idx..])?;
+    let fen = line[..idx].trim().trim_end_matches([';', ' ']);
+    let fen = fen.strip_suffix("c9").unwrap_or(fen).trim();
+    Some(Sample { fen: fen.to_owned(), result })
+}
+
+fn parse_result(s: &str) -> Option<f64> {
+    let s = s.trim().trim_matches(|c| matches!(c, '[' | ']' | '"' | ';'));
+    match s {
+        "1-0" | "1" | "1.0" => Some(1.0),
+        "0-1" | "0" | "0.0" => Some(0.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        _ => None,
+    }
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+/// Scores the samples with the static eval, one position per thread.
+pub struct Tuner {
+    samples: Vec<Sample>,
+    workers: Vec<Position>,
+}
+
+impl Tuner {
+    pub fn new(samples: Vec<Sample>, threads: usize) -> Self {
+        let workers = (0..threads).map(|_| Position::new()).collect();
+        Self { samples, workers }
+    }
+
+    /// White relative static evals of every sample under `params`.
+    pub fn evals(&mut self, params: &EvalParams) -> Vec<f64> {
+        let params = Arc::new(params.clone());
+        let chunk = self.samples.len().div_ceil(self.workers.len()).max(1);
+        let mut evals = vec![0.0; self.samples.len()];
+        std::thread::scope(|s| {
+            for ((pos, smps), out) in self.workers.iter_mut()
+                .zip(self.samples.chunks(chunk))
+                .zip(evals.chunks_mut(chunk))
+            {
+                let params = params.clone();
+                s.spawn(move || {
+                    pos.set_params(params);
+                    for (smp, e) in smps.iter().zip(out.iter_mut()) {
+                        pos.load_fen(&smp.fen);
+                        let score = pos.evaluate(&mut NoTrace);
+                        *e = if pos.turn == Color::Black { -score } else { score } as f64;
+                    }
+                });
+            }
+        });
+        evals
+    }
+
+    /// Mean squared error between the results and the predicted winning chances.
+    pub fn error(&self, evals: &[f64], k: f64) -> f64 {
+        let sum: f64 = self.samples.iter().zip(evals)
+            .map(|(smp, &e)| (smp.result - sigmoid(e, k)).powi(2))
+            .sum();
+        sum / self.samples.len().max(1) as f64
+    }
+
+    pub fn mse(&mut self, params: &EvalParams, k: f64) -> f64 {
+        let evals = self.evals(params);
+        self.error(&evals, k)
+    }
+
+    /// The sigmoid scaling that best fits the current eval, by repeated grid refinement.
+    pub fn find_k(&mut self, params: &EvalParams) -> f64 {
+        let evals = self.evals(params);
+        let (mut lo, mut hi, mut best) = (0.0, 4.0, 1.0);
+        for _ in 0..6 {
+            let step = (hi - lo) / 10.0;
+            let mut best_err = f64::MAX;
+            for i in 0..=10 {
+                let k = lo + step * i as f64;
+                let err = self.error(&evals, k);
+                if err < best_err { best_err = err; best = k; }
+            }
+            lo = (best - step).max(0.0);
+            hi = best + step;
+        }
+        best
+    }
+
+    /// Texel local search: nudges every weight by ±1 and keeps what lowers the error,
+    /// until a full pass brings no improvement. `checkpoint` runs after every pass.
+    pub fn local_search(&mut self, params: &mut EvalParams, k: f64, passes: usize,
+        mut checkpoint: impl FnMut(&EvalParams, usize, f64))
+    {
+        let len = params.len();
+        let mut best = self.mse(params, k);
+        println!("params {} positions {} k {:.3} error {:.6}", len, self.samples.len(), k, best);
+        for pass in 1..=passes {
+            let start = Instant::now();
+            let mut improved = false;
+            for idx in 0..len {
+                for delta in [1, -1] {
+                    nudge(params, idx, delta);
+                    let err = self.mse(params, k);
+                    if err < best {
+                        best = err;
+                        improved = true;
+                        break
+                    }
+                    nudge(params, idx, -delta);
+                }
+            }
+            println!("pass {} error {:.6} time {}s", pass, best, start.elapsed().as_secs());
+            checkpoint(params, pass, best);
+            if !improved { break }
+        }
+    }
+}
+
+/// Adds `delta` to the `idx`th weight in declaration order.
+fn nudge(params: &mut EvalParams, mut idx: usize, delta: i16) {
+    params.for_each_mut(|_, p| {
+        let vals = p.values_mut();
+        match vals.get_mut(idx) {
+            Some(v) => { *v += delta; idx = usize::MAX; }
+            None => idx = idx.saturating_sub(vals.len()),
+        }
+    });
+}
+
+pub fn run(cfg: &Config) -> io::Result<()> {
+    let text = fs::read_to_string(&cfg.data)?;
+    let samples: Vec<Sample> = text.lines().filter_map(parse_sample).collect();
+    if samples.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no positions with results"))
+    }
+    let mut params = DEFAULT_PARAMS;
+    if let Some(init) = &cfg.init { params.load(init)?; }
+
+    let mut tuner = Tuner::new(samples, cfg.threads);
+    let k = match cfg.k {
+        Some(k) => k,
+        None => tuner.find_k(&params),
+    };
+    let mut res = Ok(());
+    tuner.local_search(&mut params, k, cfg.passes, |p, _, _| {
+        if res.is_ok() {
+            res = p.save(&cfg.out).and_then(|_| fs::write(&cfg.rust, p.to_rust()));
+        }
+    });
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_file_is_generated() {
+        assert_eq!(DEFAULT_PARAMS.to_rust(), include_str!("weights.rs"));
+    }
+
+    #[test]
+    fn parses_samples() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        let s = parse_sample(&format!("{} | 35 | 0.5", fen)).unwrap();
+        assert_eq!((s.fen.as_str(), s.result), (fen, 0.5));
+        let s = parse_sample(&format!("{} [1.0]", fen)).unwrap();
+        assert_eq!((s.fen.as_str(), s.result), (fen, 1.0));
+        let s = parse_sample(&format!("{} c9 \"0-1\";", fen)).unwrap();
+        assert_eq!((s.fen.as_str(), s.result), (fen, 0.0));
+        assert!(parse_sample(fen).is_none());
+    }
+}
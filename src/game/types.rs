@@ -0,0 +1,360 @@
+use super::bitbrd::BitBoard;
+use std::{fmt, ops, str::FromStr};
+
+/// Lets `$ty` index fixed size arrays of `$n` elements directly.
+macro_rules! array_index {
+    ($ty:ty, $n:expr) => {
+        impl<T> ops::Index<$ty> for [T; $n] {
+            type Output = T;
+            #[inline(always)]
+            fn index(&self, i: $ty) -> &T { &self[i.index()] }
+        }
+
+        impl<T> ops::IndexMut<$ty> for [T; $n] {
+            #[inline(always)]
+            fn index_mut(&mut self, i: $ty) -> &mut T { &mut self[i.index()] }
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub const ALL: [Color; 2] = [Color::White, Color::Black];
+
+    #[inline(always)]
+    pub fn index(self) -> usize { self as usize }
+
+    pub fn from_index(c: usize) -> Self { Color::ALL[c] }
+
+    /// +1 for white and -1 for black.
+    pub fn sign(self) -> i16 { 1 - 2 * self as i16 }
+
+    /// The `w` or `b` used in FEN.
+    pub fn char(self) -> char { ['w', 'b'][self.index()] }
+}
+
+array_index!(Color, 2);
+
+impl ops::Not for Color {
+    type Output = Color;
+    #[inline(always)]
+    fn not(self) -> Color {
+        match self { Color::White => Color::Black, Color::Black => Color::White }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self { Color::White => "white", Color::Black => "black" })
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "w" | "white" => Ok(Color::White),
+            "b" | "black" => Ok(Color::Black),
+            _ => Err(format!("unknown color {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [
+        PieceType::Pawn, PieceType::Knight, PieceType::Bishop,
+        PieceType::Rook, PieceType::Queen, PieceType::King,
+    ];
+    /// What a Crazyhouse pocket can hold.
+    pub const POCKET: [PieceType; 5] = [
+        PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen,
+    ];
+
+    #[inline(always)]
+    pub fn index(self) -> usize { self as usize }
+
+    pub fn from_index(px: usize) -> Self { PieceType::ALL[px] }
+
+    /// Lowercase letter as used in FEN and UCI promotions.
+    pub fn char(self) -> char { ['p', 'n', 'b', 'r', 'q', 'k'][self.index()] }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        let c = c.to_ascii_lowercase();
+        PieceType::ALL.iter().copied().find(|pt| pt.char() == c)
+    }
+}
+
+array_index!(PieceType, 6);
+array_index!(PieceType, 5);
+
+impl fmt::Display for PieceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.char())
+    }
+}
+
+impl FromStr for PieceType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(PieceType::from_char), chars.next()) {
+            (Some(pt), None) => Ok(pt),
+            _ => Err(format!("unknown piece {}", s)),
+        }
+    }
+}
+
+/// A colored piece, laid out as type << 1 | color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Piece {
+    WhitePawn, BlackPawn,
+    WhiteKnight, BlackKnight,
+    WhiteBishop, BlackBishop,
+    WhiteRook, BlackRook,
+    WhiteQueen, BlackQueen,
+    WhiteKing, BlackKing,
+}
+
+impl Piece {
+    pub const ALL: [Piece; 12] = [
+        Piece::WhitePawn, Piece::BlackPawn, Piece::WhiteKnight, Piece::BlackKnight,
+        Piece::WhiteBishop, Piece::BlackBishop, Piece::WhiteRook, Piece::BlackRook,
+        Piece::WhiteQueen, Piece::BlackQueen, Piece::WhiteKing, Piece::BlackKing,
+    ];
+
+    #[inline(always)]
+    pub fn new(pt: PieceType, c: Color) -> Self { Piece::ALL[(pt as usize) << 1 | c as usize] }
+
+    #[inline(always)]
+    pub fn kind(self) -> PieceType { PieceType::ALL[self as usize >> 1] }
+    #[inline(always)]
+    pub fn color(self) -> Color { Color::ALL[self as usize & 1] }
+    #[inline(always)]
+    pub fn index(self) -> usize { self as usize }
+
+    /// FEN letter, uppercase for white.
+    pub fn char(self) -> char {
+        let c = self.kind().char();
+        if self.color() == Color::White { c.to_ascii_uppercase() } else { c }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        PieceType::from_char(c).map(|pt| Piece::new(pt, color))
+    }
+}
+
+array_index!(Piece, 12);
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.char())
+    }
+}
+
+impl FromStr for Piece {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(Piece::from_char), chars.next()) {
+            (Some(p), None) => Ok(p),
+            _ => Err(format!("unknown piece {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum File { A, B, C, D, E, F, G, H }
+
+impl File {
+    pub const ALL: [File; 8] = [File::A, File::B, File::C, File::D, File::E, File::F, File::G, File::H];
+
+    #[inline(always)]
+    pub fn index(self) -> usize { self as usize }
+    pub fn from_index(f: usize) -> Self { File::ALL[f] }
+    pub fn char(self) -> char { (b'a' + self as u8) as char }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        ('a'..='h').contains(&c).then(|| File::ALL[(c as u8 - b'a') as usize])
+    }
+
+    pub fn bb(self) -> BitBoard { 0x0101_0101_0101_0101 << self as u8 }
+}
+
+array_index!(File, 8);
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.char())
+    }
+}
+
+impl FromStr for File {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(File::from_char), chars.next()) {
+            (Some(f), None) => Ok(f),
+            _ => Err(format!("bad file {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Rank { R1, R2, R3, R4, R5, R6, R7, R8 }
+
+impl Rank {
+    pub const ALL: [Rank; 8] = [Rank::R1, Rank::R2, Rank::R3, Rank::R4, Rank::R5, Rank::R6, Rank::R7, Rank::R8];
+
+    #[inline(always)]
+    pub fn index(self) -> usize { self as usize }
+    pub fn from_index(r: usize) -> Self { Rank::ALL[r] }
+    pub fn char(self) -> char { (b'1' + self as u8) as char }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        ('1'..='8').contains(&c).then(|| Rank::ALL[(c as u8 - b'1') as usize])
+    }
+
+    /// The same rank seen from `c`'s side of the board.
+    pub fn relative(self, c: Color) -> Self {
+        if c == Color::White { self } else { Rank::ALL[7 - self as usize] }
+    }
+
+    pub fn bb(self) -> BitBoard { 0xFF << (8 * self as u8) }
+}
+
+array_index!(Rank, 8);
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.char())
+    }
+}
+
+impl FromStr for Rank {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(Rank::from_char), chars.next()) {
+            (Some(r), None) => Ok(r),
+            _ => Err(format!("bad rank {}", s)),
+        }
+    }
+}
+
+/// A square, a1 = 0 through h8 = 63.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(transparent)]
+pub struct Square(u8);
+
+impl Square {
+    #[inline(always)]
+    pub fn new(file: File, rank: Rank) -> Self { Square((rank as u8) << 3 | file as u8) }
+
+    pub fn from_index(sq: u8) -> Option<Self> { (sq < 64).then_some(Square(sq)) }
+
+    /// For indices known to be below 64, such as bit positions of a non-empty board.
+    #[inline(always)]
+    pub fn from_index_unchecked(sq: u8) -> Self {
+        debug_assert!(sq < 64);
+        Square(sq)
+    }
+
+    #[inline(always)]
+    pub fn index(self) -> usize { self.0 as usize }
+    #[inline(always)]
+    pub fn u8(self) -> u8 { self.0 }
+    #[inline(always)]
+    pub fn file(self) -> File { File::ALL[(self.0 & 7) as usize] }
+    #[inline(always)]
+    pub fn rank(self) -> Rank { Rank::ALL[(self.0 >> 3) as usize] }
+    #[inline(always)]
+    pub fn bb(self) -> BitBoard { 1 << self.0 }
+
+    /// Mirrored across the middle of the board, a1 <-> a8.
+    #[inline(always)]
+    pub fn flip(self) -> Self { Square(self.0 ^ 56) }
+
+    /// The square seen from `c`'s side of the board.
+    #[inline(always)]
+    pub fn relative(self, c: Color) -> Self { if c == Color::White { self } else { self.flip() } }
+
+    /// `n` ranks towards the eighth one, which must stay on the board.
+    #[inline(always)]
+    pub fn up(self, n: u8) -> Self { Square::from_index_unchecked(self.0 + 8 * n) }
+    #[inline(always)]
+    pub fn down(self, n: u8) -> Self { Square::from_index_unchecked(self.0 - 8 * n) }
+
+    /// The square `d` steps further in index order, which must stay on the board.
+    #[inline(always)]
+    pub fn offset(self, d: i8) -> Self { Square::from_index_unchecked(self.0.wrapping_add(d as u8)) }
+
+    /// `n` ranks forward from `c`'s point of view.
+    #[inline(always)]
+    pub fn forward(self, c: Color, n: u8) -> Self {
+        if c == Color::White { self.up(n) } else { self.down(n) }
+    }
+
+    /// Squares in the order of their indices.
+    pub fn all() -> impl Iterator<Item = Square> { (0..64).map(Square) }
+}
+
+array_index!(Square, 64);
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+impl FromStr for Square {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(File::from_char), chars.next().and_then(Rank::from_char), chars.next()) {
+            (Some(f), Some(r), None) => Ok(Square::new(f, r)),
+            _ => Err(format!("bad square {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let e4: Square = "e4".parse().unwrap();
+        assert_eq!((e4.index(), e4.file(), e4.rank()), (28, File::E, Rank::R4));
+        assert_eq!((e4.to_string(), e4.flip().to_string()), ("e4".to_owned(), "e5".to_owned()));
+        assert!("i1".parse::<Square>().is_err() && "e9".parse::<Square>().is_err());
+        assert_eq!(Square::from_index(64), None);
+        for p in Piece::ALL {
+            assert_eq!(Piece::new(p.kind(), p.color()), p);
+            assert_eq!(p.to_string().parse::<Piece>(), Ok(p));
+        }
+        assert_eq!(std::mem::size_of::<Option<Piece>>(), 1);
+        assert_eq!(!Color::White, Color::Black);
+        assert_eq!(Rank::R2.relative(Color::Black), Rank::R7);
+    }
+}
@@ -1,23 +1,66 @@
-use super::{pos::*, movgen::*, search::*};
-use super::defs::*;
+use super::{movgen::*, search::*};
+use super::types::Color;
 use super::pvtable::MAX_DEPTH;
+use super::{bench, perft, tunables, variant::Variant, weights::DEFAULT_PARAMS};
+use super::skill::{self, Skill};
 use std::io;
+#[cfg(feature = "nnue")]
+use super::nnue::Network;
+use std::sync::{Arc, atomic::Ordering};
 
-pub const START_FEN: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct UCI {
-    pos: Position,
+    searcher: Searcher,
+    multi_pv: usize,
+    skill_level: u8,
+    limit_strength: bool,
+    elo: u32,
+    show_wdl: bool,
+    debug: bool,
+    #[cfg(feature = "nnue")]
+    network: Option<Arc<Network>>,
+    #[cfg(feature = "nnue")]
+    use_nnue: bool,
 }
 
 impl UCI {
-    pub fn new(pos: Position) -> Self {
-        Self { pos }
+    pub fn new(searcher: Searcher) -> Self {
+        Self { 
+            searcher,
+            multi_pv: 1,
+            skill_level: skill::MAX_LEVEL,
+            limit_strength: false,
+            elo: 1500,
+            show_wdl: false,
+            debug: false,
+            #[cfg(feature = "nnue")]
+            network: None,
+            #[cfg(feature = "nnue")]
+            use_nnue: true,
+        }
     }
 
     pub fn uci_loop(&mut self) {
         let mut line = String::new();
         println!("id name baka");
         println!("id author some_retard");
+        println!("option name EvalParams type string default <empty>");
+        println!("option name UCI_Chess960 type check default false");
+        println!("{}", Variant::uci_option());
+        println!("option name MultiPV type spin default 1 min 1 max {}", MAX_DEPTH);
+        println!("option name Skill Level type spin default {0} min 0 max {0}", skill::MAX_LEVEL);
+        println!("option name UCI_LimitStrength type check default false");
+        println!("option name UCI_ShowWDL type check default false");
+        println!("option name UCI_Elo type spin default 1500 min {} max {}", skill::MIN_ELO, skill::MAX_ELO);
+        #[cfg(feature = "tune")]
+        print!("{}", tunables::uci_options());
+        #[cfg(feature = "nnue")]
+        {
+            println!("option name EvalFile type string default <empty>");
+            println!("option name Use NNUE type check default true");
+        }
         println!("uciok");
 
         loop {
@@ -29,13 +72,30 @@ impl UCI {
 
             match cmd {
                 "isready" => println!("readyok"),
+                "debug" => self.debug = line.split_whitespace().nth(1) == Some("on"),
                 "position" => self.parse_position(
                     line.strip_prefix("position").unwrap().trim()
                 ),
-                "ucinewgame" => self.parse_position("startpos"),
+                "ucinewgame" => {
+                    self.searcher.new_game();
+                    self.parse_position("startpos");
+                }
                 "go" => self.parse_go(
                     line.strip_prefix("go").unwrap().trim()
                 ),
+                "setoption" => self.parse_setoption(
+                    line.strip_prefix("setoption").unwrap().trim()
+                ),
+                "eval" => println!("{}", self.searcher.pos.eval_trace()),
+                "perft" => match line.split_whitespace().nth(1).map(|d| d.parse::<u8>()) {
+                    Some(Ok(depth)) => { perft::run(&mut self.searcher.pos, depth, 1, 16); }
+                    _ => println!("info string usage: perft <depth>"),
+                },
+                "bench" => {
+                    let depth = line.split_whitespace().nth(1).and_then(|d| d.parse().ok());
+                    bench::run(&mut self.searcher, depth.unwrap_or(bench::DEFAULT_DEPTH));
+                }
+                "spsa" => print!("{}", tunables::spsa_inputs()),
                 "quit" => break,
                 "stop" => (),
                 _ => panic!("undefined command"),
@@ -44,19 +104,106 @@ impl UCI {
         }
     }
 
+    fn parse_setoption(&mut self, line: &str) {
+        let line = line.strip_prefix("name").unwrap_or(line).trim();
+        let (name, value) = match line.find(" value") {
+            Some(idx) => (line[..idx].trim(), line[idx + 6..].trim()),
+            None => (line, ""),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "uci_chess960" => CHESS960.store(value == "true", Ordering::Relaxed),
+            "uci_variant" => match value.parse::<Variant>() {
+                Ok(variant) => {
+                    self.searcher.pos.variant = variant;
+                    self.parse_position("startpos");
+                    #[cfg(feature = "nnue")]
+                    self.apply_network();
+                }
+                Err(e) => println!("info string {}", e),
+            },
+            "multipv" => match value.parse::<usize>() {
+                Ok(n) => self.multi_pv = n.clamp(1, MAX_DEPTH),
+                Err(_) => println!("info string bad MultiPV {}", value),
+            },
+            "skill level" => match value.parse::<u8>() {
+                Ok(level) => self.skill_level = level.min(skill::MAX_LEVEL),
+                Err(_) => println!("info string bad Skill Level {}", value),
+            },
+            "uci_limitstrength" => self.limit_strength = value == "true",
+            "uci_showwdl" => self.show_wdl = value == "true",
+            "uci_elo" => match value.parse::<u32>() {
+                Ok(elo) => self.elo = elo.clamp(skill::MIN_ELO, skill::MAX_ELO),
+                Err(_) => println!("info string bad UCI_Elo {}", value),
+            },
+            "evalparams" => {
+                let mut params = DEFAULT_PARAMS;
+                if !value.is_empty() && value != "<empty>" {
+                    match params.load(value) {
+                        Ok(()) => println!("info string loaded eval params {}", value),
+                        Err(e) => {
+                            println!("info string failed to load {}: {}", value, e);
+                            return
+                        }
+                    }
+                }
+                self.searcher.set_params(Arc::new(params));
+            }
+            #[cfg(feature = "nnue")]
+            "evalfile" => {
+                if value.is_empty() || value == "<empty>" {
+                    self.network = None;
+                } else {
+                    match Network::load(value) {
+                        Ok(net) => {
+                            self.network = Some(Arc::new(net));
+                            println!("info string loaded network {}", value);
+                        }
+                        Err(e) => println!("info string failed to load {}: {}", value, e),
+                    }
+                }
+                self.apply_network();
+            }
+            #[cfg(feature = "nnue")]
+            "use nnue" => {
+                self.use_nnue = value == "true";
+                self.apply_network();
+            }
+            #[cfg(feature = "tune")]
+            _ if tunables::find(name).is_some() => {
+                if let Err(e) = tunables::set(name, value) {
+                    println!("info string {}", e);
+                }
+            }
+            _ => println!("info string unknown option {} (value {})", name, value),
+        }
+    }
+
+    /// UCI_LimitStrength takes precedence over Skill Level.
+    fn skill(&self) -> Option<Skill> {
+        if self.limit_strength { Skill::from_elo(self.elo) } else { Skill::from_level(self.skill_level) }
+    }
+
+    #[cfg(feature = "nnue")]
+    fn apply_network(&mut self) {
+        //the network only knows standard chess
+        let standard = self.searcher.pos.variant == Variant::Standard;
+        let net = if self.use_nnue && standard { self.network.clone() } else { None };
+        self.searcher.pos.set_network(net);
+    }
+
     fn parse_position(&mut self, line: &str) {
         let opt = line.split_whitespace().next().unwrap();
         if let Some(fen) = line.strip_prefix("fen") {
-            self.pos.load_fen(fen);
+            self.searcher.pos.load_fen(fen);
         } else if opt == "startpos" {
-            self.pos.load_fen(START_FEN);
+            self.searcher.pos.load_fen(self.searcher.pos.variant.start_fen());
         } else {
             panic!("invalid command {}", line);
         }
 
         if let Some(idx) = line.find("moves") {
             let rest = &line[idx..];
-            for m in rest.trim().split_whitespace().skip(1) {
+            for m in rest.split_whitespace().skip(1) {
                 self.make_move(m);
             }
         }
@@ -73,15 +220,13 @@ impl UCI {
             opts.insert(opt, val);
         }
 
-        let mut time = *opts.get(match self.pos.turn {
-            WHITE => "wtime",
-            BLACK => "btime",
-            _ => unreachable!(),
+        let mut time = *opts.get(match self.searcher.pos.turn {
+            Color::White => "wtime",
+            Color::Black => "btime",
         }).unwrap_or(&-1);
-        let inc = *opts.get(match self.pos.turn {
-            WHITE => "winc",
-            BLACK => "binc",
-            _ => unreachable!(),
+        let inc = *opts.get(match self.searcher.pos.turn {
+            Color::White => "winc",
+            Color::Black => "binc",
         }).unwrap_or(&0);
         let mut movestogo = *opts.get("movestogo").unwrap_or(&55);
         let depth = *opts.get("depth").unwrap_or(&(MAX_DEPTH as i32));
@@ -90,29 +235,28 @@ impl UCI {
             movestogo = 1;
         }
 
-        let mut info = SearchInfo::new(
+        let info = SearchInfo::new(
             depth as u8,
             if time != -1 {
                 Some(Duration::from_millis((time / movestogo + inc - 500) as u64))
             } else {
                 None
             }
-        );
-        self.pos.search(&mut info);
+        ).with_multi_pv(self.multi_pv);
+        let info = if self.show_wdl { info.with_wdl() } else { info };
+        let info = if self.debug { info.with_debug() } else { info };
+        let mut info = match self.skill() {
+            Some(skill) => info.with_skill(skill),
+            None => info,
+        };
+        self.searcher.search(&mut info);
         // println!("{}", self.pos);
     }
 
     fn make_move(&mut self, m: &str) {
-        use std::fmt::Write;
-        let mut mbuf = String::new();
-        let mut moves = MoveList::new();
-        self.pos.gen_moves::<false>(&mut moves);
-        if let Some(fnd) = moves.iter().find(|pm| {
-            mbuf.clear();
-            write!(&mut mbuf, "{}", pm.0).unwrap();
-            mbuf.trim() == m
-        }) {
-            self.pos.make_move(fnd.0);
+        match Move::from_uci(&self.searcher.pos, m) {
+            Ok(m) => { self.searcher.pos.make_move(m); }
+            Err(e) => println!("info string {}", e),
         }
     }
 }
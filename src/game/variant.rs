@@ -0,0 +1,363 @@
+//! Lichess variants. `Position` carries the variant it is played under and
+//! `gen_moves`, `make_move` and the search consult it; the rules that only some
+//! variants have live here.
+
+use super::{bitbrd::*, defs::*, eval::{distance, Backend, Tracer}, movgen::*, pawns::PawnTable, pos::*, zobrist::ZOBRIST};
+use super::types::{Color, Piece, PieceType, Square};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Standard,
+    ThreeCheck,
+    KingOfTheHill,
+    Atomic,
+    Antichess,
+    Horde,
+    Crazyhouse,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 7] = [
+        Variant::Standard, Variant::ThreeCheck, Variant::KingOfTheHill, Variant::Atomic,
+        Variant::Antichess, Variant::Horde, Variant::Crazyhouse,
+    ];
+
+    /// The `UCI_Variant` name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Atomic => "atomic",
+            Variant::Antichess => "antichess",
+            Variant::Horde => "horde",
+            Variant::Crazyhouse => "crazyhouse",
+        }
+    }
+
+    pub fn start_fen(self) -> &'static str {
+        match self {
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Horde => "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+            Variant::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            _ => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        }
+    }
+
+    /// `option name UCI_Variant ...` for the `uci` handshake.
+    pub fn uci_option() -> String {
+        let vars: String = Self::ALL.iter().map(|v| format!(" var {}", v.name())).collect();
+        format!("option name UCI_Variant type combo default chess{}", vars)
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "chess" | "standard" | "normal" => Variant::Standard,
+            "3check" | "threecheck" => Variant::ThreeCheck,
+            "kingofthehill" | "koth" => Variant::KingOfTheHill,
+            "atomic" => Variant::Atomic,
+            "antichess" | "giveaway" => Variant::Antichess,
+            "horde" => Variant::Horde,
+            "crazyhouse" | "zh" => Variant::Crazyhouse,
+            _ => return Err(format!("unknown variant {}", s)),
+        })
+    }
+}
+
+/// Game state beyond the board that some variants keep: checks given for
+/// Three-check, the pockets and the promoted pieces for Crazyhouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VariantState {
+    pub checks: [u8; 2],
+    pub pocket: [[u8; 5]; 2],
+    pub promoted: BitBoard,
+}
+
+impl VariantState {
+    pub fn new() -> Self {
+        Self { checks: [0; 2], pocket: [[0; 5]; 2], promoted: 0 }
+    }
+}
+
+impl Default for VariantState {
+    fn default() -> Self { Self::new() }
+}
+
+const CENTER: BitBoard = 0x18 << 24 | 0x18 << 32;
+const BACK_RANKS: BitBoard = 0xFF | 0xFF << 56;
+const CHECK_BONUS: [i16; 3] = [0, 150, 450];
+
+impl Position {
+    /// The winner when a variant rule has already ended the game.
+    pub fn variant_winner(&self) -> Option<Color> {
+        let (us, them) = (self.turn, !self.turn);
+        let king = |c: Color| self.pieces[c][PieceType::King];
+        match self.variant {
+            Variant::Standard | Variant::Crazyhouse => None,
+            Variant::ThreeCheck => Color::ALL.iter().copied().find(|&c| self.vstate.checks[c] >= 3),
+            Variant::KingOfTheHill => [them, us].iter().copied().find(|&c| king(c) & CENTER != 0),
+            Variant::Atomic => [us, them].iter().copied().find(|&c| king(c) == 0).map(|c| !c),
+            Variant::Antichess => [us, them].iter().copied()
+                .find(|&c| self.occupied[c] == 0),
+            Variant::Horde => (self.occupied[Color::White] == 0).then_some(Color::Black),
+        }
+    }
+
+    /// Whether the two kings stand next to each other, which rules out checks in Atomic.
+    pub fn kings_touch(&self) -> bool {
+        match self.king_square(Color::White) {
+            Some(wk) => ATTK_TBL.king_attacks(wk) & self.pieces[Color::Black][PieceType::King] != 0,
+            None => false,
+        }
+    }
+
+    /// Castling may pass squares next to the enemy king, where no capture can happen.
+    /// The path is checked with the king lifted, so attacks it used to block count; the
+    /// destination is left to the check test after the move.
+    pub(super) fn atomic_castle_attacked(&self, king: Square, rook: Square) -> bool {
+        let them = !self.turn;
+        let kt = castle_targets(king, rook).0;
+        let mut path = self.castling.safe[self.turn][(rook > king) as usize] & !kt.bb();
+        for k in self.pieces[them][PieceType::King].bits() { path &= !ATTK_TBL.king_attacks(k); }
+        self.attacked_through(them, self.all_ocupied() & !king.bb()) & path != 0
+    }
+
+    /// Crazyhouse drops of every pocket piece onto the empty squares.
+    pub(super) fn gen_drops(&self, moves: &mut MoveList) {
+        let free = !self.all_ocupied();
+        for pt in PieceType::POCKET {
+            if self.vstate.pocket[self.turn][pt] == 0 { continue }
+            let targets = if pt == PieceType::Pawn { free & !BACK_RANKS } else { free };
+            for sq in targets.bits() {
+                moves.push::<false>(Move::new_drop(pt, sq), pt, self);
+            }
+        }
+    }
+
+    pub(super) fn set_pocket(&mut self, c: Color, pt: PieceType, n: u8) {
+        self.key ^= ZOBRIST.pocket(c, pt, self.vstate.pocket[c][pt]) ^ ZOBRIST.pocket(c, pt, n);
+        self.vstate.pocket[c][pt] = n;
+    }
+
+    pub(super) fn add_check(&mut self, c: Color) {
+        let n = self.vstate.checks[c];
+        self.key ^= ZOBRIST.checks(c, n) ^ ZOBRIST.checks(c, n + 1);
+        self.vstate.checks[c] = n + 1;
+    }
+
+    /// Atomic: the capturing piece on `sq` and every piece but pawns next to it are
+    /// removed. They are kept on `blasts` for `unmake_move`; returns how many.
+    pub(super) fn explode(&mut self, sq: Square) -> u8 {
+        let area = ATTK_TBL.king_attacks(sq) & self.all_ocupied()
+            & !self.pieces[Color::White][PieceType::Pawn] & !self.pieces[Color::Black][PieceType::Pawn];
+        let mut n = 0;
+        for s in std::iter::once(sq).chain(area.bits()) {
+            self.blasts.push((s, self.board[s].expect("blast on an empty square")));
+            self.remove_piece(s);
+            self.cas.retain(self.castling.mask[s]);
+            n += 1;
+        }
+        n
+    }
+
+    pub(super) fn unexplode(&mut self, n: u8) {
+        for _ in 0..n {
+            let (sq, p) = self.blasts.pop().unwrap();
+            self.add_piece(p.color(), p.kind(), sq);
+        }
+    }
+
+    /// Crazyhouse bookkeeping for the move `m` that captured `cap` on `capsq`: the
+    /// capture goes to the pocket, as a pawn if it had been promoted.
+    pub(super) fn crazyhouse_make(&mut self, m: Move, cap: Option<Piece>, capsq: Square) {
+        let us = self.turn;
+        let promoted = &mut self.vstate.promoted;
+        let was_promoted = promoted.chk(capsq);
+        promoted.clear(capsq);
+        if m.kind() != MoveKind::Drop && promoted.chk(m.from()) {
+            promoted.clear(m.from());
+            promoted.set(m.to());
+        }
+        if m.prom().is_some() { promoted.set(m.to()); }
+        if let Some(cap) = cap {
+            let pt = if was_promoted { PieceType::Pawn } else { cap.kind() };
+            self.set_pocket(us, pt, self.vstate.pocket[us][pt] + 1);
+        }
+    }
+
+    /// The classical terms plus what the variant adds; variants without the usual
+    /// kings replace them entirely.
+    pub(super) fn variant_eval<T: Tracer>(&self, t: &mut T, pawns: Option<&PawnTable>) -> i16 {
+        let (us, them) = (self.turn, !self.turn);
+        let king = |c: Color| self.pieces[c][PieceType::King];
+        t.backend(Backend::Variant);
+        let (classical, bonus) = match self.variant {
+            Variant::Antichess => {
+                //the side with fewer pieces left is closer to winning
+                let n = |c: Color| self.occupied[c].count_ones() as i16;
+                (0, (n(them) - n(us)) * 100)
+            }
+            Variant::Horde => (0, self.material_eval()),
+            Variant::Atomic if king(us) == 0 || king(them) == 0 => (0, 0),
+            Variant::ThreeCheck => {
+                let bonus = |c: Color| CHECK_BONUS[self.vstate.checks[c].min(2) as usize];
+                (self.evaluate_cached(t, pawns), bonus(us) - bonus(them))
+            }
+            Variant::KingOfTheHill => {
+                let hill = |c: Color| match self.king_square(c) {
+                    Some(k) => CENTER.bits().map(|sq| distance(k, sq)).min().unwrap_or(7) as i16,
+                    None => 7,
+                };
+                (self.evaluate_cached(t, pawns), 40 * (hill(them) - hill(us)))
+            }
+            Variant::Crazyhouse => {
+                let pocket = |c: Color| PieceType::POCKET.iter()
+                    .map(|&pt| MATERIAL_TABLE[pt] * self.vstate.pocket[c][pt] as i16).sum::<i16>();
+                (self.evaluate_cached(t, pawns), pocket(us) - pocket(them))
+            }
+            _ => (self.evaluate_cached(t, pawns), 0),
+        };
+        t.variant(bonus);
+        classical + bonus
+    }
+
+    /// Material and piece squares only, for positions without the usual kings.
+    fn material_eval(&self) -> i16 {
+        let p = &*self.params;
+        let mut score = self.material[Color::White] - self.material[Color::Black];
+        for pt in PieceType::ALL[..PieceType::Queen.index()].iter().copied() {
+            for sq in self.pieces[Color::White][pt].bits() { score += p.pst[pt.index()][sq]; }
+            for sq in self.pieces[Color::Black][pt].bits() { score -= p.pst[pt.index()][sq.flip()]; }
+        }
+        if self.turn == Color::Black { -score } else { score }
+    }
+
+    /// `[QNp]` pockets and `~` promotion markers are written by `fen`; this reads
+    /// the pocket part of the board field, bracketed or as a ninth rank.
+    pub(super) fn parse_pocket(&mut self, pocket: &str) {
+        for p in pocket.chars().filter_map(Piece::from_char) {
+            let (c, pt) = (p.color(), p.kind());
+            if pt == PieceType::King { continue }
+            self.set_pocket(c, pt, self.vstate.pocket[c][pt] + 1);
+        }
+    }
+
+    pub(super) fn pocket_fen(&self) -> String {
+        let mut s = String::from("[");
+        for c in Color::ALL {
+            for pt in PieceType::POCKET.iter().rev().copied() {
+                let ch = Piece::new(pt, c).char();
+                s.extend(std::iter::repeat_n(ch, self.vstate.pocket[c][pt] as usize));
+            }
+        }
+        s.push(']');
+        s
+    }
+
+    /// Three-check counters, `3+3` as checks remaining or `+0+0` as checks given.
+    pub(super) fn parse_checks(&mut self, field: &str) {
+        let given = field.starts_with('+');
+        let mut nums = field.split('+').filter(|s| !s.is_empty()).map(|s| s.parse::<u8>().unwrap_or(0));
+        for c in Color::ALL {
+            let n = nums.next().unwrap_or(0).min(3);
+            let n = if given { n } else { 3 - n };
+            for _ in 0..n { self.add_check(c); }
+        }
+    }
+
+    pub(super) fn checks_fen(&self) -> String {
+        let [w, b] = self.vstate.checks;
+        format!("{}+{}", 3 - w.min(3), 3 - b.min(3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::perft::perft;
+
+    fn perfts(variant: Variant, fen: &str, expected: &[u64]) {
+        let mut pos = Position::new();
+        pos.variant = variant;
+        pos.load_fen(fen);
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&mut pos, depth as u8 + 1), nodes, "{} {} depth {}", variant, fen, depth + 1);
+        }
+        pos.verify();
+    }
+
+    #[test]
+    fn variant_fens() {
+        let mut pos = Position::new();
+        for v in Variant::ALL {
+            assert_eq!(v.name().parse::<Variant>(), Ok(v));
+            pos.variant = v;
+            pos.load_fen(v.start_fen());
+            assert_eq!(pos.fen(), v.start_fen());
+            pos.verify();
+        }
+        pos.variant = Variant::Crazyhouse;
+        pos.load_fen("r1bk3r/pppp1Bpp/2n5/4P3/8/8/PPP2PPP/RNB1K1NR/QNqp w KQ - 0 1");
+        assert_eq!(pos.fen(), "r1bk3r/pppp1Bpp/2n5/4P3/8/8/PPP2PPP/RNB1K1NR[QNqp] w KQ - 0 1");
+        pos.load_fen("4k3/1Q~6/8/8/8/8/8/4K3[] b - - 0 1");
+        assert_eq!(pos.vstate.promoted, 1 << 49);
+        pos.load_fen("4k3/8/8/8/8/8/8/Q~3K3[] b - - 0 1");
+        assert_eq!(pos.vstate.promoted, 1);
+        pos.variant = Variant::ThreeCheck;
+        pos.load_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1 +2+1");
+        assert_eq!(pos.vstate.checks, [2, 1]);
+        assert!(pos.fen().contains(" - - 1+2 0 1"));
+    }
+
+    #[test]
+    fn three_check() {
+        perfts(Variant::ThreeCheck,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 1+1 0 1",
+            &[48, 2039, 97848]);
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        perfts(Variant::KingOfTheHill, "4k3/8/8/8/8/4K3/8/8 w - - 0 1", &[8, 30]);
+        perfts(Variant::KingOfTheHill, Variant::KingOfTheHill.start_fen(), &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn atomic() {
+        perfts(Variant::Atomic, Variant::Atomic.start_fen(), &[20, 400, 8902, 197326]);
+        perfts(Variant::Atomic, "r3k1rR/5K2/8/8/8/8/8/8 b kq - 0 1", &[25, 282, 6753, 98729]);
+        perfts(Variant::Atomic, "Rr2k1rR/3K4/3p4/8/8/8/7P/8 w kq - 0 1", &[21, 465, 10631, 241478]);
+    }
+
+    #[test]
+    fn antichess() {
+        perfts(Variant::Antichess, Variant::Antichess.start_fen(), &[20, 400, 8067, 153299]);
+        perfts(Variant::Antichess, "8/1p6/8/8/8/8/P7/8 w - - 0 1", &[2, 4, 4, 3, 1, 0]);
+    }
+
+    #[test]
+    fn horde() {
+        perfts(Variant::Horde, Variant::Horde.start_fen(), &[8, 128, 1274, 23310]);
+    }
+
+    #[test]
+    fn crazyhouse() {
+        perfts(Variant::Crazyhouse, Variant::Crazyhouse.start_fen(), &[20, 400, 8902, 197281]);
+        perfts(Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", &[301, 75353]);
+        perfts(Variant::Crazyhouse, "4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - - 0 1", &[20, 360, 5445, 132758]);
+        perfts(Variant::Crazyhouse,
+            "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1", &[42, 1347, 58057]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Win/draw/loss estimates for `UCI_ShowWDL`.
+//!
+//! The chance to win at a score of `v` centipawns is `1 / (1 + exp((a - v) / b))`, the
+//! chance to lose the same with `-v`, and the draw chance what remains. `a`, the score
+//! at which a win is as likely as not, and `b`, the spread, are cubics in the game ply
+//! over 64, held constant past the plies the fit saw. `wdl-fit` fits them to `gen-data`
+//! self-play games.
+
+use super::search::Score;
+use super::tune::parse_sample;
+use std::{fmt, fs::File, io::{self, BufRead, BufReader}};
+
+const MAX_PLY: f64 = 240.;
+/// Plies per bucket when fitting.
+const BUCKET_PLIES: usize = 8;
+const MIN_BUCKET_SAMPLES: usize = 100;
+
+/// Expected result per mille from the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wdl {
+    pub win: u16,
+    pub draw: u16,
+    pub loss: u16,
+}
+
+impl fmt::Display for Wdl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "wdl {} {} {}", self.win, self.draw, self.loss)
+    }
+}
+
+/// Coefficients of `a` and `b`, highest power first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WdlModel {
+    pub a: [f64; 4],
+    pub b: [f64; 4],
+    /// Later plies use the values at this one.
+    pub max_ply: usize,
+}
+
+/// Fitted by `wdl-fit` to 720 depth 6 `gen-data` games, about 40k positions.
+pub const DEFAULT_MODEL: WdlModel = WdlModel {
+    a: [-30.35, 156.268, -108.688, 447.268],
+    b: [-49.551, 177.37, -188.869, 265.388],
+    max_ply: 140,
+};
+
+fn cubic(c: &[f64; 4], x: f64) -> f64 {
+    ((c[0] * x + c[1]) * x + c[2]) * x + c[3]
+}
+
+fn win_rate(v: f64, a: f64, b: f64) -> f64 {
+    1. / (1. + ((a - v) / b).exp())
+}
+
+impl WdlModel {
+    /// `a` and `b` at `ply`.
+    fn params(&self, ply: usize) -> (f64, f64) {
+        let m = ply.min(self.max_ply) as f64 / 64.;
+        (cubic(&self.a, m), cubic(&self.b, m).max(1.))
+    }
+
+    pub fn wdl(&self, score: Score, ply: usize) -> Wdl {
+        let cp = match score {
+            Score::Cp(cp) => cp as f64,
+            Score::Mate(n) if n > 0 => return Wdl { win: 1000, draw: 0, loss: 0 },
+            Score::Mate(_) => return Wdl { win: 0, draw: 0, loss: 1000 },
+        };
+        let (a, b) = self.params(ply);
+        let win = (1000. * win_rate(cp, a, b)).round() as u16;
+        let loss = (1000. * win_rate(-cp, a, b)).round() as u16;
+        Wdl { win, loss, draw: 1000u16.saturating_sub(win + loss) }
+    }
+}
+
+/// Negative log likelihood of the results, 0, 0.5 or 1, at the scores under `a` and `b`.
+fn nll(samples: &[(f64, f64)], a: f64, b: f64) -> f64 {
+    samples.iter().map(|&(v, r)| {
+        let (w, l) = (win_rate(v, a, b), win_rate(-v, a, b));
+        let p = if r > 0.75 { w } else if r < 0.25 { l } else { 1. - w - l };
+        -p.max(1e-12).ln()
+    }).sum()
+}
+
+/// Maximum likelihood `a` and `b` for one bucket, by pattern search.
+fn fit_bucket(samples: &[(f64, f64)]) -> (f64, f64) {
+    let (mut a, mut b) = (200., 80.);
+    let mut best = nll(samples, a, b);
+    let mut step = 64.;
+    while step > 0.05 {
+        let mut improved = false;
+        for (da, db) in [(step, 0.), (-step, 0.), (0., step), (0., -step)] {
+            let (na, nb) = (a + da, (b + db).max(1.));
+            let e = nll(samples, na, nb);
+            if e < best {
+                (a, b, best, improved) = (na, nb, e, true);
+            }
+        }
+        if !improved { step /= 2. }
+    }
+    (a, b)
+}
+
+/// Weighted least squares cubic through the points `(x, y, weight)`.
+fn fit_cubic(points: &[(f64, f64, f64)]) -> [f64; 4] {
+    //normal equations, the columns being x^3, x^2, x and 1
+    let mut m = [[0.; 5]; 4];
+    for &(x, y, w) in points {
+        let row = [x * x * x, x * x, x, 1.];
+        for i in 0..4 {
+            for j in 0..4 { m[i][j] += w * row[i] * row[j]; }
+            m[i][4] += w * row[i] * y;
+        }
+    }
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs())).unwrap();
+        m.swap(col, pivot);
+        if m[col][col].abs() < 1e-12 { continue }
+        let pivot_row = m[col];
+        for (i, row) in m.iter_mut().enumerate() {
+            if i == col { continue }
+            let f = row[col] / pivot_row[col];
+            for (x, p) in row.iter_mut().zip(pivot_row.iter()).skip(col) { *x -= f * p; }
+        }
+    }
+    let mut c = [0.; 4];
+    for i in 0..4 {
+        if m[i][i].abs() >= 1e-12 { c[i] = m[i][4] / m[i][i]; }
+    }
+    c
+}
+
+/// Fits the model to `(score, ply, result)` samples from white's side: `a` and `b` for
+/// every bucket of plies with enough samples, then cubics through them weighted by
+/// the bucket sizes.
+pub fn fit(samples: &[(i16, usize, f64)]) -> Result<WdlModel, String> {
+    let buckets = MAX_PLY as usize / BUCKET_PLIES + 1;
+    let mut by_ply = vec![Vec::new(); buckets];
+    for &(cp, ply, result) in samples {
+        by_ply[ply.min(MAX_PLY as usize) / BUCKET_PLIES].push((cp as f64, result));
+    }
+    let (mut a, mut b, mut max_ply) = (Vec::new(), Vec::new(), 0);
+    for (i, bucket) in by_ply.iter().enumerate().filter(|(_, s)| s.len() >= MIN_BUCKET_SAMPLES) {
+        let (ba, bb) = fit_bucket(bucket);
+        max_ply = (i * BUCKET_PLIES + BUCKET_PLIES / 2).min(MAX_PLY as usize);
+        let m = max_ply as f64 / 64.;
+        a.push((m, ba, bucket.len() as f64));
+        b.push((m, bb, bucket.len() as f64));
+    }
+    if a.len() < 4 {
+        return Err(format!("{} ply buckets with {} samples, 4 needed", a.len(), MIN_BUCKET_SAMPLES))
+    }
+    Ok(WdlModel { a: fit_cubic(&a), b: fit_cubic(&b), max_ply })
+}
+
+/// The game ply of a FEN, from its side to move and move number.
+fn fen_ply(fen: &str) -> usize {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let number = fields.get(5).and_then(|n| n.parse::<usize>().ok()).unwrap_or(1).max(1);
+    2 * (number - 1) + (fields.get(1) == Some(&"b")) as usize
+}
+
+/// `wdl-fit <file>...`: fits the model to `gen-data` text files and prints it as
+/// the source of `DEFAULT_MODEL`.
+pub fn run(paths: &[String]) -> io::Result<()> {
+    let mut samples = Vec::new();
+    for path in paths {
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            //the score is the middle field of `fen | score | result`
+            let score = line.split('|').nth(1).and_then(|s| s.trim().parse::<i16>().ok());
+            if let (Some(smp), Some(score)) = (parse_sample(&line), score) {
+                samples.push((score, fen_ply(&smp.fen), smp.result));
+            }
+        }
+    }
+    println!("{} samples", samples.len());
+    let model = fit(&samples).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    println!("pub const DEFAULT_MODEL: WdlModel = WdlModel {{\n    a: {:?},\n    b: {:?},\n    max_ply: {},\n}};",
+        model.a.map(|c| (c * 1000.).round() / 1000.), model.b.map(|c| (c * 1000.).round() / 1000.), model.max_ply);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn fits_generated_results() {
+        let truth = WdlModel { a: [0., -20., 60., 150.], b: [0., 0., 10., 60.], max_ply: 240 };
+        let mut rng = SmallRng::seed_from_u64(3);
+        let samples: Vec<(i16, usize, f64)> = (0..40_000).map(|_| {
+            let (cp, ply) = (rng.gen_range(-600..600), rng.gen_range(0..200));
+            let (a, b) = truth.params(ply);
+            let (w, l) = (win_rate(cp as f64, a, b), win_rate(-cp as f64, a, b));
+            let x: f64 = rng.gen();
+            (cp, ply, if x < w { 1. } else if x < w + l { 0. } else { 0.5 })
+        }).collect();
+        let model = fit(&samples).unwrap();
+        for ply in [10, 60, 120, 180] {
+            let (expected, got) = (truth.wdl(Score::Cp(150), ply), model.wdl(Score::Cp(150), ply));
+            assert!((expected.win as i32 - got.win as i32).abs() < 40, "{:?} {:?}", expected, got);
+        }
+        let wdl = DEFAULT_MODEL.wdl(Score::Cp(0), 40);
+        assert_eq!((wdl.win, wdl.win + wdl.draw + wdl.loss), (wdl.loss, 1000));
+        assert_eq!(DEFAULT_MODEL.wdl(Score::Mate(-2), 40).to_string(), "wdl 0 0 1000");
+    }
+}
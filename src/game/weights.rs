@@ -0,0 +1,162 @@
+// Evaluation weights. Generated by `bitbased tune`.
+
+use super::params::EvalParams;
+
+pub const DEFAULT_PARAMS: EvalParams = EvalParams {
+    material: [
+        [ 100, 325, 325, 550, 1000 ],
+        [ 100, 325, 325, 550, 1000 ],
+    ],
+    pst: [
+        [
+            0, 0, 0, 0, 0, 0, 0, 0,
+            10, 10, 0, -10, -10, 0, 10, 10,
+            5, 0, 0, 5, 5, 0, 0, 5,
+            0, 0, 10, 20, 20, 10, 0, 0,
+            5, 5, 5, 10, 10, 5, 5, 5,
+            10, 10, 10, 20, 20, 10, 10, 10,
+            20, 20, 20, 30, 30, 20, 20, 20,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        [
+            0, -10, 0, 0, 0, 0, -10, 0,
+            0, 0, 0, 5, 5, 0, 0, 0,
+            0, 0, 10, 10, 10, 10, 0, 0,
+            0, 0, 10, 20, 20, 10, 5, 0,
+            5, 10, 15, 20, 20, 15, 10, 5,
+            5, 10, 10, 20, 20, 10, 10, 5,
+            0, 0, 5, 10, 10, 5, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        [
+            0, 0, -10, 0, 0, -10, 0, 0,
+            0, 0, 0, 10, 10, 0, 0, 0,
+            0, 0, 10, 15, 15, 10, 0, 0,
+            0, 10, 15, 20, 20, 15, 10, 0,
+            0, 10, 15, 20, 20, 15, 10, 0,
+            0, 0, 10, 15, 15, 10, 0, 0,
+            0, 0, 0, 10, 10, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ],
+        [
+            0, 0, 5, 10, 10, 5, 0, 0,
+            0, 0, 5, 10, 10, 5, 0, 0,
+            0, 0, 5, 10, 10, 5, 0, 0,
+            0, 0, 5, 10, 10, 5, 0, 0,
+            0, 0, 5, 10, 10, 5, 0, 0,
+            0, 0, 5, 10, 10, 5, 0, 0,
+            25, 25, 25, 25, 25, 25, 25, 25,
+            0, 0, 5, 10, 10, 5, 0, 0,
+        ],
+    ],
+    king_location: [
+        [
+            0, 5, 5, -10, -10, 0, 10, 5,
+            -30, -30, -30, -30, -30, -30, -30, -30,
+            -50, -50, -50, -50, -50, -50, -50, -50,
+            -70, -70, -70, -70, -70, -70, -70, -70,
+            -70, -70, -70, -70, -70, -70, -70, -70,
+            -70, -70, -70, -70, -70, -70, -70, -70,
+            -70, -70, -70, -70, -70, -70, -70, -70,
+            -70, -70, -70, -70, -70, -70, -70, -70,
+        ],
+        [
+            -50, -10, 0, 0, 0, 0, -10, -50,
+            -10, 0, 10, 10, 10, 10, 0, -10,
+            0, 10, 20, 20, 20, 20, 10, 0,
+            0, 10, 20, 40, 40, 20, 10, 0,
+            0, 10, 20, 40, 40, 20, 10, 0,
+            0, 10, 20, 20, 20, 20, 10, 0,
+            -10, 0, 10, 10, 10, 10, 0, -10,
+            -50, -10, 0, 0, 0, 0, -10, -50,
+        ],
+    ],
+    king_shielded: 15,
+    king_attack_weight: [ 0, 2, 2, 3, 5, 0 ],
+    safe_check_weight: [ 0, 3, 2, 4, 6, 0 ],
+    king_zone_square: 1,
+    king_semi_open_file: 2,
+    king_open_file: 4,
+    safety_table: [
+        0, 0, 1, 2, 3, 5, 7, 9,
+        12, 15, 18, 22, 26, 30, 35, 39,
+        44, 50, 56, 62, 68, 75, 82, 85,
+        89, 97, 105, 113, 122, 131, 140, 150,
+        169, 180, 191, 202, 213, 225, 237, 248,
+        260, 272, 283, 295, 307, 319, 330, 342,
+        354, 366, 377, 389, 401, 412, 424, 436,
+        448, 459, 471, 483, 494, 500, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500,
+        500, 500, 500, 500,
+    ],
+    passed_pawn: [ 0, 5, 10, 20, 35, 60, 100, 200 ],
+    passer_king_weight: [ 0, 0, 0, 1, 2, 3, 5, 0 ],
+    passer_their_king: 5,
+    passer_our_king: 2,
+    unstoppable_passer: 500,
+    isolated_pawn: -5,
+    doubled_pawn: -5,
+    backward_pawn: -8,
+    connected_pawn: [ 0, 2, 4, 6, 10, 16, 25, 0 ],
+    phalanx_pawn: [ 0, 3, 5, 8, 12, 20, 30, 0 ],
+    candidate_passer: [ 0, 3, 5, 10, 18, 30, 0, 0 ],
+    knight_mobility: [
+        [
+            -25, -18, -6, -2, 2, 6, 10, 13,
+            15,
+        ],
+        [
+            -30, -22, -12, -6, 2, 5, 8, 10,
+            12,
+        ],
+    ],
+    bishop_mobility: [
+        [
+            -24, -10, 4, 10, 15, 20, 25, 28,
+            30, 33, 35, 38, 40, 42,
+        ],
+        [
+            -30, -14, -4, 3, 9, 14, 19, 22,
+            25, 27, 29, 31, 32, 34,
+        ],
+    ],
+    rook_mobility: [
+        [
+            -20, -10, -4, -2, 0, 3, 6, 9,
+            12, 14, 16, 18, 19, 20, 21,
+        ],
+        [
+            -40, -15, 2, 10, 18, 24, 28, 32,
+            36, 40, 43, 45, 47, 48, 50,
+        ],
+    ],
+    queen_mobility: [
+        [
+            -10, -6, -3, -2, 0, 2, 3, 4,
+            5, 6, 7, 8, 9, 10, 10, 11,
+            11, 12, 12, 13, 13, 14, 14, 15,
+            15, 16, 16, 17,
+        ],
+        [
+            -20, -12, -6, -2, 1, 4, 7, 10,
+            12, 14, 16, 18, 20, 21, 22, 23,
+            24, 25, 26, 27, 28, 29, 30, 31,
+            31, 32, 32, 33,
+        ],
+    ],
+    knight_outpost: [ 20, 10 ],
+    bishop_outpost: [ 10, 5 ],
+    bad_bishop_pawn: [ -3, -7 ],
+    rook_on_seventh: [ 15, 25 ],
+    trapped_bishop: [ -100, -100 ],
+    trapped_rook: [ -45, -5 ],
+    rook_open_file: 5,
+    rook_semi_open_file: 5,
+    queen_open_file: 3,
+    queen_semi_open_file: 2,
+    castle_rights: 10,
+    bishop_pair: 30,
+};
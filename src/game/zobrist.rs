@@ -1,5 +1,6 @@
 use crate::{rand, lazy_static::lazy_static};
-use super::{pos::*, defs::*};
+use super::{pos::*, bitbrd::*};
+use super::types::{Color, Piece, PieceType, Square};
 use rand::{Rng, SeedableRng};
 use rand::rngs::SmallRng;
 
@@ -12,6 +13,8 @@ pub struct Zobrist {
     cskeys: [u64; 16],
     skey: u64,
     no_ep: u64,
+    pocket: [[[u64; 17]; 5]; 2],
+    checks: [[u64; 4]; 2],
 }
 
 impl Zobrist {
@@ -21,7 +24,9 @@ impl Zobrist {
             pckeys: [[0; 64]; 13],
             cskeys: [0; 16],
             skey: rng.gen(),
-            no_ep: rng.gen()
+            no_ep: rng.gen(),
+            pocket: [[[0; 17]; 5]; 2],
+            checks: [[0; 4]; 2],
         };
         for i in inst.pckeys.iter_mut() {
             for j in i.iter_mut() {
@@ -31,11 +36,23 @@ impl Zobrist {
         for i in inst.cskeys.iter_mut() {
             *i = rng.gen();
         }
+        //an empty pocket and no checks given hash to zero, keeping standard keys unchanged
+        for i in inst.pocket.iter_mut().flatten() {
+            for j in i.iter_mut().skip(1) {
+                *j = rng.gen();
+            }
+        }
+        for i in inst.checks.iter_mut() {
+            for j in i.iter_mut().skip(1) {
+                *j = rng.gen();
+            }
+        }
         inst
     }
 
-    pub fn piece(&self, sq: u8, p: Piece) -> u64 {
-        self.pckeys[p.id() as usize][sq as usize]
+    #[inline(always)]
+    pub fn piece(&self, sq: Square, p: Piece) -> u64 {
+        self.pckeys[p.index()][sq]
     }
 
     pub fn side(&self) -> u64 { self.skey }
@@ -44,22 +61,49 @@ impl Zobrist {
         self.cskeys[cs.id() as usize]
     }
 
-    pub fn en_passant(&self, ep: u8) -> u64 {
+    pub fn pocket(&self, c: Color, pt: PieceType, n: u8) -> u64 {
+        self.pocket[c][pt][n as usize]
+    }
+
+    pub fn checks(&self, c: Color, n: u8) -> u64 {
+        self.checks[c][n.min(3) as usize]
+    }
+
+    pub fn en_passant(&self, ep: Option<Square>) -> u64 {
         match ep {
-            NS => self.no_ep,
-            ep => self.pckeys[12][ep as usize]
+            None => self.no_ep,
+            Some(ep) => self.pckeys[12][ep]
         }
     }
 
+    pub fn gen_pawn_key(&self, b: &Position) -> u64 {
+        let mut k = 0;
+        for c in Color::ALL {
+            for sq in b.pieces[c][PieceType::Pawn].bits() {
+                k ^= self.piece(sq, Piece::new(PieceType::Pawn, c));
+            }
+        }
+        k
+    }
+
     pub fn gen_key(&self, b: &Position) -> u64 {
         let mut k = 0;
-        for (sq, p) in b.board.iter().enumerate() {
-            if p.is_none() { continue; }
-            k ^= self.piece(sq as u8, *p);
+        for sq in Square::all() {
+            if let Some(p) = b.board[sq] { k ^= self.piece(sq, p); }
         }
-        if b.turn == WHITE { k ^= self.skey; }
+        if b.turn == Color::White { k ^= self.skey; }
         k ^= self.en_passant(b.ep); 
         k ^= self.cskeys[b.cas.id() as usize];
+        for c in Color::ALL {
+            for pt in PieceType::POCKET {
+                k ^= self.pocket(c, pt, b.vstate.pocket[c][pt]);
+            }
+            k ^= self.checks(c, b.vstate.checks[c]);
+        }
         k
     }
 }
+
+impl Default for Zobrist {
+    fn default() -> Self { Self::new() }
+}
@@ -0,0 +1,62 @@
+//! A chess engine library: FEN parsing, legal move generation, SAN and UCI move
+//! notation, perft and an alpha-beta search, for standard chess and several variants.
+//!
+//! ```
+//! use bitbased::{perft, Position};
+//!
+//! let mut pos = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+//! let e4 = pos.parse_san("e4").unwrap();
+//! assert!(pos.make_move(e4));
+//! assert_eq!(pos.legal_moves().len(), 20);
+//! pos.unmake_move();
+//! assert_eq!(perft(&mut pos, 2), 400);
+//! ```
+
+extern crate lazy_static;
+extern crate rand;
+// extern crate static_init;
+
+mod game;
+
+pub use game::movgen::Move;
+pub use game::perft::perft;
+pub use game::pos::{chess960_fen, Position};
+pub use game::rules::GameStatus;
+pub use game::search::{search, Bound, Score, SearchLimits, SearchResult, SearchUpdate, Searcher};
+pub use game::skill::Skill;
+pub use game::types::{Color, File, Piece, PieceType, Rank, Square};
+pub use game::variant::Variant;
+pub use game::wdl::Wdl;
+
+/// Runs the `bitbased` command line: a subcommand from `args`, or the console.
+pub fn run_cli(args: &[String]) {
+    game::cli::run(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_api() {
+        let mut pos = Position::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(pos.side_to_move(), Color::White);
+        assert_eq!(pos.piece_at("a1".parse().unwrap()), Some(Piece::WhiteRook));
+        assert_eq!(Piece::WhiteRook.kind(), PieceType::Rook);
+        assert!(Position::from_fen("8/8/8 w - - 0 1").is_err());
+
+        let limits = SearchLimits { depth: Some(3), ..SearchLimits::default() };
+        let mut depths = Vec::new();
+        let res = search(&pos, &limits, |u| if u.bound.is_none() { depths.push(u.depth) });
+        assert_eq!(depths, [1, 2, 3]);
+        assert_eq!(res.best_move.map(|m| pos.to_san(m)), Some("Ra8#".to_owned()));
+        assert_eq!(res.score, Score::Mate(1));
+        assert!(pos.make_move(res.best_move.unwrap()));
+        assert_eq!(pos.status(), GameStatus::Checkmate(Color::White));
+
+        let mut s = Searcher::new(Position::from_fen(Variant::Standard.start_fen()).unwrap());
+        s.set_position(pos.clone());
+        assert_eq!(s.position().fen(), pos.fen());
+        assert_eq!(s.go(&limits, |_| ()).best_move, None);
+    }
+}